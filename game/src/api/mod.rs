@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+};
+use futures::lock::Mutex;
+use serde_json::json;
+
+use crate::{
+    error::{AppResult, ServicesError},
+    gamerunner::{GameEventSender, GameRunner},
+    kafka::{bridge::Bridge, bus::EventBus},
+    workflow::{manager::WorkflowError, service::WorkflowService},
+};
+
+pub mod routes;
+
+pub struct GameHandle {
+    pub runner: Arc<Mutex<GameRunner>>,
+    pub events: GameEventSender,
+}
+
+impl GameHandle {
+    pub async fn workflow(&self) -> Arc<WorkflowService> {
+        let game = { self.runner.lock().await.game.clone() };
+        game.lock().await.workflow.clone()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ApiState {
+    games: Arc<Mutex<HashMap<String, Arc<GameHandle>>>>,
+    bridge: Option<Bridge>,
+}
+
+impl ApiState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publish the workflow events of every game created through the API.
+    pub async fn with_event_bus(mut self, event_bus: Arc<dyn EventBus>, server_id: &str) -> Self {
+        self.bridge = Some(Bridge::new(event_bus, server_id).await);
+        self
+    }
+
+    pub fn bridge(&self) -> Option<Bridge> {
+        self.bridge.clone()
+    }
+
+    pub async fn insert_game(&self, game_id: String, runner: Arc<Mutex<GameRunner>>) {
+        let events = { runner.lock().await.event_sender.clone() };
+        self.games
+            .lock()
+            .await
+            .insert(game_id, Arc::new(GameHandle { runner, events }));
+    }
+
+    pub async fn get_game(&self, game_id: &str) -> AppResult<Arc<GameHandle>> {
+        self.games
+            .lock()
+            .await
+            .get(game_id)
+            .cloned()
+            .ok_or(ServicesError::NotFound(format!("game {game_id}")))
+    }
+}
+
+pub fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/games", post(routes::create_game))
+        .route("/games/restore", post(routes::restore_game))
+        .route("/games/{game_id}/snapshot", get(routes::get_snapshot))
+        .route("/games/{game_id}/votes", post(routes::cast_votes))
+        .route(
+            "/games/{game_id}/players/{player_id}/workflows",
+            get(routes::list_player_workflows),
+        )
+        .route(
+            "/games/{game_id}/players/{player_id}/workflows/{instance_id}",
+            get(routes::get_workflow),
+        )
+        .route(
+            "/games/{game_id}/players/{player_id}/workflows/{instance_id}/tree",
+            get(routes::get_workflow_tree),
+        )
+        .route(
+            "/games/{game_id}/players/{player_id}/workflows/{instance_id}/actions",
+            post(routes::submit_action),
+        )
+        .route(
+            "/games/{game_id}/players/{player_id}/workflows/{instance_id}/delegates",
+            post(routes::delegate_workflow),
+        )
+        .route(
+            "/games/{game_id}/players/{player_id}/workflows/{instance_id}/delegates/{delegate_id}",
+            delete(routes::revoke_delegation),
+        )
+        .route(
+            "/games/{game_id}/players/{player_id}/server-actions",
+            post(routes::respond_server_action),
+        )
+        .with_state(state)
+}
+
+pub async fn serve(addr: &str, state: ApiState) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    println!("HTTP API listening on {addr}");
+    axum::serve(listener, router(state)).await
+}
+
+impl IntoResponse for ServicesError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            ServicesError::NotFound(_) => StatusCode::NOT_FOUND,
+            ServicesError::WorkflowError(error) => match error {
+                WorkflowError::WorkflowNotFound
+                | WorkflowError::WorkflowInstanceNotFound
+                | WorkflowError::NodeNotFound
+                | WorkflowError::ActionNotFound
+                | WorkflowError::ServerActionNotFound => StatusCode::NOT_FOUND,
+                WorkflowError::WorkflowAlreadyCompleted
+                | WorkflowError::InvalidState
+                | WorkflowError::WaitCycle(_) => StatusCode::CONFLICT,
+                WorkflowError::Forbidden(_) => StatusCode::FORBIDDEN,
+                WorkflowError::InvalidDefinition(_) | WorkflowError::InvalidInput(_) => {
+                    StatusCode::UNPROCESSABLE_ENTITY
+                }
+                WorkflowError::ServerActionFailed(_)
+                | WorkflowError::Storage(_)
+                | WorkflowError::Expression(..) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            ServicesError::Config(_)
+            | ServicesError::InternalError(_)
+            | ServicesError::SQLError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        let mut body = json!({ "error": self.to_string() });
+        if let ServicesError::WorkflowError(WorkflowError::InvalidInput(fields)) = &self {
+            body["fields"] = json!(fields);
+        }
+
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use serde_json::{Value, json};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::{ApiState, router};
+
+    async fn serve() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(ApiState::new())).await });
+        addr
+    }
+
+    async fn request(addr: SocketAddr, method: &str, path: &str, body: Value) -> (u16, Value) {
+        let body = if body.is_null() {
+            String::new()
+        } else {
+            body.to_string()
+        };
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap_or(Value::Null))
+    }
+
+    /// Wait for the runner to give `player_id` a workflow and return it.
+    async fn first_workflow(addr: SocketAddr, game_id: &str, player_id: &str) -> Value {
+        let path = format!("/games/{game_id}/players/{player_id}/workflows");
+        for _ in 0..50 {
+            let (status, workflows) = request(addr, "GET", &path, Value::Null).await;
+            assert_eq!(status, 200);
+            if let Some(workflow) = workflows.as_array().and_then(|list| list.first()) {
+                return workflow.clone();
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("{player_id} never got a workflow");
+    }
+
+    #[tokio::test]
+    async fn players_play_their_workflows_over_http() {
+        let addr = serve().await;
+        let (status, created) = request(
+            addr,
+            "POST",
+            "/games",
+            json!({ "players": [
+                { "id": "sam", "name": "Sam", "role": "seer", "middle_position": null },
+                { "id": "vince", "name": "Vince", "role": "werewolf", "middle_position": null },
+                { "id": "val", "name": "Val", "role": "villager", "middle_position": null },
+                { "id": "middle1", "name": "middle 1", "role": "villager", "middle_position": 0 },
+            ]}),
+        )
+        .await;
+        assert_eq!(status, 200);
+        let game_id = created["game_id"].as_str().unwrap();
+
+        let workflow = first_workflow(addr, game_id, "sam").await;
+        let instance_id = workflow["instance_id"].as_str().unwrap();
+        assert_eq!(workflow["current_node_id"], "select_card_node");
+
+        let path = format!("/games/{game_id}/players/sam/workflows/{instance_id}");
+        let (status, fetched) = request(addr, "GET", &path, Value::Null).await;
+        assert_eq!(status, 200);
+        assert_eq!(fetched["instance_id"], instance_id);
+        let other = format!("/games/{game_id}/players/vince/workflows/{instance_id}");
+        let (status, _) = request(addr, "GET", &other, Value::Null).await;
+        assert_eq!(status, 403);
+
+        let (status, updated) = request(
+            addr,
+            "POST",
+            &format!("{path}/actions"),
+            json!({
+                "action_id": "next",
+                "inputs": { "selected_card": { "type": "Player", "Player": { "id": "vince" } } },
+            }),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(updated["current_node_id"], "prompt_player_reveal");
+
+        let (status, voted) = request(
+            addr,
+            "POST",
+            &format!("/games/{game_id}/votes"),
+            json!({ "votes": { "sam": "vince", "vince": "sam", "val": "vince" } }),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(voted["eliminated"], json!(["vince"]));
+    }
+
+    #[tokio::test]
+    async fn mistakes_get_their_status_code() {
+        let addr = serve().await;
+        let (status, _) = request(
+            addr,
+            "POST",
+            "/games",
+            json!({ "players": [
+                { "id": "sam", "name": "Sam", "role": "tanner", "middle_position": null },
+            ]}),
+        )
+        .await;
+        assert_eq!(status, 404);
+
+        let (status, _) = request(
+            addr,
+            "GET",
+            "/games/nowhere/players/sam/workflows",
+            Value::Null,
+        )
+        .await;
+        assert_eq!(status, 404);
+
+        let (status, created) = request(
+            addr,
+            "POST",
+            "/games",
+            json!({ "players": [
+                { "id": "sam", "name": "Sam", "role": "villager", "middle_position": null },
+            ]}),
+        )
+        .await;
+        assert_eq!(status, 200);
+        let game_id = created["game_id"].as_str().unwrap();
+        let (status, body) = request(
+            addr,
+            "POST",
+            &format!("/games/{game_id}/players/oracle/server-actions"),
+            json!({
+                "token": "unknown",
+                "result": { "CompleteWorkflow": { "responses": {}, "message": "Done" } },
+            }),
+        )
+        .await;
+        assert_eq!(status, 404);
+        assert!(body["error"].as_str().unwrap().contains("unknown"));
+    }
+}
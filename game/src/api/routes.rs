@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use specta::Type;
+use tokio::sync::broadcast;
+
+use crate::{
+    error::{AppResult, ServicesError},
+    gamerunner::GameRunner,
+    gamestate::{GameState, Player},
+    roles::registry::RoleRegistry,
+    snapshot::GameSnapshot,
+    workflow::service::{
+        ProcessWorkflowActionArgs, WorkflowResource, WorkflowRespondServerActionArgs, WorkflowTree,
+    },
+};
+
+use super::ApiState;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct CreatePlayerArgs {
+    pub id: String,
+    pub name: String,
+    pub role: String,
+    pub middle_position: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct CreateGameArgs {
+    pub players: Vec<CreatePlayerArgs>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct SubmitActionArgs {
+    pub action_id: String,
+    #[serde(default)]
+    pub inputs: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct DelegateWorkflowArgs {
+    pub user_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct CastVotesArgs {
+    /// Who each player voted for, by voter id.
+    pub votes: HashMap<String, String>,
+}
+
+pub async fn create_game(
+    State(state): State<ApiState>,
+    Json(args): Json<CreateGameArgs>,
+) -> AppResult<Json<Value>> {
+    let mut players = Vec::new();
+    for player in args.players {
+        let card = RoleRegistry::global().create(&player.role)?;
+        players.push(Player::new(
+            &player.id,
+            &player.name,
+            Arc::new(card),
+            player.middle_position,
+        ));
+    }
+
+    let player_ids = players.iter().map(|p| p.id.clone()).collect();
+    let game = GameState::new(players).await;
+    let workflow = game.workflow.clone();
+    let (tx, _rx) = broadcast::channel(16);
+    let runner = GameRunner::new(game, tx.clone()).await;
+
+    let game_id = ulid::Ulid::new().to_string();
+    if let Some(bridge) = state.bridge() {
+        bridge.attach(workflow, &game_id).await;
+        bridge.attach_game(&game_id, player_ids, &tx).await;
+    }
+    state.insert_game(game_id.clone(), runner.clone()).await;
+    tokio::spawn(GameRunner::run(runner));
+
+    Ok(Json(json!({ "game_id": game_id })))
+}
+
+pub async fn get_snapshot(
+    State(state): State<ApiState>,
+    Path(game_id): Path<String>,
+) -> AppResult<Json<GameSnapshot>> {
+    let game = state.get_game(&game_id).await?;
+    let snapshot = game.runner.lock().await.snapshot().await;
+
+    Ok(Json(snapshot))
+}
+
+pub async fn restore_game(
+    State(state): State<ApiState>,
+    Json(snapshot): Json<GameSnapshot>,
+) -> AppResult<Json<Value>> {
+    let player_ids = snapshot.players.iter().map(|p| p.id.clone()).collect();
+    let (tx, _rx) = broadcast::channel(16);
+    let runner = GameRunner::restore(snapshot, tx.clone()).await?;
+    let workflow = { runner.lock().await.game.lock().await.workflow.clone() };
+
+    let game_id = ulid::Ulid::new().to_string();
+    if let Some(bridge) = state.bridge() {
+        bridge.attach(workflow, &game_id).await;
+        bridge.attach_game(&game_id, player_ids, &tx).await;
+    }
+    GameRunner::resume(&runner).await?;
+    state.insert_game(game_id.clone(), runner.clone()).await;
+    tokio::spawn(GameRunner::run(runner));
+
+    Ok(Json(json!({ "game_id": game_id })))
+}
+
+pub async fn cast_votes(
+    State(state): State<ApiState>,
+    Path(game_id): Path<String>,
+    Json(args): Json<CastVotesArgs>,
+) -> AppResult<Json<Value>> {
+    let game = state.get_game(&game_id).await?;
+    let eliminated = game.runner.lock().await.record_votes(args.votes).await?;
+
+    Ok(Json(json!({ "eliminated": eliminated })))
+}
+
+pub async fn list_player_workflows(
+    State(state): State<ApiState>,
+    Path((game_id, player_id)): Path<(String, String)>,
+) -> AppResult<Json<Vec<WorkflowResource>>> {
+    let workflow = state.get_game(&game_id).await?.workflow().await;
+    let resources = workflow
+        .manager
+        .list_user_workflow_resources(&player_id)
+        .await
+        .iter()
+        .map(|resource| resource.for_recipient(&player_id))
+        .collect();
+
+    Ok(Json(resources))
+}
+
+pub async fn get_workflow(
+    State(state): State<ApiState>,
+    Path((game_id, player_id, instance_id)): Path<(String, String, String)>,
+) -> AppResult<Json<WorkflowResource>> {
+    let workflow = state.get_game(&game_id).await?.workflow().await;
+    workflow
+        .manager
+        .authorize_user(&instance_id, &player_id)
+        .await?;
+    let resource = workflow
+        .manager
+        .get_workflow_resource(&instance_id)
+        .await
+        .ok_or(ServicesError::NotFound(format!("workflow {instance_id}")))?;
+
+    Ok(Json(resource.for_recipient(&player_id)))
+}
+
+pub async fn get_workflow_tree(
+    State(state): State<ApiState>,
+    Path((game_id, player_id, instance_id)): Path<(String, String, String)>,
+) -> AppResult<Json<WorkflowTree>> {
+    let workflow = state.get_game(&game_id).await?.workflow().await;
+    workflow
+        .manager
+        .authorize_user(&instance_id, &player_id)
+        .await?;
+    let tree = workflow.manager.workflow_tree(&instance_id).await?;
+
+    Ok(Json(tree.for_recipient(&player_id)))
+}
+
+pub async fn submit_action(
+    State(state): State<ApiState>,
+    Path((game_id, player_id, instance_id)): Path<(String, String, String)>,
+    Json(args): Json<SubmitActionArgs>,
+) -> AppResult<Json<WorkflowResource>> {
+    let workflow = state.get_game(&game_id).await?.workflow().await;
+    workflow
+        .process_action(
+            &player_id,
+            ProcessWorkflowActionArgs::new(instance_id.clone(), args.action_id, args.inputs),
+        )
+        .await?;
+
+    let resource = workflow.get_workflow_resource(&instance_id).await?;
+    Ok(Json(resource.for_recipient(&player_id)))
+}
+
+pub async fn respond_server_action(
+    State(state): State<ApiState>,
+    Path((game_id, player_id)): Path<(String, String)>,
+    Json(args): Json<WorkflowRespondServerActionArgs>,
+) -> AppResult<Json<Value>> {
+    let workflow = state.get_game(&game_id).await?.workflow().await;
+    workflow.respond_server_action(&player_id, args).await?;
+
+    Ok(Json(json!({ "ok": true })))
+}
+
+pub async fn delegate_workflow(
+    State(state): State<ApiState>,
+    Path((game_id, player_id, instance_id)): Path<(String, String, String)>,
+    Json(args): Json<DelegateWorkflowArgs>,
+) -> AppResult<Json<Value>> {
+    let workflow = state.get_game(&game_id).await?.workflow().await;
+    workflow
+        .delegate_workflow(&player_id, &instance_id, &args.user_id)
+        .await?;
+
+    Ok(Json(json!({ "ok": true })))
+}
+
+pub async fn revoke_delegation(
+    State(state): State<ApiState>,
+    Path((game_id, player_id, instance_id, delegate_id)): Path<(String, String, String, String)>,
+) -> AppResult<Json<Value>> {
+    let workflow = state.get_game(&game_id).await?.workflow().await;
+    workflow
+        .revoke_delegation(&player_id, &instance_id, &delegate_id)
+        .await?;
+
+    Ok(Json(json!({ "ok": true })))
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::{AppResult, ServicesError},
+    snapshot::PlayerSnapshot,
+    workflow::server_action::ServerActionResult,
+};
+
+/// Something that changed the course of a game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum GameLogEvent {
+    Dealt {
+        players: Vec<PlayerSnapshot>,
+        seed: u64,
+    },
+    TurnStarted {
+        player_id: String,
+        role: String,
+    },
+    WorkflowStarted {
+        instance_id: String,
+        workflow_id: String,
+        user_id: String,
+        inputs: HashMap<String, Value>,
+    },
+    ActionProcessed {
+        instance_id: String,
+        user_id: String,
+        action_id: String,
+        inputs: HashMap<String, Value>,
+    },
+    ServerActionCompleted {
+        instance_id: String,
+        action_id: String,
+        result: ServerActionResult,
+    },
+    CardsSwapped {
+        first_player_id: String,
+        second_player_id: String,
+    },
+    VotesCast {
+        votes: HashMap<String, String>,
+        eliminated: Vec<String>,
+    },
+    PlayerDied {
+        player_id: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameLogEntry {
+    pub sequence: u64,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub event: GameLogEvent,
+}
+
+/// Append-only record of a game. Entries are kept in memory and, when a file
+/// is attached, written to it as one JSON object per line. Appending does not
+/// wait, so entries are numbered in the order their events happened.
+#[derive(Debug, Default)]
+pub struct GameLog {
+    entries: Mutex<Vec<GameLogEntry>>,
+    file: Option<Mutex<File>>,
+}
+
+impl GameLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write this game's entries to `path`. Each log holds a single game, so
+    /// whatever was there before is replaced.
+    pub fn to_file(path: impl AsRef<Path>) -> AppResult<Self> {
+        let file = File::create(path)
+            .map_err(|e| ServicesError::InternalError(format!("Unable to open game log: {e}")))?;
+
+        Ok(Self {
+            entries: Mutex::new(Vec::new()),
+            file: Some(Mutex::new(file)),
+        })
+    }
+
+    pub fn append(&self, event: GameLogEvent) {
+        let mut entries = self.entries.lock().expect("game log poisoned");
+        let entry = GameLogEntry {
+            sequence: entries.len() as u64,
+            timestamp: chrono::Utc::now(),
+            event,
+        };
+
+        self.write(&entry);
+        entries.push(entry);
+    }
+
+    /// Carry on from the `entries` of a restored game. They are written out
+    /// again, as the file starts empty.
+    pub fn restore(&self, restored: Vec<GameLogEntry>) {
+        let mut entries = self.entries.lock().expect("game log poisoned");
+        for entry in &restored {
+            self.write(entry);
+        }
+        *entries = restored;
+    }
+
+    fn write(&self, entry: &GameLogEntry) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let mut file = file.lock().expect("game log file poisoned");
+        let written = serde_json::to_string(entry)
+            .map_err(|e| e.to_string())
+            .and_then(|line| writeln!(file, "{line}").map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            eprintln!("Failed to write game log entry {}: {}", entry.sequence, e);
+        }
+    }
+
+    pub fn entries(&self) -> Vec<GameLogEntry> {
+        self.entries.lock().expect("game log poisoned").clone()
+    }
+
+    /// Read back a log written by [`GameLog::to_file`].
+    pub fn read(path: impl AsRef<Path>) -> AppResult<Vec<GameLogEntry>> {
+        let file = File::open(path)
+            .map_err(|e| ServicesError::InternalError(format!("Unable to open game log: {e}")))?;
+
+        BufReader::new(file)
+            .lines()
+            .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+            .map(|line| {
+                let line = line.map_err(|e| ServicesError::InternalError(e.to_string()))?;
+                serde_json::from_str(&line).map_err(|e| {
+                    ServicesError::InternalError(format!("Invalid game log entry: {e}"))
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GameLog, GameLogEvent};
+
+    fn turn(player_id: &str) -> GameLogEvent {
+        GameLogEvent::TurnStarted {
+            player_id: player_id.to_string(),
+            role: "Seer".to_string(),
+        }
+    }
+
+    #[test]
+    fn reopening_a_log_starts_a_new_game() {
+        let path = std::env::temp_dir().join(format!("game-log-{}.jsonl", ulid::Ulid::new()));
+
+        let first = GameLog::to_file(&path).unwrap();
+        first.append(turn("alice"));
+        first.append(turn("bob"));
+        assert_eq!(GameLog::read(&path).unwrap().len(), 2);
+
+        let second = GameLog::to_file(&path).unwrap();
+        second.append(turn("carol"));
+        let entries = GameLog::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].sequence, 0);
+        assert!(matches!(
+            &entries[0].event,
+            GameLogEvent::TurnStarted { player_id, .. } if player_id == "carol"
+        ));
+    }
+}
@@ -7,10 +7,17 @@ use tokio::time::sleep;
 
 use tokio::sync::broadcast;
 
-use crate::gamestate::{GameState, RoleContext};
+use crate::error::AppResult;
+use crate::gamelog::{GameLog, GameLogEvent};
+use crate::gamestate::{GameFacts, GameState, Player, RoleContext};
 use crate::roles::{RoleAbility, RoleAbilitySpec, RoleCard};
-use crate::workflow::manager::WorkflowEvent;
-use crate::workflow::service::{ProcessWorkflowActionArgs, WorkflowResource};
+use crate::snapshot::{GameSnapshot, PlayerSnapshot, StageSnapshot};
+use crate::summary::{Locale, NightSummary};
+use crate::workflow::manager::{WorkflowEvent, WorkflowSnapshot};
+use crate::workflow::service::{
+    PendingExternalActionSnapshot, ProcessWorkflowActionArgs, WorkflowResource,
+};
+use crate::workflow::store::WorkflowStore;
 
 #[derive(Debug, Clone)]
 pub enum GameEvent {
@@ -28,6 +35,13 @@ pub enum GameEvent {
         player_id: String,
         workflow: WorkflowResource,
     },
+    VotesCast {
+        votes: HashMap<String, String>,
+        eliminated: Vec<String>,
+    },
+    GameResult {
+        summary: NightSummary,
+    },
 }
 
 pub enum PlayableAbility {
@@ -43,6 +57,10 @@ pub struct GameRunner {
     pub stages: VecDeque<(String, RoleCard)>,
     pub event_sender: GameEventSender,
     pub pending_actions: Arc<Mutex<HashMap<String, RoleAbility>>>,
+    pub locale: Locale,
+    cards_registered: bool,
+    // External actions of a restored game, asked for again on resume
+    restored_external_actions: Vec<PendingExternalActionSnapshot>,
 }
 
 impl GameRunner {
@@ -69,19 +87,80 @@ impl GameRunner {
         all_abilities.sort_by_key(|(_, a)| a.priority);
         let stages = VecDeque::from(all_abilities);
 
+        {
+            let game = game.lock().await;
+            let mut players: Vec<PlayerSnapshot> = game.players.values().map(Into::into).collect();
+            players.sort_by(|a, b| a.id.cmp(&b.id));
+            game.log.append(GameLogEvent::Dealt {
+                players,
+                seed: game.seed(),
+            });
+        }
+
+        Self::attach(game, stages, event_sender).await
+    }
+
+    /// Wrap `game` in a runner and hook it up to the game's workflow events.
+    async fn attach(
+        game: Arc<Mutex<GameState>>,
+        stages: VecDeque<(String, RoleCard)>,
+        event_sender: GameEventSender,
+    ) -> Arc<Mutex<Self>> {
         let runner = Arc::new(Mutex::new(Self {
             game: game.clone(),
             stages,
             event_sender,
             pending_actions: Arc::new(Mutex::new(HashMap::new())),
+            locale: Locale::default(),
+            cards_registered: false,
+            restored_external_actions: Vec::new(),
         }));
 
         {
+            let facts = Arc::new(GameFacts::new(&game));
             let game = game.lock().await;
             let _workflow_inner = Arc::clone(&game.workflow);
+            game.workflow.manager.set_fact_provider(facts).await;
 
             let mut event_manager = game.workflow.manager.event_manager.lock().await;
 
+            // Written before the callback returns rather than from the spawned
+            // future, so the log follows the order the events were emitted in.
+            let log = game.log.clone();
+            event_manager.on_event(Box::new(move |event| {
+                let entry = match event {
+                    WorkflowEvent::WorkflowStarted { resource } => GameLogEvent::WorkflowStarted {
+                        instance_id: resource.instance_id,
+                        workflow_id: resource.workflow_id,
+                        user_id: resource.user_id,
+                        inputs: resource.responses,
+                    },
+                    WorkflowEvent::ActionProcessed {
+                        instance_id,
+                        user_id,
+                        action_id,
+                        inputs,
+                    } => GameLogEvent::ActionProcessed {
+                        instance_id,
+                        user_id,
+                        action_id,
+                        inputs,
+                    },
+                    WorkflowEvent::ServerActionCompleted {
+                        instance_id,
+                        action_id,
+                        result,
+                    } => GameLogEvent::ServerActionCompleted {
+                        instance_id,
+                        action_id,
+                        result,
+                    },
+                    _ => return Box::pin(async {}),
+                };
+                log.append(entry);
+                Box::pin(async {})
+            }));
+
             let runner_inner = Arc::clone(&runner);
             event_manager.on_event(Box::new(move |event| {
                 let event = event.clone();
@@ -95,21 +174,27 @@ impl GameRunner {
                                 .event_sender
                                 .send(GameEvent::UpdateWorkflow {
                                     player_id: resource.user_id.clone(),
-                                    workflow: resource.clone(),
+                                    workflow: resource.for_recipient(&resource.user_id),
                                 })
                                 .ok();
                         }
-                        WorkflowEvent::WorkflowStarted { resource } => {
+                        WorkflowEvent::WorkflowStarted { resource }
+                        | WorkflowEvent::WorkflowCancelled { resource } => {
                             runner_inner
                                 .lock()
                                 .await
                                 .event_sender
                                 .send(GameEvent::UpdateWorkflow {
                                     player_id: resource.user_id.clone(),
-                                    workflow: resource.clone(),
+                                    workflow: resource.for_recipient(&resource.user_id),
                                 })
                                 .ok();
                         }
+                        // Players and cards can change along with the workflow.
+                        WorkflowEvent::ActionProcessed { .. }
+                        | WorkflowEvent::ServerActionCompleted { .. } => {
+                            runner_inner.lock().await.save().await;
+                        }
                         _ => {}
                     }
                 })
@@ -119,6 +204,107 @@ impl GameRunner {
         runner
     }
 
+    pub async fn snapshot(&self) -> GameSnapshot {
+        let mut snapshot = self.game.lock().await.snapshot().await;
+        snapshot.stages = self
+            .stages
+            .iter()
+            .map(|(player_id, card)| StageSnapshot {
+                player_id: player_id.clone(),
+                role: card.name.clone(),
+            })
+            .collect();
+        snapshot
+    }
+
+    /// Rebuild a game from `snapshot`, without the `Dealt` entry a new game
+    /// starts its log with. Cards are registered right away, but the restored
+    /// workflows only pick up again with [`GameRunner::resume`], so anything
+    /// listening to the game can be attached first.
+    pub async fn restore(
+        mut snapshot: GameSnapshot,
+        event_sender: GameEventSender,
+    ) -> AppResult<Arc<Mutex<Self>>> {
+        let stages = snapshot.restored_stages()?;
+        let external_actions = std::mem::take(&mut snapshot.external_actions);
+
+        let game = GameState::restore(snapshot).await?;
+        Ok(Self::restored(game, stages, external_actions, event_sender).await)
+    }
+
+    /// Play the game kept in `store`, or deal `players` a new one when it
+    /// holds none. As with [`GameRunner::restore`], a game picked up from the
+    /// store only runs again with [`GameRunner::resume`].
+    pub async fn open(
+        players: Vec<Player>,
+        store: Arc<dyn WorkflowStore>,
+        log: GameLog,
+        event_sender: GameEventSender,
+    ) -> AppResult<Arc<Mutex<Self>>> {
+        let Some(mut snapshot) = store.load_game().await? else {
+            let game = GameState::with_store(players, store).await?.with_log(log);
+            let runner = Self::new(game, event_sender).await;
+            runner.lock().await.save().await;
+            return Ok(runner);
+        };
+
+        let stages = snapshot.restored_stages()?;
+        let external_actions = std::mem::take(&mut snapshot.external_actions);
+        let game = GameState::with_store(snapshot.restored_players()?, store)
+            .await?
+            .with_log(log)
+            .continue_from(snapshot)
+            .await;
+        Ok(Self::restored(game, stages, external_actions, event_sender).await)
+    }
+
+    async fn restored(
+        game: GameState,
+        stages: VecDeque<(String, RoleCard)>,
+        external_actions: Vec<PendingExternalActionSnapshot>,
+        event_sender: GameEventSender,
+    ) -> Arc<Mutex<Self>> {
+        let runner = Self::attach(Arc::new(Mutex::new(game)), stages, event_sender).await;
+        {
+            let mut guard = runner.lock().await;
+            guard.register_cards().await;
+            guard.cards_registered = true;
+            guard.restored_external_actions = external_actions;
+        }
+        runner
+    }
+
+    /// Keep the game in its workflow store, so a restart carries on with it.
+    /// The workflows themselves are already saved as they change.
+    pub async fn save(&self) {
+        let mut snapshot = self.snapshot().await;
+        snapshot.workflows = WorkflowSnapshot {
+            external_server_actions: std::mem::take(
+                &mut snapshot.workflows.external_server_actions,
+            ),
+            ..WorkflowSnapshot::default()
+        };
+
+        let store = self.game.lock().await.workflow.manager.store.clone();
+        if let Err(e) = store.save_game(&snapshot).await {
+            eprintln!("Failed to save the game: {}", e);
+        }
+    }
+
+    /// Schedule the timeouts and check the waits of a restored game, and ask
+    /// again for the external actions it was waiting on.
+    pub async fn resume(runner: &Arc<Mutex<Self>>) -> AppResult<()> {
+        let (workflow, external_actions) = {
+            let mut guard = runner.lock().await;
+            let workflow = guard.game.lock().await.workflow.clone();
+            (
+                workflow,
+                std::mem::take(&mut guard.restored_external_actions),
+            )
+        };
+        workflow.resume(external_actions).await
+    }
+
     // pub async fn submit_action(
     //     &self,
     //     player_id: String,
@@ -158,10 +344,10 @@ impl GameRunner {
         if let Some(ability) = action {
             let ctx = RoleContext::new(Arc::clone(&self.game), player_id.to_string());
             if let Some(workflow_definition_with_input) = (&ability)(ctx).await {
-                self.game
-                    .lock()
-                    .await
-                    .workflow
+                // Entering the first node may read game facts, so the game
+                // must not stay locked.
+                let workflow = self.game.lock().await.workflow.clone();
+                workflow
                     .manager
                     .start_workflow(
                         &workflow_definition_with_input.definition,
@@ -183,6 +369,9 @@ impl GameRunner {
     }
 
     pub async fn register_cards(&self) {
+        GameState::register_game_server_actions(self.game.clone())
+            .await
+            .expect("Failed to register game server actions");
         let all_cards = self.game.lock().await.all_cards();
         for player in all_cards.iter() {
             if let Some(register) = &player.register {
@@ -192,11 +381,56 @@ impl GameRunner {
         }
     }
 
+    /// Tell everyone it is the turn of `ctx`'s player and start the workflow
+    /// of their night ability, if it has one.
+    pub async fn start_turn(runner: &Arc<Mutex<Self>>, ctx: RoleContext, ability: &RoleCard) {
+        let game = ctx.get_game();
+
+        // STEP 3: Emit TurnStarted
+        {
+            let log = { game.lock().await.log.clone() };
+            log.append(GameLogEvent::TurnStarted {
+                player_id: ctx.user_id.clone(),
+                role: ability.name.clone(),
+            });
+
+            runner
+                .lock()
+                .await
+                .event_sender
+                .send(GameEvent::TurnStarted {
+                    player_id: ctx.user_id.clone(),
+                    role: ability.clone(),
+                })
+                .ok();
+        }
+
+        // STEP 4: Generate workflow input (no locks held)
+
+        let mut workflow_input = None;
+        if let Some(ability) = &ability.night_ability {
+            workflow_input = (ability)(ctx.clone()).await;
+        }
+
+        // STEP 5: Start workflow if needed
+        if let Some(input) = workflow_input {
+            let workflow = game.lock().await.workflow.clone();
+            workflow
+                .manager
+                .start_workflow(&input.definition, &ctx.user_id, input.input)
+                .await
+                .expect("workflow start failed");
+        }
+    }
+
     pub async fn run(runner: Arc<Mutex<Self>>) {
         {
             // Register cards up front — safe
-            let runner_guard = runner.lock().await;
-            runner_guard.register_cards().await;
+            let mut runner_guard = runner.lock().await;
+            if !runner_guard.cards_registered {
+                runner_guard.register_cards().await;
+                runner_guard.cards_registered = true;
+            }
         }
         println!("beforeloop {:?}", runner);
 
@@ -206,7 +440,7 @@ impl GameRunner {
                 let mut guard = runner.lock().await;
                 match guard.stages.pop_front() {
                     Some((pid, ab)) => (pid.clone(), ab.clone(), Arc::clone(&guard.game)),
-                    None => return,
+                    None => break,
                 }
             };
 
@@ -234,35 +468,9 @@ impl GameRunner {
                 continue;
             }
 
-            // STEP 3: Emit TurnStarted
-            {
-                runner
-                    .lock()
-                    .await
-                    .event_sender
-                    .send(GameEvent::TurnStarted {
-                        player_id: player_id.clone(),
-                        role: ability.clone(),
-                    })
-                    .ok();
-            }
-
-            // STEP 4: Generate workflow input (no locks held)
-
-            let mut workflow_input = None;
-            if let Some(ability) = &ability.night_ability {
-                workflow_input = (ability)(ctx.clone()).await;
-            }
-
-            // STEP 5: Start workflow if needed
-            if let Some(input) = workflow_input {
-                let workflow = game_arc.lock().await.workflow.clone();
-                workflow
-                    .manager
-                    .start_workflow(&input.definition, &player_id, input.input)
-                    .await
-                    .expect("workflow start failed");
-            }
+            // STEPS 3-5: Emit TurnStarted and start the ability's workflow
+            Self::start_turn(&runner, ctx, &ability).await;
+            runner.lock().await.save().await;
 
             // STEP 6: Sleep with no locks
             println!(
@@ -284,6 +492,32 @@ impl GameRunner {
                     .ok();
             }
         }
+
+        // STEP 8: Tell everyone what really happened
+        let guard = runner.lock().await;
+        let summary = guard.summarize().await;
+        guard
+            .event_sender
+            .send(GameEvent::GameResult { summary })
+            .ok();
+    }
+
+    /// Settle the day's vote and tell everyone who was eliminated.
+    pub async fn record_votes(&self, votes: HashMap<String, String>) -> AppResult<Vec<String>> {
+        let eliminated = self.game.lock().await.record_votes(votes.clone()).await?;
+        self.save().await;
+        self.event_sender
+            .send(GameEvent::VotesCast {
+                votes,
+                eliminated: eliminated.clone(),
+            })
+            .ok();
+        Ok(eliminated)
+    }
+
+    pub async fn summarize(&self) -> NightSummary {
+        let log = { self.game.lock().await.log.clone() };
+        NightSummary::from_log(&log.entries(), self.locale)
     }
 
     async fn should_execute(&self, ctx: &RoleContext, ability: &RoleAbilitySpec) -> bool {
@@ -1,16 +1,24 @@
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
+
+use async_trait::async_trait;
 
 use futures::lock::Mutex;
 use rand::{SeedableRng, seq::IndexedRandom};
 use rand_chacha::ChaCha12Rng;
-use serde_json::Value;
+use serde_json::{Value, json};
 
 use crate::{
     error::{AppResult, ServicesError},
+    gamelog::{GameLog, GameLogEvent},
     roles::{Alliance, RoleCard},
+    snapshot::{GameSnapshot, PlayerSnapshot, SabotagedInputs},
     workflow::{
-        CreateWorkflowDefinition, server_action::ServerActionHandler, service::WorkflowService,
+        CreateWorkflowDefinition,
+        facts::FactProvider,
+        server_action::{ServerActionContext, ServerActionHandler, ServerActionResult},
+        service::WorkflowService,
+        store::WorkflowStore,
     },
 };
 
@@ -89,12 +97,86 @@ impl RoleContext {
     }
 }
 
+/// Exposes a game to workflow conditions. Holds the game weakly since the
+/// game owns the workflow manager that holds this provider.
+pub struct GameFacts {
+    game: Weak<Mutex<GameState>>,
+}
+
+impl GameFacts {
+    pub fn new(game: &Arc<Mutex<GameState>>) -> Self {
+        Self {
+            game: Arc::downgrade(game),
+        }
+    }
+}
+
+#[async_trait]
+impl FactProvider for GameFacts {
+    async fn facts(&self, user_id: &str) -> HashMap<String, Value> {
+        let Some(game) = self.game.upgrade() else {
+            return HashMap::new();
+        };
+        let game = game.lock().await;
+
+        let mut facts = HashMap::new();
+        if let Some(actor) = game.players.get(user_id) {
+            let card = actor.effective_role_card();
+            facts.insert(
+                "actor".to_string(),
+                json!({
+                    "id": actor.id,
+                    "role": card.name,
+                    "original_role": actor.role_card.name,
+                    "alliance": card.alliance,
+                    "is_alive": actor.is_alive,
+                }),
+            );
+        }
+        facts.insert(
+            "alive_players".to_string(),
+            json!(game.players.values().filter(|p| p.is_alive).count()),
+        );
+
+        let mut players: Vec<&Player> = game.players.values().collect();
+        players.sort_by_key(|p| (p.middle_position, p.id.clone()));
+        let (middle, seated): (Vec<&Player>, Vec<&Player>) = players
+            .into_iter()
+            .partition(|p| p.middle_position.is_some());
+        facts.insert(
+            "table".to_string(),
+            json!({
+                "players": seated.iter().map(|p| &p.id).collect::<Vec<_>>(),
+                "middle": middle.iter().map(|p| &p.id).collect::<Vec<_>>(),
+            }),
+        );
+        facts.insert(
+            "cards".to_string(),
+            json!(
+                game.all_cards()
+                    .iter()
+                    .map(|card| json!({
+                        "name": card.name,
+                        "alliance": card.alliance,
+                        "night_ability": card.night_ability.is_some(),
+                    }))
+                    .collect::<Vec<_>>()
+            ),
+        );
+
+        facts
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct GameState {
     pub players: HashMap<String, Player>,
     pub workflow: Arc<WorkflowService>,
     pub role_contexts: Arc<Mutex<HashMap<String, RoleContext>>>,
+    pub log: Arc<GameLog>,
     sabotaged_inputs: HashMap<(String, String), HashMap<String, Value>>,
+    seed: u64,
+    rng: Arc<Mutex<ChaCha12Rng>>,
 }
 
 impl GameState {
@@ -119,27 +201,214 @@ impl GameState {
         &'a self,
         roles: &'a [Arc<RoleCard>],
     ) -> Option<&'a Arc<RoleCard>> {
-        let mut rng = ChaCha12Rng::from_os_rng();
-        roles.choose(&mut rng)
+        let mut rng = self.rng.lock().await;
+        roles.choose(&mut *rng)
     }
 
     pub async fn new(players: Vec<Player>) -> Self {
+        Self::with_workflow(players, WorkflowService::new().await)
+    }
+
+    /// Same as [`GameState::new`], but workflows are persisted to `store` and
+    /// anything it already holds is loaded back.
+    pub async fn with_store(
+        players: Vec<Player>,
+        store: Arc<dyn WorkflowStore>,
+    ) -> AppResult<Self> {
+        let workflow_service = WorkflowService::with_store(store).await?;
+        Ok(Self::with_workflow(players, workflow_service))
+    }
+
+    fn with_workflow(players: Vec<Player>, workflow: Arc<WorkflowService>) -> Self {
         let mut map = HashMap::new();
         for player in players {
             map.insert(player.id.clone(), player);
         }
 
-        let workflow_service = WorkflowService::new().await;
-        let workflow = Arc::new(workflow_service);
+        let seed = rand::random();
 
         GameState {
             role_contexts: Arc::new(Mutex::new(HashMap::new())),
             players: map,
             workflow,
+            log: Arc::new(GameLog::new()),
             sabotaged_inputs: HashMap::new(),
+            seed,
+            rng: Arc::new(Mutex::new(ChaCha12Rng::seed_from_u64(seed))),
+        }
+    }
+
+    /// Record this game's events in `log` instead of the default in-memory one.
+    pub fn with_log(mut self, log: GameLog) -> Self {
+        self.log = Arc::new(log);
+        self
+    }
+
+    /// Deal out randomness from `seed`, to play a logged game again.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.rng = Arc::new(Mutex::new(ChaCha12Rng::seed_from_u64(seed)));
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Exchange the cards in front of two players (or middle positions).
+    pub async fn swap_cards(&mut self, first_id: &str, second_id: &str) -> AppResult<()> {
+        let first = self.get_player(first_id).await?.role_card;
+        let second = self.get_player(second_id).await?.role_card;
+
+        if let Some(player) = self.players.get_mut(first_id) {
+            player.role_card = second;
+        }
+        if let Some(player) = self.players.get_mut(second_id) {
+            player.role_card = first;
+        }
+
+        self.log.append(GameLogEvent::CardsSwapped {
+            first_player_id: first_id.to_string(),
+            second_player_id: second_id.to_string(),
+        });
+
+        Ok(())
+    }
+
+    pub async fn kill_player(&mut self, player_id: &str) -> AppResult<()> {
+        let player = self
+            .players
+            .get_mut(player_id)
+            .ok_or(ServicesError::InternalError(format!(
+                "Unable to find player with id {player_id}"
+            )))?;
+        player.is_alive = false;
+
+        self.log.append(GameLogEvent::PlayerDied {
+            player_id: player_id.to_string(),
+        });
+
+        Ok(())
+    }
+
+    /// Count `votes`, each voter's choice by voter id, and kill whoever got the
+    /// most. Nobody dies unless someone got more than one vote.
+    pub async fn record_votes(&mut self, votes: HashMap<String, String>) -> AppResult<Vec<String>> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for (voter_id, target_id) in &votes {
+            for id in [voter_id, target_id] {
+                if self.get_player(id).await?.middle_position.is_some() {
+                    return Err(ServicesError::InternalError(format!(
+                        "{id} is a middle card and cannot vote or be voted for"
+                    )));
+                }
+            }
+            *counts.entry(target_id).or_default() += 1;
+        }
+
+        let most = counts.values().copied().max().unwrap_or_default();
+        let mut eliminated: Vec<String> = counts
+            .into_iter()
+            .filter(|(_, count)| most > 1 && *count == most)
+            .map(|(id, _)| id.to_string())
+            .collect();
+        eliminated.sort();
+
+        self.log.append(GameLogEvent::VotesCast {
+            votes,
+            eliminated: eliminated.clone(),
+        });
+
+        for player_id in &eliminated {
+            self.kill_player(player_id).await?;
+        }
+
+        Ok(eliminated)
+    }
+
+    /// Register the server actions any role's workflow may run, whatever the
+    /// cards dealt.
+    pub async fn register_game_server_actions(game: Arc<Mutex<GameState>>) -> AppResult<()> {
+        let game_for_swap = game.clone();
+        game.lock()
+            .await
+            .register_server_action(
+                "swap_cards",
+                Box::new(move |context| {
+                    let game = game_for_swap.clone();
+                    Box::pin(async move {
+                        let first = card_id(&context, "first_card")?;
+                        let second = card_id(&context, "second_card")?;
+                        game.lock().await.swap_cards(&first, &second).await?;
+                        Ok(ServerActionResult::UpdateResponses(HashMap::new()))
+                    })
+                }),
+            )
+            .await
+    }
+
+    pub async fn snapshot(&self) -> GameSnapshot {
+        let mut players: Vec<PlayerSnapshot> = self.players.values().map(Into::into).collect();
+        players.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let sabotaged_inputs = self
+            .sabotaged_inputs
+            .iter()
+            .map(|((user_id, workflow_id), inputs)| SabotagedInputs {
+                user_id: user_id.clone(),
+                workflow_id: workflow_id.clone(),
+                inputs: inputs.clone(),
+            })
+            .collect();
+
+        GameSnapshot {
+            players,
+            stages: Vec::new(),
+            sabotaged_inputs,
+            workflows: self.workflow.manager.snapshot().await,
+            seed: self.seed,
+            rng_word_pos: self.rng.lock().await.get_word_pos(),
+            external_actions: self.workflow.pending_external_actions().await,
+            log: self.log.entries(),
         }
     }
 
+    /// Rebuild a game from `snapshot`. Role cards are looked up by name, and
+    /// the random generator continues where the snapshot left it. Nothing is
+    /// resumed yet, as the game's server actions are not registered.
+    pub async fn restore(mut snapshot: GameSnapshot) -> AppResult<Self> {
+        let workflows = std::mem::take(&mut snapshot.workflows);
+        let state = Self::new(snapshot.restored_players()?).await;
+        state.workflow.manager.restore_snapshot(workflows).await?;
+
+        Ok(state.continue_from(snapshot).await)
+    }
+
+    /// Take over everything in `snapshot` but its players and workflow
+    /// instances: sabotage, the random generator, who answers which external
+    /// action and the log so far.
+    pub async fn continue_from(mut self, snapshot: GameSnapshot) -> Self {
+        self.sabotaged_inputs = snapshot
+            .sabotaged_inputs
+            .into_iter()
+            .map(|s| ((s.user_id, s.workflow_id), s.inputs))
+            .collect();
+        self.workflow
+            .manager
+            .external_server_actions
+            .lock()
+            .await
+            .extend(snapshot.workflows.external_server_actions);
+        self.log.restore(snapshot.log);
+
+        let mut rng = ChaCha12Rng::seed_from_u64(snapshot.seed);
+        rng.set_word_pos(snapshot.rng_word_pos);
+        self.seed = snapshot.seed;
+        self.rng = Arc::new(Mutex::new(rng));
+
+        self
+    }
+
     pub async fn set_sabotage_inputs(
         &mut self,
         user_id: &str,
@@ -244,3 +513,151 @@ impl GameState {
         Ok(response)
     }
 }
+
+/// The id of the card selected in `input`, as submitted by a card input.
+fn card_id(context: &ServerActionContext, input: &str) -> AppResult<String> {
+    let kind = context.get_required_input_as_str(&format!("{input}.type"))?;
+    Ok(context
+        .get_required_input_as_str(&format!("{input}.{kind}.id"))?
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use futures::lock::Mutex;
+    use serde_json::{Value, json};
+
+    use super::{GameState, Player};
+    use crate::gamelog::GameLogEvent;
+    use crate::roles::seer::seer_card;
+    use crate::roles::villager_card;
+    use crate::roles::werewolf::werewolf_card;
+    use crate::workflow::builder::NodeId;
+    use crate::workflow::service::ProcessWorkflowActionArgs;
+    use crate::workflow::{
+        CardFilter, CreateWorkflowDefinition, WorkflowAction, WorkflowInput, WorkflowNode,
+    };
+
+    async fn game() -> GameState {
+        GameState::new(vec![
+            Player::new("alice", "Alice", Arc::new(villager_card()), None),
+            Player::new("bob", "Bob", Arc::new(werewolf_card()), None),
+            Player::new("carol", "Carol", Arc::new(seer_card()), None),
+            Player::new("middle1", "middle 1", Arc::new(villager_card()), Some(0)),
+        ])
+        .await
+    }
+
+    fn card(kind: &str, id: &str) -> Value {
+        let mut card = json!({ "type": kind });
+        card[kind] = json!({ "id": id });
+        card
+    }
+
+    #[tokio::test]
+    async fn votes_kill_whoever_got_the_most() {
+        let cases = vec![
+            (
+                vec![("alice", "bob"), ("bob", "alice"), ("carol", "bob")],
+                vec!["bob"],
+            ),
+            // Nobody got more than one vote.
+            (
+                vec![("alice", "bob"), ("bob", "carol"), ("carol", "alice")],
+                vec![],
+            ),
+        ];
+
+        for (votes, expected) in cases {
+            let mut game = game().await;
+            let votes: HashMap<String, String> = votes
+                .into_iter()
+                .map(|(voter, target)| (voter.to_string(), target.to_string()))
+                .collect();
+            let eliminated = game.record_votes(votes.clone()).await.unwrap();
+            assert_eq!(eliminated, expected, "{votes:?}");
+
+            let mut logged = game.log.entries().into_iter().map(|entry| entry.event);
+            assert!(matches!(
+                logged.next(),
+                Some(GameLogEvent::VotesCast { votes: cast, .. }) if cast == votes
+            ));
+            for player_id in &expected {
+                assert!(matches!(
+                    logged.next(),
+                    Some(GameLogEvent::PlayerDied { player_id: died }) if died == *player_id
+                ));
+                assert!(!game.players[*player_id].is_alive);
+            }
+            assert!(logged.next().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn middle_cards_cannot_vote() {
+        let mut game = game().await;
+        let votes = HashMap::from([("middle1".to_string(), "bob".to_string())]);
+        assert!(game.record_votes(votes).await.is_err());
+        assert!(game.log.entries().is_empty());
+    }
+
+    #[tokio::test]
+    async fn workflows_can_swap_cards() {
+        let game = Arc::new(Mutex::new(game().await));
+        GameState::register_game_server_actions(game.clone())
+            .await
+            .unwrap();
+        let pick = |id: &str| {
+            WorkflowInput::select_card(id, id, CardFilter::PlayerOrMiddle { allow_self: true })
+        };
+        let definition = CreateWorkflowDefinition::new("swap", "Swap")
+            .server_action("swap_cards", "Swap", "Swap two cards")
+            .node(
+                WorkflowNode::new(NodeId("pick"), "Pick")
+                    .input(pick("first_card"))
+                    .input(pick("second_card"))
+                    .action(WorkflowAction::run_server_action(
+                        "swap",
+                        "Swap",
+                        "swap_cards",
+                    ))
+                    .transition(NodeId("done")),
+            )
+            .node(WorkflowNode::new(NodeId("done"), "Done"));
+        let workflow = game.lock().await.workflow.clone();
+        let definition_id = game
+            .lock()
+            .await
+            .register_workflow_definition(definition)
+            .await
+            .unwrap();
+
+        let started = workflow
+            .start_command_workflow(&definition_id, "alice", HashMap::new())
+            .await
+            .unwrap();
+        let inputs = HashMap::from([
+            ("first_card".to_string(), card("Player", "bob")),
+            ("second_card".to_string(), card("Middle", "middle1")),
+        ]);
+        workflow
+            .process_action(
+                "alice",
+                ProcessWorkflowActionArgs::new(started.instance_id, "swap".to_string(), inputs),
+            )
+            .await
+            .unwrap();
+
+        let game = game.lock().await;
+        assert_eq!(game.players["bob"].role_card.name, "Villager");
+        assert_eq!(game.players["middle1"].role_card.name, "Werewolf");
+        assert!(game.log.entries().iter().any(|entry| matches!(
+            &entry.event,
+            GameLogEvent::CardsSwapped { first_player_id, second_player_id }
+                if first_player_id == "bob" && second_player_id == "middle1"
+        )));
+    }
+}
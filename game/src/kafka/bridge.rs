@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+use futures::lock::Mutex;
+
+use crate::{
+    error::ServicesError,
+    gamerunner::{GameEvent, GameEventSender},
+    workflow::{
+        manager::WorkflowEvent,
+        service::{WorkflowRespondServerActionArgs, WorkflowService},
+    },
+};
+
+use super::{
+    bus::EventBus,
+    topic::{GameTopicMessage, KafkaTopic, TopicMessage, VoteTopicMessage, WorkflowTopicMessage},
+};
+
+/// Consumer group of every game hosted by the server `server_id`. Each server
+/// needs a group of its own: members of one group split the partitions, so a
+/// shared group would hand responses for a game to whichever server owns its
+/// partition rather than the one hosting it. Responses are then routed to
+/// their game by message key.
+pub fn consumer_group(server_id: &str) -> String {
+    format!("midnight-games-{server_id}")
+}
+
+/// Connects the games of this server to an [`EventBus`]. Each game publishes
+/// under its game id as key, and the one subscription hands what comes back
+/// to the game with that id.
+#[derive(Clone)]
+pub struct Bridge {
+    bus: Arc<dyn EventBus>,
+    games: Arc<Mutex<HashMap<String, Weak<WorkflowService>>>>,
+}
+
+impl Bridge {
+    pub async fn new(bus: Arc<dyn EventBus>, server_id: &str) -> Self {
+        let games: Arc<Mutex<HashMap<String, Weak<WorkflowService>>>> = Arc::default();
+        let group_id = consumer_group(server_id);
+
+        let routes = games.clone();
+        bus.subscribe(
+            KafkaTopic::Workflows,
+            &group_id,
+            Arc::new(move |key, message| {
+                let routes = routes.clone();
+                Box::pin(async move {
+                    let TopicMessage::Workflow(message) = message else {
+                        return;
+                    };
+                    let WorkflowTopicMessage::ServerActionResponse {
+                        id,
+                        user_id,
+                        result,
+                    } = *message
+                    else {
+                        return;
+                    };
+                    // Games hosted elsewhere, or already gone, are not ours to answer.
+                    let Some(workflow) = routes.lock().await.get(&key).and_then(Weak::upgrade)
+                    else {
+                        return;
+                    };
+
+                    let args = WorkflowRespondServerActionArgs { token: id, result };
+                    match workflow.respond_server_action(&user_id, args).await {
+                        // Already answered, or the token has expired.
+                        Ok(()) | Err(ServicesError::NotFound(_)) => {}
+                        Err(e) => eprintln!("Failed to apply server action response: {}", e),
+                    }
+                })
+            }),
+        )
+        .await;
+
+        println!("Event bus bridge attached with consumer group {group_id}");
+        Self { bus, games }
+    }
+
+    /// Publish every workflow event of `workflow` keyed by `game_id`, and apply
+    /// the external server action responses published under the same key.
+    pub async fn attach(&self, workflow: Arc<WorkflowService>, game_id: &str) {
+        self.games
+            .lock()
+            .await
+            .insert(game_id.to_string(), Arc::downgrade(&workflow));
+
+        let publisher = self.bus.clone();
+        let key = game_id.to_string();
+        workflow
+            .manager
+            .event_manager
+            .lock()
+            .await
+            .on_event(Box::new(move |event| {
+                let bus = publisher.clone();
+                let key = key.clone();
+                Box::pin(async move {
+                    let message = match event {
+                        WorkflowEvent::WorkflowStarted { resource } => {
+                            WorkflowTopicMessage::Created { workflow: resource }
+                        }
+                        WorkflowEvent::WorkflowUpdated { resource } => {
+                            WorkflowTopicMessage::Updated { workflow: resource }
+                        }
+                        WorkflowEvent::WorkflowCancelled { resource } => {
+                            WorkflowTopicMessage::Cancelled { workflow: resource }
+                        }
+                        WorkflowEvent::ExternalServerActionRequested {
+                            token,
+                            action_id,
+                            resource,
+                        } => WorkflowTopicMessage::ServerActionRequest {
+                            id: token,
+                            workflow: resource,
+                            action_id,
+                        },
+                        WorkflowEvent::NodeReminder {
+                            resource,
+                            remaining_seconds,
+                        } => WorkflowTopicMessage::Reminder {
+                            workflow: resource,
+                            remaining_seconds,
+                        },
+                        WorkflowEvent::ActionProcessed { .. }
+                        | WorkflowEvent::ServerActionCompleted { .. }
+                        | WorkflowEvent::NodeExpired { .. } => return,
+                    };
+
+                    if let Err(e) = bus
+                        .publish(KafkaTopic::Workflows, &key, message.into())
+                        .await
+                    {
+                        eprintln!("Failed to publish workflow event: {}", e);
+                    }
+                })
+            }));
+    }
+
+    /// Publish the lifecycle of a game on the `game-lifecycle` topic, and the
+    /// outcome of its votes on `vote-results`.
+    pub async fn attach_game(
+        &self,
+        game_id: &str,
+        player_ids: Vec<String>,
+        events: &GameEventSender,
+    ) {
+        let bus = self.bus.clone();
+        let created = GameTopicMessage::GameCreated {
+            game_id: game_id.to_string(),
+            player_ids,
+        };
+        if let Err(e) = bus
+            .publish(KafkaTopic::GameLifecycle, game_id, created.into())
+            .await
+        {
+            eprintln!("Failed to publish game creation: {}", e);
+        }
+
+        let game_id = game_id.to_string();
+        let mut receiver = events.subscribe();
+        tokio::spawn(async move {
+            while let Ok(event) = receiver.recv().await {
+                let (topic, message): (KafkaTopic, TopicMessage) = match event {
+                    GameEvent::TurnStarted { player_id, role } => (
+                        KafkaTopic::GameLifecycle,
+                        GameTopicMessage::TurnStarted {
+                            game_id: game_id.clone(),
+                            player_id,
+                            role: role.name,
+                        }
+                        .into(),
+                    ),
+                    GameEvent::TurnExpired { player_id } => (
+                        KafkaTopic::GameLifecycle,
+                        GameTopicMessage::TurnExpired {
+                            game_id: game_id.clone(),
+                            player_id,
+                        }
+                        .into(),
+                    ),
+                    GameEvent::VotesCast { votes, eliminated } => (
+                        KafkaTopic::VoteResults,
+                        VoteTopicMessage::VoteResult {
+                            game_id: game_id.clone(),
+                            votes,
+                            eliminated,
+                        }
+                        .into(),
+                    ),
+                    _ => continue,
+                };
+
+                if let Err(e) = bus.publish(topic, &game_id, message).await {
+                    eprintln!("Failed to publish {topic} event: {}", e);
+                }
+            }
+        });
+    }
+}
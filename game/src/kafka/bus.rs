@@ -0,0 +1,27 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use super::topic::{KafkaTopic, TopicMessage};
+
+/// Called with the key a message was published under, empty if it had none,
+/// and the message itself.
+pub type MessageHandler =
+    Arc<dyn Fn(String, TopicMessage) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+/// Transport used to share workflow messages with other services.
+#[async_trait]
+pub trait EventBus: Send + Sync {
+    /// Messages sharing a `key` are delivered in the order they were published.
+    async fn publish(
+        &self,
+        topic: KafkaTopic,
+        key: &str,
+        message: TopicMessage,
+    ) -> Result<(), String>;
+
+    /// Deliver every message published to `topic` from now on to `handler`.
+    async fn subscribe(&self, topic: KafkaTopic, group_id: &str, handler: MessageHandler);
+}
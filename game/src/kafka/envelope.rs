@@ -0,0 +1,172 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use super::topic::{DeadLetterMessage, KafkaTopic, TopicMessage};
+
+/// Version written by this producer. Version 1 is the bare, unwrapped
+/// message that was published before envelopes existed.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Error)]
+pub enum EnvelopeError {
+    #[error("Invalid message: {0}")]
+    InvalidMessage(#[from] serde_json::Error),
+
+    #[error("Unsupported schema version {0}")]
+    UnsupportedVersion(u64),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageEnvelope {
+    pub schema_version: u32,
+    pub producer_id: String,
+    pub message_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub payload: TopicMessage,
+}
+
+#[derive(Deserialize)]
+struct RawEnvelope {
+    producer_id: String,
+    message_id: String,
+    timestamp: DateTime<Utc>,
+    payload: Value,
+}
+
+impl MessageEnvelope {
+    pub fn new(producer_id: &str, payload: TopicMessage) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            producer_id: producer_id.to_string(),
+            message_id: ulid::Ulid::new().to_string(),
+            timestamp: Utc::now(),
+            payload,
+        }
+    }
+
+    pub fn encode(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    pub fn decode(topic: KafkaTopic, payload: &[u8]) -> Result<Self, EnvelopeError> {
+        let value: Value = serde_json::from_slice(payload)?;
+
+        match value.get("schema_version").and_then(Value::as_u64) {
+            None => Ok(Self {
+                schema_version: 1,
+                producer_id: "unknown".to_string(),
+                message_id: ulid::Ulid::new().to_string(),
+                timestamp: Utc::now(),
+                payload: TopicMessage::decode(topic, value)?,
+            }),
+            Some(2) => {
+                let raw: RawEnvelope = serde_json::from_value(value)?;
+                Ok(Self {
+                    schema_version: SCHEMA_VERSION,
+                    producer_id: raw.producer_id,
+                    message_id: raw.message_id,
+                    timestamp: raw.timestamp,
+                    payload: TopicMessage::decode(topic, raw.payload)?,
+                })
+            }
+            Some(version) => Err(EnvelopeError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+/// Decode a `payload` read from `topic` under `key`, or turn it into the dead
+/// letter it should be kept as.
+pub fn decode_or_dead_letter(
+    topic: KafkaTopic,
+    key: Option<String>,
+    payload: &[u8],
+) -> Result<MessageEnvelope, DeadLetterMessage> {
+    MessageEnvelope::decode(topic, payload).map_err(|e| DeadLetterMessage {
+        source_topic: topic.topic_name().to_string(),
+        key,
+        payload: String::from_utf8_lossy(payload).to_string(),
+        error: e.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{EnvelopeError, MessageEnvelope, SCHEMA_VERSION, decode_or_dead_letter};
+    use crate::kafka::topic::{GameTopicMessage, KafkaTopic, TopicMessage};
+
+    fn turn_expired() -> GameTopicMessage {
+        GameTopicMessage::TurnExpired {
+            game_id: "game".to_string(),
+            player_id: "alice".to_string(),
+        }
+    }
+
+    fn is_turn_expired(message: &TopicMessage) -> bool {
+        matches!(
+            message,
+            TopicMessage::Game(GameTopicMessage::TurnExpired { player_id, .. }) if player_id == "alice"
+        )
+    }
+
+    #[test]
+    fn envelopes_come_back_as_they_were_sent() {
+        let sent = MessageEnvelope::new("server-1", turn_expired().into());
+        let encoded = sent.encode().unwrap();
+
+        let received =
+            MessageEnvelope::decode(KafkaTopic::GameLifecycle, encoded.as_bytes()).unwrap();
+        assert_eq!(received.schema_version, SCHEMA_VERSION);
+        assert_eq!(received.producer_id, "server-1");
+        assert_eq!(received.message_id, sent.message_id);
+        assert_eq!(received.timestamp, sent.timestamp);
+        assert!(is_turn_expired(&received.payload));
+    }
+
+    #[test]
+    fn bare_messages_decode_as_version_one() {
+        let bare = serde_json::to_vec(&turn_expired()).unwrap();
+
+        let received = MessageEnvelope::decode(KafkaTopic::GameLifecycle, &bare).unwrap();
+        assert_eq!(received.schema_version, 1);
+        assert_eq!(received.producer_id, "unknown");
+        assert!(is_turn_expired(&received.payload));
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let mut envelope =
+            serde_json::to_value(MessageEnvelope::new("server-1", turn_expired().into())).unwrap();
+        envelope["schema_version"] = json!(SCHEMA_VERSION + 1);
+        let payload = serde_json::to_vec(&envelope).unwrap();
+
+        assert!(matches!(
+            MessageEnvelope::decode(KafkaTopic::GameLifecycle, &payload),
+            Err(EnvelopeError::UnsupportedVersion(version)) if version == u64::from(SCHEMA_VERSION + 1)
+        ));
+    }
+
+    #[test]
+    fn undecodable_messages_become_dead_letters() {
+        let on_the_wrong_topic = MessageEnvelope::new("server-1", turn_expired().into())
+            .encode()
+            .unwrap();
+        let cases: Vec<(&str, &[u8])> = vec![
+            ("not json", b"{not json"),
+            ("wrong topic", on_the_wrong_topic.as_bytes()),
+        ];
+
+        for (name, payload) in cases {
+            let dead_letter =
+                decode_or_dead_letter(KafkaTopic::Workflows, Some("game".to_string()), payload)
+                    .expect_err(name);
+            assert_eq!(dead_letter.source_topic, "workflows", "{name}");
+            assert_eq!(dead_letter.key.as_deref(), Some("game"), "{name}");
+            assert_eq!(dead_letter.payload.as_bytes(), payload, "{name}");
+            assert!(!dead_letter.error.is_empty(), "{name}");
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+
+use super::{
+    bus::{EventBus, MessageHandler},
+    topic::{KafkaTopic, TopicMessage},
+};
+
+/// In-process stand-in for Kafka. Every consumer group receives every message
+/// published to its topic, in publish order. Members of one group split the
+/// messages between them by key, as they would Kafka partitions.
+#[derive(Clone)]
+pub struct InMemoryBus {
+    sender: broadcast::Sender<(KafkaTopic, String, TopicMessage)>,
+    members: Arc<Mutex<HashMap<(KafkaTopic, String), usize>>>,
+}
+
+impl InMemoryBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(256);
+        Self {
+            sender,
+            members: Arc::default(),
+        }
+    }
+}
+
+impl Default for InMemoryBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl EventBus for InMemoryBus {
+    async fn publish(
+        &self,
+        topic: KafkaTopic,
+        key: &str,
+        message: TopicMessage,
+    ) -> Result<(), String> {
+        // Publishing without subscribers is not an error, same as Kafka.
+        let _ = self.sender.send((topic, key.to_string(), message));
+        Ok(())
+    }
+
+    async fn subscribe(&self, topic: KafkaTopic, group_id: &str, handler: MessageHandler) {
+        let mut receiver = self.sender.subscribe();
+        let group = (topic, group_id.to_string());
+        let member = {
+            let mut members = self.members.lock().expect("bus members poisoned");
+            let count = members.entry(group.clone()).or_default();
+            *count += 1;
+            *count - 1
+        };
+        let members = self.members.clone();
+
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok((message_topic, key, message)) => {
+                        if message_topic != topic {
+                            continue;
+                        }
+                        let count = members.lock().expect("bus members poisoned")[&group];
+                        let mut hasher = DefaultHasher::new();
+                        key.hash(&mut hasher);
+                        if hasher.finish() % count as u64 == member as u64 {
+                            handler(key, message).await;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        eprintln!("In-memory consumer {} skipped {skipped} messages", group.1);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::InMemoryBus;
+    use crate::kafka::bridge::Bridge;
+    use crate::kafka::bus::EventBus;
+    use crate::kafka::topic::{GameTopicMessage, KafkaTopic, TopicMessage, WorkflowTopicMessage};
+    use crate::workflow::builder::NodeId;
+    use crate::workflow::server_action::ServerActionResult;
+    use crate::workflow::service::{ProcessWorkflowActionArgs, WorkflowService};
+    use crate::workflow::{CreateWorkflowDefinition, WorkflowAction, WorkflowNode};
+
+    /// Stands in for another service answering every request it is sent.
+    async fn answer_every_request(bus: Arc<dyn EventBus>) {
+        let responder = bus.clone();
+        bus.subscribe(
+            KafkaTopic::Workflows,
+            "oracle",
+            Arc::new(move |key, message| {
+                let bus = responder.clone();
+                Box::pin(async move {
+                    let TopicMessage::Workflow(message) = message else {
+                        return;
+                    };
+                    let WorkflowTopicMessage::ServerActionRequest { id, .. } = *message else {
+                        return;
+                    };
+                    let response = WorkflowTopicMessage::ServerActionResponse {
+                        id,
+                        user_id: "oracle".to_string(),
+                        result: ServerActionResult::UpdateResponses(HashMap::from([(
+                            "answer".to_string(),
+                            json!(42),
+                        )])),
+                    };
+                    bus.publish(KafkaTopic::Workflows, &key, response.into())
+                        .await
+                        .unwrap();
+                })
+            }),
+        )
+        .await;
+    }
+
+    /// Host a game on `bridge` whose only workflow asks the oracle, and return
+    /// the service and the instance waiting for the answer.
+    async fn ask_oracle(bridge: &Bridge, game_id: &str) -> (Arc<WorkflowService>, String) {
+        let service = WorkflowService::new().await;
+        let action_id = service
+            .register_external_server_action("oracle", "lookup")
+            .await
+            .unwrap();
+        let definition = CreateWorkflowDefinition::new("asker", "Asker")
+            .server_action(&action_id, "Lookup", "Answered by the oracle")
+            .node(
+                WorkflowNode::new(NodeId("ask"), "Ask")
+                    .action(WorkflowAction::run_server_action("go", "Go", &action_id))
+                    .transition(NodeId("answered")),
+            )
+            .node(WorkflowNode::new(NodeId("answered"), "Answered"));
+        let definition_id = service
+            .register_workflow_definition("oracle", definition)
+            .await
+            .unwrap();
+        bridge.attach(service.clone(), game_id).await;
+
+        let workflow = service
+            .start_command_workflow(&definition_id, "owner", HashMap::new())
+            .await
+            .unwrap();
+        service
+            .process_action(
+                "owner",
+                ProcessWorkflowActionArgs::new(
+                    workflow.instance_id.clone(),
+                    "go".to_string(),
+                    HashMap::new(),
+                ),
+            )
+            .await
+            .unwrap();
+        (service, workflow.instance_id)
+    }
+
+    async fn answered(service: &WorkflowService, instance_id: &str) -> bool {
+        for _ in 0..50 {
+            let workflow = service.get_workflow_resource(instance_id).await.unwrap();
+            if let Some(answer) = workflow.responses.get("answer") {
+                assert_eq!(answer, &json!(42));
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn external_actions_round_trip_through_the_bus() {
+        let bus: Arc<dyn EventBus> = Arc::new(InMemoryBus::new());
+        let bridge = Bridge::new(bus.clone(), "server").await;
+        answer_every_request(bus).await;
+
+        let (service, instance_id) = ask_oracle(&bridge, "game").await;
+        assert!(
+            answered(&service, &instance_id).await,
+            "the response never came back through the bus"
+        );
+    }
+
+    #[tokio::test]
+    async fn every_server_hears_back_for_its_own_games() {
+        let bus: Arc<dyn EventBus> = Arc::new(InMemoryBus::new());
+        let east = Bridge::new(bus.clone(), "east").await;
+        let west = Bridge::new(bus.clone(), "west").await;
+        answer_every_request(bus).await;
+
+        for (bridge, game_id) in [(&east, "east-game"), (&west, "west-game")] {
+            let (service, instance_id) = ask_oracle(bridge, game_id).await;
+            assert!(
+                answered(&service, &instance_id).await,
+                "{game_id} never heard back"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn members_of_a_group_split_the_messages() {
+        let bus = InMemoryBus::new();
+        let (received, mut messages) = tokio::sync::mpsc::unbounded_channel();
+        for member in 0..2 {
+            let received = received.clone();
+            bus.subscribe(
+                KafkaTopic::GameLifecycle,
+                "group",
+                Arc::new(move |key, _| {
+                    let _ = received.send((member, key));
+                    Box::pin(async {})
+                }),
+            )
+            .await;
+        }
+
+        let game_ids: Vec<String> = (0..20).map(|game| format!("game-{game}")).collect();
+        for game_id in game_ids.iter().chain(&game_ids) {
+            let created = GameTopicMessage::GameCreated {
+                game_id: game_id.clone(),
+                player_ids: vec![],
+            };
+            bus.publish(KafkaTopic::GameLifecycle, game_id, created.into())
+                .await
+                .unwrap();
+        }
+
+        let mut handled_by = HashMap::new();
+        for _ in 0..game_ids.len() * 2 {
+            let (member, key) = tokio::time::timeout(Duration::from_secs(1), messages.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            // Every message of a game goes to the same member.
+            assert_eq!(*handled_by.entry(key).or_insert(member), member);
+        }
+        assert_eq!(handled_by.len(), game_ids.len());
+        assert!(messages.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn subscribers_only_see_their_topic() {
+        let bus = InMemoryBus::new();
+        let (received, mut messages) = tokio::sync::mpsc::unbounded_channel();
+        bus.subscribe(
+            KafkaTopic::GameLifecycle,
+            "test",
+            Arc::new(move |key, message| {
+                let _ = received.send((key, message));
+                Box::pin(async {})
+            }),
+        )
+        .await;
+
+        let response = WorkflowTopicMessage::ServerActionResponse {
+            id: "token".to_string(),
+            user_id: "oracle".to_string(),
+            result: ServerActionResult::CancelWorkflow,
+        };
+        let created = GameTopicMessage::GameCreated {
+            game_id: "game".to_string(),
+            player_ids: vec![],
+        };
+        bus.publish(KafkaTopic::Workflows, "game", response.into())
+            .await
+            .unwrap();
+        bus.publish(KafkaTopic::GameLifecycle, "game", created.into())
+            .await
+            .unwrap();
+
+        let (key, message) = tokio::time::timeout(Duration::from_secs(1), messages.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(key, "game");
+        assert!(matches!(message, TopicMessage::Game(_)));
+        assert!(messages.try_recv().is_err());
+    }
+}
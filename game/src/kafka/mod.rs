@@ -1,2 +1,6 @@
+pub mod bridge;
+pub mod bus;
+pub mod envelope;
+pub mod memory;
 pub mod service;
 pub mod topic;
@@ -1,9 +1,10 @@
+use async_trait::async_trait;
 use futures::lock::Mutex;
+use rdkafka::ClientConfig;
 use rdkafka::config::RDKafkaLogLevel;
 use rdkafka::consumer::{Consumer, StreamConsumer};
 use rdkafka::message::{Message, OwnedMessage};
 use rdkafka::producer::{FutureProducer, FutureRecord};
-use rdkafka::{ClientConfig, Offset, TopicPartitionList};
 use serde::Serialize;
 use serde_json::Value;
 use std::collections::HashMap;
@@ -11,100 +12,37 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use crate::workflow::WorkflowDefinition;
-use crate::workflow::service::WorkflowResource;
 
-use super::topic::{KafkaTopic, WorkflowTopicMessage};
+use super::bus::{EventBus, MessageHandler};
+use super::envelope::{MessageEnvelope, decode_or_dead_letter};
+use super::topic::{KafkaTopic, TopicMessage};
 
+#[derive(Clone)]
 pub struct WorkflowsPublisher {
     producer: FutureProducer,
+    producer_id: String,
 }
 
 impl WorkflowsPublisher {
-    pub async fn publish(&self, message: &WorkflowTopicMessage) -> Result<(), String> {
-        let payload =
-            serde_json::to_string(message).map_err(|e| format!("Serialization error: {}", e))?;
-
-        let record = FutureRecord::to(KafkaTopic::Workflows.topic_name())
-            .payload(Box::leak(payload.into_boxed_str()))
-            .key("");
-
-        let producer = self.producer.clone();
-        let send_future = tokio::spawn(async move {
-            producer
-                .send(record, Duration::from_secs(5))
-                .await
-                .map_err(|(e, _)| format!("Failed to send message: {}", e))
-        });
-
-        send_future
-            .await
-            .map_err(|e| format!("Join error: {:?}", e))?
-            .map(|_| ())
-    }
-
-    pub async fn create_workflow(&self, workflow: WorkflowResource) -> Result<(), String> {
-        let message = WorkflowTopicMessage::Created { workflow };
-        // println!("Published message: {:?}", message);
-
-        let producer = self.producer.clone();
-        let payload =
-            serde_json::to_string(&message).map_err(|e| format!("Serialization error: {}", e))?;
-
-        let record = FutureRecord::to(KafkaTopic::Workflows.topic_name())
-            .payload(Box::leak(payload.into_boxed_str()))
-            .key("");
-
-        tokio::spawn(async move {
-            if let Err(e) = producer
-                .send(record, Duration::from_secs(5))
-                .await
-                .map_err(|(e, _)| format!("Failed to send message: {}", e))
-            {
-                eprintln!("Failed to publish workflow creation: {}", e);
-            }
-        });
-
-        Ok(())
-    }
-
-    pub async fn update_workflow(&self, workflow: WorkflowResource) -> Result<(), String> {
-        let message = WorkflowTopicMessage::Updated { workflow };
-        // println!("Published message: {:?}", message);
-
-        let producer = self.producer.clone();
-        let payload =
-            serde_json::to_string(&message).map_err(|e| format!("Serialization error: {}", e))?;
-
-        let record = FutureRecord::to(KafkaTopic::Workflows.topic_name())
-            .payload(Box::leak(payload.into_boxed_str()))
-            .key("");
-
-        tokio::spawn(async move {
-            if let Err(e) = producer
-                .send(record, Duration::from_secs(5))
-                .await
-                .map_err(|(e, _)| format!("Failed to send message: {}", e))
-            {
-                eprintln!("Failed to publish workflow creation: {}", e);
-            }
-        });
-
-        Ok(())
-    }
-
-    pub async fn request_server_action_request(
+    pub async fn publish_to(
         &self,
-        id: String,
-        workflow: WorkflowResource,
-        action_id: String,
+        topic: KafkaTopic,
+        key: &str,
+        message: &TopicMessage,
     ) -> Result<(), String> {
-        let message = WorkflowTopicMessage::ServerActionRequest {
-            id,
-            workflow,
-            action_id,
-        };
+        let payload = MessageEnvelope::new(&self.producer_id, message.clone())
+            .encode()
+            .map_err(|e| format!("Serialization error: {}", e))?;
 
-        self.publish(&message).await
+        let record = FutureRecord::to(topic.topic_name())
+            .payload(&payload)
+            .key(key);
+
+        self.producer
+            .send(record, Duration::from_secs(5))
+            .await
+            .map(|_| ())
+            .map_err(|(e, _)| format!("Failed to send message: {}", e))
     }
 }
 
@@ -137,14 +75,16 @@ impl KafkaService {
             producer: producer.clone(),
             workflows: WorkflowsPublisher {
                 producer: producer.clone(),
+                producer_id: format!("game-{}", ulid::Ulid::new()),
             },
         }
     }
 
-    pub async fn start_workflow_consumer<F>(&self, group_id: String, handler: F)
+    pub async fn start_consumer<F>(&self, topic: KafkaTopic, group_id: String, handler: F)
     where
         F: Fn(
-                WorkflowTopicMessage,
+                String,
+                TopicMessage,
             ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>
             + Send
             + Sync
@@ -153,6 +93,7 @@ impl KafkaService {
     {
         let brokers = self.brokers.clone();
         let handler_clone = handler.clone();
+        let dead_letters = self.workflows.clone();
 
         tokio::spawn(async move {
             let consumer: StreamConsumer = ClientConfig::new()
@@ -164,29 +105,42 @@ impl KafkaService {
                 .create()
                 .expect("Failed to create consumer");
 
-            let topic = KafkaTopic::Workflows.topic_name();
-            let topics = &[topic];
+            let topic_name = topic.topic_name();
 
+            // Partitions are assigned by the consumer group, so messages sharing
+            // a key (one game) are always handled in order by a single member.
             consumer
-                .subscribe(topics)
+                .subscribe(&[topic_name])
                 .expect("Failed to subscribe to topics");
 
-            let mut tpl = TopicPartitionList::new();
-            tpl.add_partition_offset(topic, 0, Offset::End)
-                .expect("Failed to set partition offset");
-            consumer.assign(&tpl).expect("Failed to assign partitions");
-
             loop {
                 match consumer.recv().await {
                     Ok(msg) => {
                         if let Some(payload) = msg.payload() {
-                            match serde_json::from_slice::<WorkflowTopicMessage>(payload) {
-                                Ok(chat_message) => {
-                                    let future = handler_clone(chat_message);
+                            let key = msg
+                                .key()
+                                .map(|key| String::from_utf8_lossy(key).to_string());
+                            match decode_or_dead_letter(topic, key.clone(), payload) {
+                                Ok(envelope) => {
+                                    let future =
+                                        handler_clone(key.unwrap_or_default(), envelope.payload);
                                     future.await;
                                 }
-                                Err(e) => {
-                                    eprintln!("Failed to deserialize workflow message: {}", e)
+                                Err(dead_letter) => {
+                                    eprintln!(
+                                        "Failed to decode {} message: {}",
+                                        topic, dead_letter.error
+                                    );
+                                    if let Err(e) = dead_letters
+                                        .publish_to(
+                                            KafkaTopic::DeadLetters,
+                                            key.as_deref().unwrap_or(""),
+                                            &dead_letter.into(),
+                                        )
+                                        .await
+                                    {
+                                        eprintln!("Failed to publish dead letter: {}", e);
+                                    }
                                 }
                             }
                         }
@@ -205,9 +159,26 @@ impl Clone for KafkaService {
         Self {
             brokers: self.brokers.clone(),
             producer: self.producer.clone(),
-            workflows: WorkflowsPublisher {
-                producer: self.producer.clone(),
-            },
+            workflows: self.workflows.clone(),
         }
     }
 }
+
+#[async_trait]
+impl EventBus for KafkaService {
+    async fn publish(
+        &self,
+        topic: KafkaTopic,
+        key: &str,
+        message: TopicMessage,
+    ) -> Result<(), String> {
+        self.workflows.publish_to(topic, key, &message).await
+    }
+
+    async fn subscribe(&self, topic: KafkaTopic, group_id: &str, handler: MessageHandler) {
+        self.start_consumer(topic, group_id.to_string(), move |key, message| {
+            handler(key, message)
+        })
+        .await;
+    }
+}
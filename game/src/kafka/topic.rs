@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{collections::HashMap, fmt};
 
-use crate::workflow::service::WorkflowResource;
+use crate::workflow::{server_action::ServerActionResult, service::WorkflowResource};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum WorkflowTopicMessage {
@@ -12,22 +12,122 @@ pub enum WorkflowTopicMessage {
     Updated {
         workflow: WorkflowResource,
     },
+    Cancelled {
+        workflow: WorkflowResource,
+    },
+    Reminder {
+        workflow: WorkflowResource,
+        remaining_seconds: u64,
+    },
     ServerActionRequest {
         id: String,
         workflow: WorkflowResource,
         action_id: String,
     },
+    ServerActionResponse {
+        id: String,
+        user_id: String,
+        result: ServerActionResult,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GameTopicMessage {
+    GameCreated {
+        game_id: String,
+        player_ids: Vec<String>,
+    },
+    TurnStarted {
+        game_id: String,
+        player_id: String,
+        role: String,
+    },
+    TurnExpired {
+        game_id: String,
+        player_id: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum VoteTopicMessage {
+    VoteResult {
+        game_id: String,
+        votes: HashMap<String, String>,
+        eliminated: Vec<String>,
+    },
+}
+
+/// A message that could not be decoded, kept verbatim for inspection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetterMessage {
+    pub source_topic: String,
+    pub key: Option<String>,
+    pub payload: String,
+    pub error: String,
+}
+
+/// Any message that can travel on one of the [`KafkaTopic`]s. Serialized
+/// without a wrapper, the topic tells consumers which payload to expect.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TopicMessage {
+    Workflow(Box<WorkflowTopicMessage>),
+    Game(GameTopicMessage),
+    Vote(VoteTopicMessage),
+    DeadLetter(DeadLetterMessage),
+}
+
+impl TopicMessage {
+    pub fn decode(topic: KafkaTopic, value: Value) -> Result<TopicMessage, serde_json::Error> {
+        match topic {
+            KafkaTopic::Workflows => serde_json::from_value(value)
+                .map(|message| TopicMessage::Workflow(Box::new(message))),
+            KafkaTopic::GameLifecycle => serde_json::from_value(value).map(TopicMessage::Game),
+            KafkaTopic::VoteResults => serde_json::from_value(value).map(TopicMessage::Vote),
+            KafkaTopic::DeadLetters => serde_json::from_value(value).map(TopicMessage::DeadLetter),
+        }
+    }
+}
+
+impl From<WorkflowTopicMessage> for TopicMessage {
+    fn from(value: WorkflowTopicMessage) -> Self {
+        TopicMessage::Workflow(Box::new(value))
+    }
+}
+
+impl From<GameTopicMessage> for TopicMessage {
+    fn from(value: GameTopicMessage) -> Self {
+        TopicMessage::Game(value)
+    }
+}
+
+impl From<VoteTopicMessage> for TopicMessage {
+    fn from(value: VoteTopicMessage) -> Self {
+        TopicMessage::Vote(value)
+    }
+}
+
+impl From<DeadLetterMessage> for TopicMessage {
+    fn from(value: DeadLetterMessage) -> Self {
+        TopicMessage::DeadLetter(value)
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum KafkaTopic {
     Workflows,
+    GameLifecycle,
+    VoteResults,
+    DeadLetters,
 }
 
 impl KafkaTopic {
     pub fn topic_name(&self) -> &'static str {
         match self {
             KafkaTopic::Workflows => "workflows",
+            KafkaTopic::GameLifecycle => "game-lifecycle",
+            KafkaTopic::VoteResults => "vote-results",
+            KafkaTopic::DeadLetters => "dead-letters",
         }
     }
 }
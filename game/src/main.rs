@@ -10,11 +10,16 @@ use crate::{
     workflow::InputType,
 };
 
+pub mod api;
 pub mod error;
+pub mod gamelog;
 pub mod gamerunner;
 pub mod gamestate;
 mod kafka;
+pub mod replay;
 pub mod roles;
+pub mod snapshot;
+pub mod summary;
 pub mod workflow;
 
 use std::collections::HashMap;
@@ -23,13 +28,55 @@ use serde_json::json;
 use tokio::sync::broadcast;
 
 use crate::{
+    api::ApiState,
+    gamelog::GameLog,
     gamerunner::{GameEvent, GameRunner},
-    kafka::service::KafkaService,
-    workflow::service::ProcessWorkflowActionArgs,
+    kafka::{bridge::Bridge, bus::EventBus, memory::InMemoryBus, service::KafkaService},
+    workflow::{service::ProcessWorkflowActionArgs, store::sqlite::SqliteStore},
 };
 
 #[tokio::main]
 async fn main() {
+    let event_bus: Option<Arc<dyn EventBus>> = match std::env::var("MIDNIGHT_EVENT_BUS").as_deref()
+    {
+        Ok("memory") => Some(Arc::new(InMemoryBus::new())),
+        _ => std::env::var("KAFKA_BROKERS")
+            .ok()
+            .map(|brokers| Arc::new(KafkaService::new(&brokers)) as Arc<dyn EventBus>),
+    };
+
+    // Give servers a stable id so they pick up where their consumer group left
+    // off after a restart.
+    let server_id =
+        std::env::var("MIDNIGHT_SERVER_ID").unwrap_or_else(|_| ulid::Ulid::new().to_string());
+
+    if let Ok(path) = std::env::var("MIDNIGHT_REPLAY") {
+        replay::print_replay(&path)
+            .await
+            .expect("Failed to replay game log");
+        return;
+    }
+
+    if let Ok(dir) = std::env::var("MIDNIGHT_DUMP_WORKFLOWS") {
+        for definition in roles::workflow_definitions() {
+            let path = std::path::Path::new(&dir).join(format!("{}.json", definition.id));
+            std::fs::write(&path, definition.to_json())
+                .expect("Failed to write workflow definition");
+        }
+        return;
+    }
+
+    if let Ok(addr) = std::env::var("MIDNIGHT_HTTP_ADDR") {
+        let mut api_state = ApiState::new();
+        if let Some(event_bus) = event_bus {
+            api_state = api_state.with_event_bus(event_bus, &server_id).await;
+        }
+        api::serve(&addr, api_state)
+            .await
+            .expect("HTTP API server failed");
+        return;
+    }
+
     let mut seer = seer_card();
     let mut dopple = doppelganger_card();
     let mut witch = witch_card();
@@ -48,9 +95,31 @@ async fn main() {
         Player::new("middle3", "middle 3", Arc::new(villager1.clone()), Some(2)),
     ];
 
-    let state = GameState::new(players).await;
+    let player_ids = players.iter().map(|p| p.id.clone()).collect();
+    let log = match std::env::var("MIDNIGHT_GAME_LOG") {
+        Ok(path) => GameLog::to_file(&path).expect("Failed to open game log"),
+        Err(_) => GameLog::new(),
+    };
     let (tx, mut rx) = broadcast::channel(16);
-    let runner = GameRunner::new(state, tx.clone()).await;
+    // A game kept in SQLite carries on where the last run left it.
+    let runner = match std::env::var("MIDNIGHT_SQLITE_PATH") {
+        Ok(path) => {
+            let store = SqliteStore::open(&path, "demo").expect("Failed to open SQLite store");
+            GameRunner::open(players, Arc::new(store), log, tx.clone())
+                .await
+                .expect("Failed to open the game")
+        }
+        Err(_) => GameRunner::new(GameState::new(players).await.with_log(log), tx.clone()).await,
+    };
+    if let Some(event_bus) = event_bus {
+        let workflow = runner.lock().await.game.lock().await.workflow.clone();
+        let bridge = Bridge::new(event_bus, &server_id).await;
+        bridge.attach(workflow, "demo").await;
+        bridge.attach_game("demo", player_ids, &tx).await;
+    }
+    GameRunner::resume(&runner)
+        .await
+        .expect("Failed to resume the game");
     let runner_inner = runner.clone();
 
     tokio::spawn(async move {
@@ -160,7 +229,7 @@ async fn main() {
                                 let mut input = HashMap::new();
                                 input.insert(
                                     "selected_card".to_string(),
-                                    json!({"type": "Player", "Player": {"id": "seer"}}),
+                                    json!({"type": "Player", "Player": {"id": "werewolf"}}),
                                 );
                                 ProcessWorkflowActionArgs::new(
                                     workflow.instance_id.clone(),
@@ -193,10 +262,20 @@ async fn main() {
                     }
                 }
 
+                GameEvent::GameResult { summary } => {
+                    println!("What really happened:");
+                    for line in summary.text {
+                        println!("  {line}");
+                    }
+                }
+
                 _ => {}
             }
         }
     });
 
     GameRunner::run(runner.clone()).await;
+
+    // Give the listener a moment to print the game result.
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
 }
@@ -0,0 +1,509 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::{
+    error::{AppResult, ServicesError},
+    gamelog::{GameLog, GameLogEntry, GameLogEvent},
+    gamerunner::GameRunner,
+    gamestate::{GameState, RoleContext},
+    snapshot::{PlayerSnapshot, role_card},
+    workflow::{server_action::ServerActionResult, service::ProcessWorkflowActionArgs},
+};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReplayedWorkflow {
+    pub workflow_id: String,
+    pub user_id: String,
+    pub responses: HashMap<String, Value>,
+    pub actions: Vec<String>,
+    pub server_actions: Vec<(String, ServerActionResult)>,
+    pub completed: bool,
+}
+
+/// A game as rebuilt from its log up to the last applied entry.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReplayState {
+    pub seed: Option<u64>,
+    pub players: BTreeMap<String, PlayerSnapshot>,
+    pub current_turn: Option<(String, String)>,
+    pub workflows: BTreeMap<String, ReplayedWorkflow>,
+    pub votes: Vec<HashMap<String, String>>,
+}
+
+/// Walks a game log entry by entry, keeping a [`ReplayState`] in sync.
+pub struct Replay {
+    entries: Vec<GameLogEntry>,
+    position: usize,
+    state: ReplayState,
+}
+
+impl Replay {
+    pub fn new(entries: Vec<GameLogEntry>) -> Self {
+        Self {
+            entries,
+            position: 0,
+            state: ReplayState::default(),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> AppResult<Self> {
+        Ok(Self::new(GameLog::read(path)?))
+    }
+
+    pub fn state(&self) -> &ReplayState {
+        &self.state
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Apply the next entry and return it, or `None` once the log is exhausted.
+    pub fn step(&mut self) -> Option<&GameLogEntry> {
+        let entry = self.entries.get(self.position)?;
+        self.position += 1;
+        Self::apply(&mut self.state, &entry.event);
+        Some(entry)
+    }
+
+    pub fn run_to_end(&mut self) -> &ReplayState {
+        while self.step().is_some() {}
+        &self.state
+    }
+
+    fn apply(state: &mut ReplayState, event: &GameLogEvent) {
+        match event {
+            GameLogEvent::Dealt { players, seed } => {
+                state.seed = Some(*seed);
+                state.players = players
+                    .iter()
+                    .map(|player| (player.id.clone(), player.clone()))
+                    .collect();
+            }
+            GameLogEvent::TurnStarted { player_id, role } => {
+                state.current_turn = Some((player_id.clone(), role.clone()));
+            }
+            GameLogEvent::WorkflowStarted {
+                instance_id,
+                workflow_id,
+                user_id,
+                inputs,
+            } => {
+                let workflow = state.workflows.entry(instance_id.clone()).or_default();
+                workflow.workflow_id = workflow_id.clone();
+                workflow.user_id = user_id.clone();
+                workflow.responses.extend(inputs.clone());
+            }
+            GameLogEvent::ActionProcessed {
+                instance_id,
+                user_id,
+                action_id,
+                inputs,
+            } => {
+                let workflow = state.workflows.entry(instance_id.clone()).or_default();
+                workflow.user_id.clone_from(user_id);
+                workflow.actions.push(action_id.clone());
+                workflow.responses.extend(inputs.clone());
+            }
+            GameLogEvent::ServerActionCompleted {
+                instance_id,
+                action_id,
+                result,
+            } => {
+                let workflow = state.workflows.entry(instance_id.clone()).or_default();
+                match result {
+                    ServerActionResult::UpdateResponses(responses) => {
+                        workflow.responses.extend(responses.clone());
+                    }
+                    ServerActionResult::CompleteWorkflow { responses, .. } => {
+                        workflow.responses.extend(responses.clone());
+                        workflow.completed = true;
+                    }
+                    ServerActionResult::CancelWorkflow => workflow.completed = true,
+                    _ => {}
+                }
+                workflow
+                    .server_actions
+                    .push((action_id.clone(), result.clone()));
+            }
+            GameLogEvent::CardsSwapped {
+                first_player_id,
+                second_player_id,
+            } => {
+                let first = state.players.get(first_player_id).map(|p| p.role.clone());
+                let second = state.players.get(second_player_id).map(|p| p.role.clone());
+                if let (Some(first), Some(second)) = (first, second) {
+                    if let Some(player) = state.players.get_mut(first_player_id) {
+                        player.role = second;
+                    }
+                    if let Some(player) = state.players.get_mut(second_player_id) {
+                        player.role = first;
+                    }
+                }
+            }
+            GameLogEvent::VotesCast { votes, .. } => state.votes.push(votes.clone()),
+            GameLogEvent::PlayerDied { player_id } => {
+                if let Some(player) = state.players.get_mut(player_id) {
+                    player.is_alive = false;
+                }
+            }
+        }
+    }
+}
+
+/// An entry the game played again did not reproduce. Either side is missing
+/// when one game logged more than the other.
+#[derive(Debug, Clone, Serialize)]
+pub struct Divergence {
+    pub sequence: u64,
+    pub logged: Option<GameLogEvent>,
+    pub replayed: Option<GameLogEvent>,
+}
+
+/// How long a replayed step may take to settle before the next one is fed in.
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Play the game in `entries` again: deal the same cards from the same seed,
+/// take the same turns and submit the same actions and votes. Everything the
+/// game worked out by itself, such as what a server action revealed, is then
+/// compared with what was logged.
+pub async fn rerun(entries: &[GameLogEntry]) -> AppResult<Vec<Divergence>> {
+    let Some(GameLogEvent::Dealt { players, seed }) = entries.first().map(|entry| &entry.event)
+    else {
+        return Err(ServicesError::InternalError(
+            "a game log must start with the deal".to_string(),
+        ));
+    };
+    let players = players
+        .iter()
+        .cloned()
+        .map(PlayerSnapshot::into_player)
+        .collect::<AppResult<Vec<_>>>()?;
+
+    let game = GameState::new(players).await.with_seed(*seed);
+    let log = game.log.clone();
+    let (events, _) = broadcast::channel(16);
+    let runner = GameRunner::new(game, events).await;
+    let (game, workflow) = {
+        let runner = runner.lock().await;
+        runner.register_cards().await;
+        let workflow = runner.game.lock().await.workflow.clone();
+        (runner.game.clone(), workflow)
+    };
+
+    let logged_instances = instance_ids(entries);
+    for (index, entry) in entries.iter().enumerate().skip(1) {
+        match &entry.event {
+            GameLogEvent::TurnStarted { player_id, role } => {
+                let ctx = RoleContext::new(game.clone(), player_id.clone());
+                game.lock()
+                    .await
+                    .set_context(player_id.clone(), ctx.clone())
+                    .await;
+                GameRunner::start_turn(&runner, ctx, &role_card(role)?).await;
+            }
+            GameLogEvent::ActionProcessed {
+                instance_id,
+                user_id,
+                action_id,
+                inputs,
+            } => {
+                // Instances are numbered afresh, so find the one started in
+                // the same place.
+                let replayed = logged_instances
+                    .iter()
+                    .position(|id| id == instance_id)
+                    .and_then(|position| instance_ids(&log.entries()).get(position).cloned());
+                let Some(replayed) = replayed else {
+                    continue;
+                };
+                let args =
+                    ProcessWorkflowActionArgs::new(replayed, action_id.clone(), inputs.clone());
+                if let Err(e) = workflow.process_action(user_id, args).await {
+                    eprintln!("Replaying entry {} failed: {}", entry.sequence, e);
+                }
+            }
+            GameLogEvent::VotesCast { votes, .. } => {
+                if let Err(e) = runner.lock().await.record_votes(votes.clone()).await {
+                    eprintln!("Replaying entry {} failed: {}", entry.sequence, e);
+                }
+            }
+            // Everything else follows from the deal, the turns, the actions
+            // and the votes.
+            _ => continue,
+        }
+
+        // Let what the step set off run its course before taking the next one.
+        let settled = entries[index + 1..]
+            .iter()
+            .position(|entry| is_input(&entry.event))
+            .map_or(entries.len(), |next| index + 1 + next);
+        let deadline = tokio::time::Instant::now() + SETTLE_TIMEOUT;
+        while log.entries().len() < settled && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    let replayed = log.entries();
+    let replayed_instances = instance_ids(&replayed);
+    let divergences = (0..entries.len().max(replayed.len()))
+        .filter_map(|index| {
+            let logged = entries.get(index);
+            let replayed = replayed.get(index);
+            let same = match (logged, replayed) {
+                (Some(logged), Some(replayed)) => {
+                    comparable(&logged.event, &logged_instances)
+                        == comparable(&replayed.event, &replayed_instances)
+                }
+                _ => false,
+            };
+            (!same).then(|| Divergence {
+                sequence: index as u64,
+                logged: logged.map(|entry| entry.event.clone()),
+                replayed: replayed.map(|entry| entry.event.clone()),
+            })
+        })
+        .collect();
+
+    Ok(divergences)
+}
+
+/// Whether `event` came from outside the game rather than from the game
+/// itself.
+fn is_input(event: &GameLogEvent) -> bool {
+    matches!(
+        event,
+        GameLogEvent::TurnStarted { .. }
+            | GameLogEvent::ActionProcessed { .. }
+            | GameLogEvent::VotesCast { .. }
+    )
+}
+
+/// Workflow instances in the order they were started.
+fn instance_ids(entries: &[GameLogEntry]) -> Vec<String> {
+    entries
+        .iter()
+        .filter_map(|entry| match &entry.event {
+            GameLogEvent::WorkflowStarted { instance_id, .. } => Some(instance_id.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `event` with every instance id replaced by the order it was started in,
+/// so events of two runs of a game can be compared.
+fn comparable(event: &GameLogEvent, instances: &[String]) -> Value {
+    fn replace(value: &mut Value, instances: &[String]) {
+        match value {
+            Value::String(text) => {
+                if let Some(position) = instances.iter().position(|id| id == text) {
+                    *value = Value::String(format!("#{position}"));
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| replace(item, instances)),
+            Value::Object(object) => object
+                .values_mut()
+                .for_each(|item| replace(item, instances)),
+            _ => {}
+        }
+    }
+
+    let mut value = serde_json::to_value(event).unwrap_or_default();
+    replace(&mut value, instances);
+    value
+}
+
+/// Print every step of the log at `path` along with the rebuilt game, then
+/// play the game again and print whatever came out differently.
+pub async fn print_replay(path: impl AsRef<Path>) -> AppResult<()> {
+    let mut replay = Replay::from_file(path)?;
+    println!("Replaying {} game log entries", replay.len());
+
+    while let Some(entry) = replay.step() {
+        println!(
+            "#{} {} {}",
+            entry.sequence,
+            entry.timestamp.to_rfc3339(),
+            serde_json::to_string(&entry.event).unwrap_or_default()
+        );
+    }
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(replay.state()).unwrap_or_default()
+    );
+
+    let divergences = rerun(&replay.entries).await?;
+    if divergences.is_empty() {
+        println!("Playing the game again gave the same outcome");
+    }
+    for divergence in divergences {
+        println!(
+            "#{} logged {} but replayed {}",
+            divergence.sequence,
+            serde_json::to_string(&divergence.logged).unwrap_or_default(),
+            serde_json::to_string(&divergence.replayed).unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use serde_json::json;
+    use tokio::sync::broadcast;
+
+    use super::{Replay, rerun};
+    use crate::gamelog::{GameLogEntry, GameLogEvent};
+    use crate::gamerunner::GameRunner;
+    use crate::gamestate::{GameState, Player, RoleContext};
+    use crate::roles::seer::seer_card;
+    use crate::roles::villager_card;
+    use crate::roles::werewolf::werewolf_card;
+    use crate::workflow::server_action::ServerActionResult;
+    use crate::workflow::service::ProcessWorkflowActionArgs;
+
+    /// Play the seer's turn of a game where the seer looks at Vince's card.
+    async fn seer_looks_at_vince() -> Vec<GameLogEntry> {
+        let game = GameState::new(vec![
+            Player::new("seer", "Sam", Arc::new(seer_card()), None),
+            Player::new("vince", "Vince", Arc::new(werewolf_card()), None),
+            Player::new("middle1", "middle 1", Arc::new(villager_card()), Some(0)),
+        ])
+        .await;
+        let log = game.log.clone();
+        let (events, _) = broadcast::channel(16);
+        let runner = GameRunner::new(game, events).await;
+        let game = {
+            let runner = runner.lock().await;
+            runner.register_cards().await;
+            runner.game.clone()
+        };
+
+        let ctx = RoleContext::new(game.clone(), "seer");
+        GameRunner::start_turn(&runner, ctx, &seer_card()).await;
+        let workflow = game.lock().await.workflow.clone();
+        let instance_id = workflow
+            .manager
+            .list_user_workflow_resources("seer")
+            .await
+            .remove(0)
+            .instance_id;
+
+        let card = json!({ "type": "Player", "Player": { "id": "vince" } });
+        for (action_id, inputs) in [
+            ("next", HashMap::from([("selected_card".to_string(), card)])),
+            ("next", HashMap::new()),
+        ] {
+            let args =
+                ProcessWorkflowActionArgs::new(instance_id.clone(), action_id.to_string(), inputs);
+            workflow.process_action("seer", args).await.unwrap();
+        }
+        log.entries()
+    }
+
+    #[tokio::test]
+    async fn stepping_follows_swaps_votes_and_deaths() {
+        let entries = seer_looks_at_vince().await;
+        let mut events: Vec<GameLogEvent> = vec![entries[0].event.clone()];
+        events.extend([
+            GameLogEvent::CardsSwapped {
+                first_player_id: "seer".to_string(),
+                second_player_id: "vince".to_string(),
+            },
+            GameLogEvent::VotesCast {
+                votes: HashMap::from([
+                    ("seer".to_string(), "vince".to_string()),
+                    ("vince".to_string(), "seer".to_string()),
+                ]),
+                eliminated: vec![],
+            },
+            GameLogEvent::PlayerDied {
+                player_id: "vince".to_string(),
+            },
+        ]);
+        let entries = events
+            .into_iter()
+            .enumerate()
+            .map(|(sequence, event)| GameLogEntry {
+                sequence: sequence as u64,
+                timestamp: chrono::Utc::now(),
+                event,
+            })
+            .collect();
+
+        let mut replay = Replay::new(entries);
+        let state = replay.run_to_end();
+        assert_eq!(state.players["seer"].role, "Werewolf");
+        assert_eq!(state.players["vince"].role, "Seer");
+        assert!(state.players["seer"].is_alive);
+        assert!(!state.players["vince"].is_alive);
+        assert_eq!(state.votes.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn playing_a_logged_game_again_gives_the_same_outcome() {
+        let entries = seer_looks_at_vince().await;
+        assert!(
+            entries.iter().any(|entry| matches!(
+                &entry.event,
+                GameLogEvent::ServerActionCompleted { action_id, .. } if action_id == "reveal_player"
+            )),
+            "the seer never looked: {entries:?}"
+        );
+
+        let divergences = rerun(&entries).await.unwrap();
+        assert!(divergences.is_empty(), "{divergences:?}");
+    }
+
+    #[tokio::test]
+    async fn replaying_points_at_what_the_log_got_wrong() {
+        let mut entries = seer_looks_at_vince().await;
+        // Claim the seer saw a villager.
+        let reveal = entries
+            .iter_mut()
+            .find(|entry| {
+                matches!(
+                    &entry.event,
+                    GameLogEvent::ServerActionCompleted { action_id, .. } if action_id == "reveal_player"
+                )
+            })
+            .unwrap();
+        let GameLogEvent::ServerActionCompleted { result, .. } = &mut reveal.event else {
+            unreachable!();
+        };
+        *result = ServerActionResult::UpdateResponses(HashMap::from([(
+            "reveal_player".to_string(),
+            json!([{ "name": "Vince", "card": { "name": "Villager" } }]),
+        )]));
+        let sequence = reveal.sequence;
+
+        let divergences = rerun(&entries).await.unwrap();
+        assert_eq!(divergences.len(), 1, "{divergences:?}");
+        assert_eq!(divergences[0].sequence, sequence);
+        let Some(GameLogEvent::ServerActionCompleted {
+            result: ServerActionResult::UpdateResponses(responses),
+            ..
+        }) = &divergences[0].replayed
+        else {
+            panic!("expected the reveal, got {:?}", divergences[0].replayed);
+        };
+        assert_eq!(responses["reveal_player"][0]["card"]["name"], "Werewolf");
+    }
+}
@@ -6,12 +6,25 @@ use futures::lock::Mutex;
 use serde::{Deserialize, Serialize};
 
 use crate::gamestate::{GameState, RoleContext};
+use crate::workflow::CreateWorkflowDefinition;
+use registry::RoleRegistry;
 
+pub mod registry;
 pub mod seer;
 pub mod spy;
 pub mod werewolf;
 pub mod witch;
 
+/// Every workflow definition the roles register.
+pub fn workflow_definitions() -> Vec<CreateWorkflowDefinition> {
+    vec![
+        seer::seer_workflow(),
+        spy::spy_workflow(),
+        werewolf::werewolf_workflow(),
+        witch::sabotage_workflow(),
+    ]
+}
+
 pub struct WorkflowDefinitionWithInput {
     pub definition: String,
     pub input: HashMap<String, serde_json::Value>,
@@ -72,15 +85,15 @@ impl std::fmt::Debug for RoleAbilitySpec {
     }
 }
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Clone, Serialize)]
 pub struct RoleCard {
     pub name: String,
     pub alliance: Alliance,
     pub priority: i32,
 
-    #[serde(skip_serializing, skip_deserializing)]
+    #[serde(skip_serializing)]
     pub night_ability: Option<RoleAbility>,
-    #[serde(skip_serializing, skip_deserializing)]
+    #[serde(skip_serializing)]
     pub register: Option<
         Arc<
             dyn Fn(Arc<Mutex<GameState>>) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync,
@@ -88,6 +101,33 @@ pub struct RoleCard {
     >,
 }
 
+/// A card is deserialized from its name, or from an object carrying one, and
+/// rebuilt through the [`RoleRegistry`] so it keeps its abilities.
+impl<'de> Deserialize<'de> for RoleCard {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct NamedCard {
+            name: String,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum SerializedCard {
+            Name(String),
+            Card(NamedCard),
+        }
+
+        let name = match SerializedCard::deserialize(deserializer)? {
+            SerializedCard::Name(name) => name,
+            SerializedCard::Card(card) => card.name,
+        };
+
+        RoleRegistry::global()
+            .get(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown role {name}")))
+    }
+}
+
 impl std::fmt::Debug for RoleCard {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("RoleCard")
@@ -122,3 +162,171 @@ pub fn doppelganger_card() -> RoleCard {
         night_ability: Some(Arc::new(|ctx: RoleContext| Box::pin(async move { None }))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use std::collections::HashMap;
+
+    use super::seer::seer_workflow;
+    use super::workflow_definitions;
+    use crate::workflow::manager::{ActionProcessResult, WorkflowManager};
+    use crate::workflow::server_action::ServerActionResult;
+    use crate::workflow::{CreateWorkflowDefinition, NodeCondition, ResponseVisibility};
+
+    #[tokio::test]
+    async fn secret_responses_stay_with_their_owner() {
+        for definition in workflow_definitions() {
+            let manager = WorkflowManager::new();
+            for action_id in definition.server_actions.keys() {
+                manager
+                    .register_server_action(
+                        action_id,
+                        Box::new(|_| Box::pin(async { Ok(ServerActionResult::CancelWorkflow) })),
+                    )
+                    .await
+                    .unwrap();
+            }
+
+            let secrets: Vec<String> = definition
+                .response_visibility
+                .iter()
+                .filter(|(_, visibility)| **visibility != ResponseVisibility::Public)
+                .map(|(key, _)| key.clone())
+                .collect();
+            assert!(
+                !secrets.is_empty(),
+                "{} has no secret responses",
+                definition.id
+            );
+
+            let definition_id = manager
+                .register_workflow_definition("bot", definition.clone())
+                .await
+                .unwrap();
+            let inputs = secrets
+                .iter()
+                .map(|key| (key.clone(), json!("secret")))
+                .collect();
+            let instance_id = manager
+                .start_workflow(&definition_id, "owner", inputs)
+                .await
+                .unwrap();
+            let resource = manager.get_workflow_resource(&instance_id).await.unwrap();
+
+            let owner_view = resource.for_recipient("owner");
+            let other_view = resource.for_recipient("other");
+            for key in &secrets {
+                assert!(
+                    owner_view.responses.contains_key(key),
+                    "{key} hidden from owner"
+                );
+                assert!(
+                    !other_view.responses.contains_key(key),
+                    "{} leaks {key} to other players",
+                    definition.id
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn templates_only_show_what_the_recipient_may_see() {
+        let manager = WorkflowManager::new();
+        manager
+            .register_server_action(
+                "reveal_player",
+                Box::new(|_| {
+                    Box::pin(async {
+                        Ok(ServerActionResult::UpdateResponses(HashMap::from([(
+                            "reveal_player".to_string(),
+                            json!([{ "name": "Vince", "role": "Werewolf" }]),
+                        )])))
+                    })
+                }),
+            )
+            .await
+            .unwrap();
+        manager
+            .register_server_action(
+                "reveal_cards",
+                Box::new(|_| Box::pin(async { Ok(ServerActionResult::CancelWorkflow) })),
+            )
+            .await
+            .unwrap();
+        let definition_id = manager
+            .register_workflow_definition("bot", seer_workflow())
+            .await
+            .unwrap();
+        let instance_id = manager
+            .start_workflow(&definition_id, "seer", HashMap::new())
+            .await
+            .unwrap();
+
+        let card = json!({ "type": "Player", "Player": { "id": "vince" } });
+        manager
+            .process_action(
+                instance_id.clone(),
+                "next",
+                HashMap::from([("selected_card".to_string(), card)]),
+            )
+            .await
+            .unwrap();
+        let (action, processed) = manager
+            .process_action(instance_id.clone(), "next", HashMap::new())
+            .await
+            .unwrap();
+        let ActionProcessResult::ServerActionStarted {
+            workflow_id,
+            action_id,
+        } = action
+        else {
+            panic!("expected the reveal to start, got {action:?}");
+        };
+        manager
+            .execute_server_action(
+                instance_id.clone(),
+                &workflow_id,
+                &action_id,
+                Some(processed),
+            )
+            .await
+            .unwrap();
+
+        let resource = manager.get_workflow_resource(&instance_id).await.unwrap();
+        assert_eq!(resource.current_node_id, "reveal_player_cards");
+        assert_eq!(resource.for_recipient("seer").name, "You saw Vince's card");
+        assert_eq!(resource.for_recipient("spy").name, "You saw 's card");
+    }
+
+    /// The JSON files the roles used to ship, kept to check the builder
+    /// against.
+    const HAND_WRITTEN: [&str; 4] = [
+        include_str!("seer/seer.json"),
+        include_str!("spy/spy.json"),
+        include_str!("werewolf/werewolf.json"),
+        include_str!("witch/sabotage.json"),
+    ];
+
+    /// `definition` as JSON, with the `Always` node conditions the files
+    /// spelled out left implicit, as the builder does. Both mean the same.
+    fn comparable(mut definition: CreateWorkflowDefinition) -> String {
+        for node in definition.nodes.values_mut() {
+            if matches!(node.condition, Some(NodeCondition::Always)) {
+                node.condition = None;
+            }
+        }
+        let value = serde_json::to_value(&definition).unwrap();
+        serde_json::to_string_pretty(&value).unwrap()
+    }
+
+    #[test]
+    fn built_definitions_match_the_hand_written_ones() {
+        for (built, written) in workflow_definitions().into_iter().zip(HAND_WRITTEN) {
+            let id = built.id.clone();
+            let written: CreateWorkflowDefinition = serde_json::from_str(written).unwrap();
+            assert_eq!(comparable(built), comparable(written), "{id}");
+        }
+    }
+}
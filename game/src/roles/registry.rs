@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::error::{AppResult, ServicesError};
+
+use super::{
+    RoleCard, doppelganger_card, seer::seer_card, spy::spy_card, villager_card,
+    werewolf::werewolf_card, witch::witch_card,
+};
+
+pub type RoleFactory = fn() -> RoleCard;
+
+/// Maps role names and ids to the functions building their cards, so a card
+/// known only by name gets its abilities back.
+#[derive(Default)]
+pub struct RoleRegistry {
+    factories: HashMap<String, RoleFactory>,
+    ids: HashMap<String, String>,
+}
+
+impl RoleRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The registry with every role shipped with the game.
+    pub fn global() -> &'static RoleRegistry {
+        static REGISTRY: OnceLock<RoleRegistry> = OnceLock::new();
+        REGISTRY.get_or_init(|| {
+            let mut registry = RoleRegistry::new();
+            registry.register("villager", villager_card);
+            registry.register("doppelganger", doppelganger_card);
+            registry.register("seer", seer_card);
+            registry.register("spy", spy_card);
+            registry.register("werewolf", werewolf_card);
+            registry.register("witch", witch_card);
+            registry
+        })
+    }
+
+    /// Register `factory` under the name of the card it builds and under `id`.
+    pub fn register(&mut self, id: &str, factory: RoleFactory) {
+        let name = factory().name;
+        self.ids.insert(id.to_lowercase(), name.clone());
+        self.factories.insert(name, factory);
+    }
+
+    /// Canonical card name for a role name or id.
+    pub fn resolve_name(&self, name_or_id: &str) -> Option<&str> {
+        if let Some((name, _)) = self.factories.get_key_value(name_or_id) {
+            return Some(name);
+        }
+        self.ids.get(&name_or_id.to_lowercase()).map(String::as_str)
+    }
+
+    pub fn get(&self, name_or_id: &str) -> Option<RoleCard> {
+        let name = self.resolve_name(name_or_id)?;
+        self.factories.get(name).map(|factory| factory())
+    }
+
+    pub fn create(&self, name_or_id: &str) -> AppResult<RoleCard> {
+        self.get(name_or_id)
+            .ok_or(ServicesError::NotFound(format!("role {name_or_id}")))
+    }
+
+    /// Build one card per entry of `roles`, in order.
+    pub fn deck(&self, roles: &[String]) -> AppResult<Vec<RoleCard>> {
+        roles.iter().map(|role| self.create(role)).collect()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.factories.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RoleRegistry;
+    use crate::error::ServicesError;
+
+    #[test]
+    fn roles_resolve_by_name_or_id() {
+        let registry = RoleRegistry::global();
+        assert_eq!(registry.resolve_name("Seer"), Some("Seer"));
+        assert_eq!(registry.resolve_name("seer"), Some("Seer"));
+        assert_eq!(registry.resolve_name("DOPPELGANGER"), Some("Doppelgänger"));
+        assert_eq!(registry.resolve_name("Doppelgänger"), Some("Doppelgänger"));
+        assert_eq!(registry.resolve_name("tanner"), None);
+    }
+
+    #[test]
+    fn created_cards_get_their_abilities_back() {
+        let registry = RoleRegistry::global();
+        let seer = registry.create("seer").unwrap();
+        assert_eq!(seer.name, "Seer");
+        assert!(seer.night_ability.is_some());
+        assert!(registry.create("villager").unwrap().night_ability.is_none());
+    }
+
+    #[test]
+    fn decks_keep_their_order_and_refuse_unknown_roles() {
+        let registry = RoleRegistry::global();
+        let roles = ["werewolf", "Seer", "villager"].map(String::from);
+        let names: Vec<String> = registry
+            .deck(&roles)
+            .unwrap()
+            .into_iter()
+            .map(|card| card.name)
+            .collect();
+        assert_eq!(names, ["Werewolf", "Seer", "Villager"]);
+
+        let roles = ["seer", "tanner"].map(String::from);
+        assert!(matches!(
+            registry.deck(&roles),
+            Err(ServicesError::NotFound(message)) if message == "role tanner"
+        ));
+    }
+}
@@ -5,22 +5,148 @@ use std::{collections::HashMap, future::Future};
 use futures::lock::Mutex;
 use serde_json::json;
 
+use crate::gamestate::{GameState, RoleContext};
 use crate::roles::{Alliance, RoleCard, WorkflowDefinitionWithInput};
+use crate::workflow::builder::NodeId;
 use crate::workflow::server_action::{ServerActionContext, ServerActionResult};
-use crate::{
-    gamestate::{GameState, RoleContext},
-    workflow::CreateWorkflowDefinition,
+use crate::workflow::{
+    CardFilter, CreateWorkflowDefinition, DisplayType, NodeCondition, ResponseVisibility,
+    WorkflowAction, WorkflowInput, WorkflowNode,
 };
 
-async fn register_seer_workflow_definition(game: Arc<Mutex<GameState>>) {
-    let workflow: CreateWorkflowDefinition = serde_json::from_str(include_str!("./seer.json"))
-        .expect("Failed to parse seer.json workflow definition");
+const SELECT_CARD_NODE: NodeId = NodeId("select_card_node");
+const PROMPT_PLAYER_REVEAL: NodeId = NodeId("prompt_player_reveal");
+const PROMPT_ANOTHER_MIDDLE_NODE: NodeId = NodeId("prompt_another_middle_node");
+const REVEAL_PLAYER_CARDS: NodeId = NodeId("reveal_player_cards");
+const REVEAL_MIDDLE_CARDS: NodeId = NodeId("reveal_middle_cards");
+
+pub fn seer_workflow() -> CreateWorkflowDefinition {
+    let selected_card = || DisplayType::SelectCards {
+        card_id_keys: vec!["selected_card".to_string()],
+    };
+
+    CreateWorkflowDefinition::new("seer_ability_workflow", "Seer Ability")
+        .with_description("Workflow for Seer to inspect a card")
+        .node(
+            WorkflowNode::new(SELECT_CARD_NODE, "Select a Card")
+                .with_description("Choose a player or middle card to inspect")
+                .input(
+                    WorkflowInput::select_card(
+                        "selected_card",
+                        "Which card do you want to inspect?",
+                        CardFilter::PlayerOrMiddle { allow_self: false },
+                    )
+                    .with_width("full"),
+                )
+                .action(WorkflowAction::next_node("next", "Continue").with_style("primary"))
+                .transition_if(
+                    PROMPT_PLAYER_REVEAL,
+                    NodeCondition::ResponseEquals {
+                        field: "selected_card.type".to_string(),
+                        value: json!("Player"),
+                    },
+                )
+                .transition_if(
+                    PROMPT_ANOTHER_MIDDLE_NODE,
+                    NodeCondition::ResponseEquals {
+                        field: "selected_card.type".to_string(),
+                        value: json!("Middle"),
+                    },
+                )
+                .transition(SELECT_CARD_NODE),
+        )
+        .node(
+            WorkflowNode::new(
+                PROMPT_PLAYER_REVEAL,
+                "Do you want to reveal this player's role?",
+            )
+            .with_parent(SELECT_CARD_NODE)
+            .display("selected_card", selected_card())
+            .action(
+                WorkflowAction::run_server_action("next", "Reveal Cards", "reveal_player")
+                    .with_style("primary"),
+            )
+            .transition_if(
+                REVEAL_PLAYER_CARDS,
+                NodeCondition::ResponseExists("reveal_player".to_string()),
+            )
+            .transition(PROMPT_PLAYER_REVEAL),
+        )
+        .node(
+            WorkflowNode::new(
+                PROMPT_ANOTHER_MIDDLE_NODE,
+                "Please chose another middle card.",
+            )
+            .with_parent(SELECT_CARD_NODE)
+            .display("selected_card", selected_card())
+            .input(
+                WorkflowInput::select_card(
+                    "selected_card_2",
+                    "Pick another middle card",
+                    CardFilter::MiddleOnly,
+                )
+                .with_width("full"),
+            )
+            .action(
+                WorkflowAction::run_server_action("next", "Reveal Cards", "reveal_cards")
+                    .with_style("primary"),
+            )
+            .transition_if(
+                REVEAL_MIDDLE_CARDS,
+                NodeCondition::ResponseExists("reveal_middle_one".to_string()),
+            )
+            .transition(PROMPT_ANOTHER_MIDDLE_NODE),
+        )
+        .node(
+            WorkflowNode::new(
+                REVEAL_PLAYER_CARDS,
+                "You saw {{reveal_player.0.name}}'s card",
+            )
+            .with_parent(PROMPT_PLAYER_REVEAL)
+            .display(
+                "reveal_cards",
+                DisplayType::RevealCards {
+                    reveal_card_keys: vec!["reveal_player".to_string()],
+                },
+            ),
+        )
+        .node(
+            WorkflowNode::new(REVEAL_MIDDLE_CARDS, "Middle Cards Revealed")
+                .with_parent(PROMPT_ANOTHER_MIDDLE_NODE)
+                .display(
+                    "reveal_cards",
+                    DisplayType::RevealCards {
+                        reveal_card_keys: vec![
+                            "reveal_middle_one".to_string(),
+                            "reveal_middle_two".to_string(),
+                        ],
+                    },
+                ),
+        )
+        // Which cards the seer picked and what was on them stays with the seer.
+        .response_visibility("selected_card", ResponseVisibility::Owner)
+        .response_visibility("selected_card_2", ResponseVisibility::Owner)
+        .response_visibility("reveal_player", ResponseVisibility::Owner)
+        .response_visibility("reveal_middle_one", ResponseVisibility::Owner)
+        .response_visibility("reveal_middle_two", ResponseVisibility::Owner)
+        .server_action(
+            "reveal_player",
+            "Reveal Player Role",
+            "Resolves the selected player's role",
+        )
+        .server_action(
+            "reveal_cards",
+            "Reveal Middle Cards",
+            "Resolves two middle cards",
+        )
+}
 
+async fn register_seer_workflow_definition(game: Arc<Mutex<GameState>>) {
     game.lock()
         .await
-        .register_workflow_definition(workflow)
+        .register_workflow_definition(seer_workflow())
         .await
-        .expect("Failed to register seer.json workflow");
+        .expect("Failed to register seer workflow");
 }
 
 async fn register_reveal_player_action(game: Arc<Mutex<GameState>>) {
@@ -1,19 +1,25 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::{collections::HashMap, future::Future};
 
 use futures::lock::Mutex;
 use serde_json::json;
 
 use crate::error::ServicesError;
-use crate::roles::{Alliance, RoleCard, WorkflowDefinitionWithInput};
+use crate::gamestate::{GameState, RoleContext};
+use crate::roles::{Alliance, RoleCard, WorkflowDefinitionWithInput, registry::RoleRegistry};
+use crate::workflow::builder::NodeId;
+use crate::workflow::expression::Expression;
 use crate::workflow::server_action::ServerActionResult;
-use crate::workflow::{ActionType, WorkflowPredicate};
-use crate::{
-    gamestate::{GameState, RoleContext},
-    workflow::CreateWorkflowDefinition,
+use crate::workflow::{
+    Breakpoint, Comparison, CreateWorkflowDefinition, DisplayType, InputType, OnComplete,
+    ResponseVisibility, WorkflowAction, WorkflowInput, WorkflowNode, WorkflowPredicate,
 };
 
+const SELECT_ROLE: NodeId = NodeId("select_role");
+const WATCH_COMPLETE: NodeId = NodeId("watch_complete");
+
 async fn register_start_role_workflow(game: Arc<Mutex<GameState>>) {
     let game_for_observe = game.clone();
     game.lock()
@@ -32,12 +38,11 @@ async fn register_start_role_workflow(game: Arc<Mutex<GameState>>) {
 
                     let lock = game.lock().await;
 
-                    // Get all matching role cards
-                    let selected = lock
-                        .all_cards()
-                        .iter()
-                        .find(|r| r.name == chosen_role_name)
-                        .cloned();
+                    // Only roles dealt in this game can be observed
+                    let selected = RoleRegistry::global()
+                        .resolve_name(chosen_role_name)
+                        .filter(|name| lock.all_cards().iter().any(|card| card.name == *name))
+                        .and_then(|name| RoleRegistry::global().get(name));
 
                     let Some(role) = &selected else {
                         return Ok(ServerActionResult::CompleteWorkflow {
@@ -61,12 +66,14 @@ async fn register_start_role_workflow(game: Arc<Mutex<GameState>>) {
                         });
                     };
 
-                    if let Some(player) = lock.get_player_by_role(chosen_role_name).await.ok() {
+                    if let Some(player) = lock.get_player_by_role(&role.name).await.ok() {
                         if player.middle_position.is_none() {
                             return Ok(ServerActionResult::WaitForPredicate {
                                 predicate: WorkflowPredicate::ByUserId(player.id),
                                 inject_workflow_as: Some("observed_results".to_string()),
-                                on_complete: Some(ActionType::NextNode),
+                                on_complete: Some(OnComplete::NextNode),
+                                // The spy sees what the observed role sees.
+                                observe: true,
                             });
                         }
                     }
@@ -84,12 +91,91 @@ async fn register_start_role_workflow(game: Arc<Mutex<GameState>>) {
         .expect("Failed to register spy observer action");
 }
 
+pub fn spy_workflow() -> CreateWorkflowDefinition {
+    let item = |path: &str| Box::new(Expression::Item(path.to_string()));
+    let is_not = |path: &str, value: &str| Expression::Compare {
+        left: item(path),
+        comparison: Comparison::NotEqual,
+        right: Box::new(Expression::Literal(json!(value))),
+    };
+
+    // Every role dealt this game that wakes up at night, apart from the Spy
+    // itself and the werewolves.
+    let observable_roles = Expression::Map {
+        list: Box::new(Expression::Filter {
+            list: Box::new(Expression::Fact("cards".to_string())),
+            condition: Box::new(Expression::And(vec![
+                Expression::Item("night_ability".to_string()),
+                is_not("name", "Spy"),
+                is_not("alliance", "Werewolf"),
+            ])),
+        }),
+        to: Box::new(Expression::Object(BTreeMap::from([
+            ("label".to_string(), Expression::Item("name".to_string())),
+            ("value".to_string(), Expression::Item("name".to_string())),
+        ]))),
+    };
+
+    CreateWorkflowDefinition::new("spy_observe_workflow", "Spy Ability")
+        .with_description(
+            "Choose a role to observe. You’ll see what that role sees during the night.",
+        )
+        .node(
+            WorkflowNode::new(SELECT_ROLE, "Pick a Role to Observe")
+                .with_description("Choose a role to impersonate and observe its ability.")
+                .compute("observe_role_options", observable_roles)
+                .input(
+                    WorkflowInput::new(
+                        "chosen_role",
+                        "Which role do you want to observe?",
+                        InputType::SelectFromList {
+                            items_key: "observe_role_options".to_string(),
+                            r#as: String::new(),
+                            layout: HashMap::from([(Breakpoint::Sm, 1)]),
+                            content: Vec::new(),
+                        },
+                    )
+                    .with_width("full"),
+                )
+                .action(
+                    WorkflowAction::run_server_action(
+                        "next",
+                        "Observe Role",
+                        "start_selected_role_workflow",
+                    )
+                    .with_style("primary"),
+                )
+                .transition(WATCH_COMPLETE),
+        )
+        .node(
+            WorkflowNode::new(WATCH_COMPLETE, "Observation Complete")
+                .with_description("You watched the chosen role perform their night ability.")
+                .with_parent(SELECT_ROLE)
+                .display(
+                    "summary",
+                    DisplayType::Text {
+                        text_key: "summary".to_string(),
+                        text: None,
+                    },
+                ),
+        )
+        // The options give away which roles were dealt, so they stay with the
+        // spy along with the pick and what it showed.
+        .response_visibility("observe_role_options", ResponseVisibility::Owner)
+        .response_visibility("chosen_role", ResponseVisibility::Owner)
+        .response_visibility("summary", ResponseVisibility::Owner)
+        .response("observe_role_options", json!([]))
+        .server_action(
+            "start_selected_role_workflow",
+            "Run Role Workflow",
+            "Start the workflow of the chosen role.",
+        )
+}
+
 async fn register_workflow_definition(game: Arc<Mutex<GameState>>) {
     game.lock()
         .await
-        .register_workflow_definition(
-            serde_json::from_str::<CreateWorkflowDefinition>(include_str!("./spy.json")).unwrap(),
-        )
+        .register_workflow_definition(spy_workflow())
         .await
         .unwrap();
 }
@@ -108,27 +194,12 @@ pub fn spy_card() -> RoleCard {
 
         alliance: Alliance::Villager,
         name: "Spy".to_string(),
-        night_ability: Some(Arc::new(|ctx: RoleContext| {
+        night_ability: Some(Arc::new(|_ctx: RoleContext| {
             Box::pin(async move {
-                let options = ctx
-                    .game
-                    .lock()
-                    .await
-                    .all_cards()
-                    .iter()
-                    .filter(|c| {
-                        c.night_ability.is_some()
-                            && c.name != "Spy"
-                            && c.alliance != Alliance::Werewolf
-                    })
-                    .map(|r| json!({ "label": r.name, "value": r.name }))
-                    .collect::<Vec<_>>();
-
-                let mut input = HashMap::new();
-                input.insert("observe_role_options".to_string(), json!(options));
+                // The roles to pick from are computed by the workflow itself.
                 Some(WorkflowDefinitionWithInput {
                     definition: "user-bot-wf-spy_observe_workflow".to_string(),
-                    input,
+                    input: HashMap::new(),
                 })
             })
         })),
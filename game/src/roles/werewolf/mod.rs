@@ -5,20 +5,68 @@ use std::{collections::HashMap, future::Future};
 use futures::lock::Mutex;
 use serde_json::json;
 
+use crate::gamestate::{GameState, RoleContext};
 use crate::roles::{Alliance, RoleCard, WorkflowDefinitionWithInput};
+use crate::workflow::builder::NodeId;
 use crate::workflow::server_action::ServerActionResult;
-use crate::{
-    gamestate::{GameState, RoleContext},
-    workflow::CreateWorkflowDefinition,
+use crate::workflow::{
+    CardFilter, CreateWorkflowDefinition, DisplayType, NodeCondition, ResponseVisibility,
+    WorkflowAction, WorkflowInput, WorkflowNode,
 };
 
+const SELECT_CARD_NODE: NodeId = NodeId("select_card_node");
+const REVEAL_MIDDLE_CARDS: NodeId = NodeId("reveal_middle_cards");
+
+pub fn werewolf_workflow() -> CreateWorkflowDefinition {
+    CreateWorkflowDefinition::new("werewolf_ability_workflow", "Werewolf Ability")
+        .with_description(
+            "If a Werewolf wakes up and they see no other Werewolves, they are allowed to look at one card in the center",
+        )
+        .node(
+            WorkflowNode::new(SELECT_CARD_NODE, "Select a Card")
+                .with_description("Choose a card from a the middle")
+                .input(
+                    WorkflowInput::select_card(
+                        "selected_card",
+                        "Which card do you want to inspect?",
+                        CardFilter::MiddleOnly,
+                    )
+                    .with_width("full"),
+                )
+                .action(
+                    WorkflowAction::run_server_action("next", "Reveal Cards", "reveal_cards")
+                        .with_style("primary"),
+                )
+                .transition_if(
+                    REVEAL_MIDDLE_CARDS,
+                    NodeCondition::ResponseExists("reveal_middle_one".to_string()),
+                )
+                .transition(SELECT_CARD_NODE),
+        )
+        .node(
+            WorkflowNode::new(REVEAL_MIDDLE_CARDS, "Middle Cards Revealed")
+                .with_parent(SELECT_CARD_NODE)
+                .display(
+                    "reveal_cards",
+                    DisplayType::RevealCards {
+                        reveal_card_keys: vec!["reveal_middle_one".to_string()],
+                    },
+                ),
+        )
+        // Only the werewolf who looked knows the middle card.
+        .response_visibility("selected_card", ResponseVisibility::Owner)
+        .response_visibility("reveal_middle_one", ResponseVisibility::Owner)
+        .server_action(
+            "reveal_cards",
+            "Reveal Middle Cards",
+            "Resolves two middle cards",
+        )
+}
+
 async fn register_workflow(game: Arc<Mutex<GameState>>) {
     game.lock()
         .await
-        .register_workflow_definition(
-            serde_json::from_str::<CreateWorkflowDefinition>(include_str!("./werewolf.json"))
-                .unwrap(),
-        )
+        .register_workflow_definition(werewolf_workflow())
         .await
         .expect("unable to register wf");
 }
@@ -5,13 +5,19 @@ use std::{collections::HashMap, future::Future};
 use futures::lock::Mutex;
 use serde_json::json;
 
+use crate::gamestate::{GameState, RoleContext};
 use crate::roles::{Alliance, RoleCard, WorkflowDefinitionWithInput};
+use crate::workflow::builder::NodeId;
 use crate::workflow::server_action::ServerActionResult;
-use crate::{
-    gamestate::{GameState, RoleContext},
-    workflow::CreateWorkflowDefinition,
+use crate::workflow::{
+    CreateWorkflowDefinition, DisplayType, ResponseVisibility, WorkflowAction, WorkflowInput,
+    WorkflowNode,
 };
 
+const START_NODE: NodeId = NodeId("start_node");
+const LOAD_RESULTS: NodeId = NodeId("load_results");
+const DISPLAY_RESULTS: NodeId = NodeId("display_results");
+
 async fn register_show_sabotaged_results(game: Arc<Mutex<GameState>>) {
     let game_clone = game.clone();
     game.lock()
@@ -86,16 +92,81 @@ async fn register_start_sabotaged_role_workflow(game: Arc<Mutex<GameState>>) {
         .expect("unable to register start_sabotaged_role_workflow");
 }
 
+pub fn sabotage_workflow() -> CreateWorkflowDefinition {
+    CreateWorkflowDefinition::new("witch_sabotage_workflow", "Witch Sabotage")
+        .with_description("Sabotage a random role and trigger its workflow")
+        .node(
+            WorkflowNode::new(START_NODE, "Sabotaging...")
+                .with_description("You are sabotaging a random role.")
+                .input(
+                    WorkflowInput::server_action_loader(
+                        "sabotage_trigger",
+                        "Sabotaging Role",
+                        "start_sabotaged_role_workflow",
+                    )
+                    .with_width("full"),
+                )
+                .action(
+                    WorkflowAction::run_server_action(
+                        "start_sabotaged_role_workflow",
+                        "Start sabotage",
+                        "start_sabotaged_role_workflow",
+                    )
+                    .with_style("primary"),
+                )
+                .transition(LOAD_RESULTS),
+        )
+        .node(
+            WorkflowNode::new(LOAD_RESULTS, "You sabotaged")
+                .with_parent(START_NODE)
+                .input(
+                    WorkflowInput::server_action_loader(
+                        "sabotage_results",
+                        "Loading results",
+                        "show_sabotaged_results_workflow",
+                    )
+                    .with_width("full"),
+                )
+                .action(
+                    WorkflowAction::run_server_action(
+                        "show_sabotaged_results_workflow",
+                        "Start sabotage",
+                        "show_sabotaged_results_workflow",
+                    )
+                    .with_style("primary"),
+                )
+                .transition(DISPLAY_RESULTS),
+        )
+        .node(
+            WorkflowNode::new(DISPLAY_RESULTS, "You sabotaged")
+                .with_parent(LOAD_RESULTS)
+                .display(
+                    "results",
+                    DisplayType::Text {
+                        text_key: "results".to_string(),
+                        text: None,
+                    },
+                ),
+        )
+        // The sabotaged role and its outcome are the witch's secret.
+        .response_visibility("sabotage_trigger", ResponseVisibility::Owner)
+        .response_visibility("sabotage_results", ResponseVisibility::Owner)
+        .response_visibility("results", ResponseVisibility::Owner)
+        .server_action(
+            "start_sabotaged_role_workflow",
+            "Start Sabotaged Role Workflow",
+            "Randomly select a role and trigger their workflow for the Witch",
+        )
+}
+
 async fn register_witch_workflow_definition(game: Arc<Mutex<GameState>>) {
     game.lock()
         .await
-        .register_workflow_definition(
-            serde_json::from_str::<CreateWorkflowDefinition>(include_str!("./sabotage.json"))
-                .unwrap(),
-        )
+        .register_workflow_definition(sabotage_workflow())
         .await
         .unwrap();
 }
+
 fn register_witch_workflows(
     game: Arc<Mutex<GameState>>,
 ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
@@ -0,0 +1,372 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::AppResult,
+    gamelog::GameLogEntry,
+    gamestate::Player,
+    roles::{RoleCard, registry::RoleRegistry},
+    workflow::{manager::WorkflowSnapshot, service::PendingExternalActionSnapshot},
+};
+
+/// A serializable copy of a running game. Role cards carry closures, so they
+/// are stored by name and rebuilt on restore.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameSnapshot {
+    pub players: Vec<PlayerSnapshot>,
+    pub stages: Vec<StageSnapshot>,
+    pub sabotaged_inputs: Vec<SabotagedInputs>,
+    pub workflows: WorkflowSnapshot,
+    pub seed: u64,
+    pub rng_word_pos: u128,
+    #[serde(default)]
+    pub external_actions: Vec<PendingExternalActionSnapshot>,
+    #[serde(default)]
+    pub log: Vec<GameLogEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    pub id: String,
+    pub name: String,
+    pub role: String,
+    pub copied_role: Option<String>,
+    pub is_alive: bool,
+    pub middle_position: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageSnapshot {
+    pub player_id: String,
+    pub role: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SabotagedInputs {
+    pub user_id: String,
+    pub workflow_id: String,
+    pub inputs: HashMap<String, Value>,
+}
+
+impl GameSnapshot {
+    pub fn restored_players(&self) -> AppResult<Vec<Player>> {
+        self.players
+            .iter()
+            .cloned()
+            .map(PlayerSnapshot::into_player)
+            .collect()
+    }
+
+    /// The turns still to play, in order.
+    pub fn restored_stages(&self) -> AppResult<VecDeque<(String, RoleCard)>> {
+        self.stages
+            .iter()
+            .map(|stage| Ok((stage.player_id.clone(), role_card(&stage.role)?)))
+            .collect()
+    }
+}
+
+pub fn role_card(name: &str) -> AppResult<RoleCard> {
+    RoleRegistry::global().create(name)
+}
+
+impl From<&Player> for PlayerSnapshot {
+    fn from(player: &Player) -> Self {
+        PlayerSnapshot {
+            id: player.id.clone(),
+            name: player.name.clone(),
+            role: player.role_card.name.clone(),
+            copied_role: player
+                .copied_role_card
+                .as_ref()
+                .map(|card| card.name.clone()),
+            is_alive: player.is_alive,
+            middle_position: player.middle_position,
+        }
+    }
+}
+
+impl PlayerSnapshot {
+    pub fn into_player(self) -> AppResult<Player> {
+        let mut player = Player::new(
+            &self.id,
+            &self.name,
+            Arc::new(role_card(&self.role)?),
+            self.middle_position,
+        );
+        player.copied_role_card = match self.copied_role {
+            Some(name) => Some(Arc::new(role_card(&name)?)),
+            None => None,
+        };
+        player.is_alive = self.is_alive;
+
+        Ok(player)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use serde_json::json;
+    use tokio::sync::broadcast;
+
+    use crate::gamelog::{GameLog, GameLogEvent};
+    use crate::gamerunner::{GameEvent, GameEventReceiver, GameRunner};
+    use crate::gamestate::{GameState, Player, RoleContext};
+    use crate::roles::seer::seer_card;
+    use crate::roles::spy::spy_card;
+    use crate::roles::villager_card;
+    use crate::roles::werewolf::werewolf_card;
+    use crate::workflow::builder::NodeId;
+    use crate::workflow::manager::WorkflowEvent;
+    use crate::workflow::server_action::ServerActionResult;
+    use crate::workflow::service::{
+        ProcessWorkflowActionArgs, WorkflowRespondServerActionArgs, WorkflowService,
+    };
+    use crate::workflow::store::{WorkflowStore, sqlite::SqliteStore};
+    use crate::workflow::{CreateWorkflowDefinition, WorkflowAction, WorkflowNode};
+
+    fn players() -> Vec<Player> {
+        vec![
+            Player::new("sam", "Sam", Arc::new(spy_card()), None),
+            Player::new("sara", "Sara", Arc::new(seer_card()), None),
+            Player::new("vince", "Vince", Arc::new(werewolf_card()), None),
+            Player::new("middle1", "middle 1", Arc::new(villager_card()), Some(0)),
+        ]
+    }
+
+    async fn new_game() -> Arc<futures::lock::Mutex<GameRunner>> {
+        let game = GameState::new(players()).await;
+        let (events, _) = broadcast::channel(16);
+        let runner = GameRunner::new(game, events).await;
+        runner.lock().await.register_cards().await;
+        runner
+    }
+
+    async fn workflow_of(runner: &Arc<futures::lock::Mutex<GameRunner>>) -> Arc<WorkflowService> {
+        let runner = runner.lock().await;
+        runner.game.lock().await.workflow.clone()
+    }
+
+    async fn first_instance(workflow: &WorkflowService, user_id: &str) -> String {
+        workflow
+            .manager
+            .list_user_workflow_resources(user_id)
+            .await
+            .remove(0)
+            .instance_id
+    }
+
+    async fn start_turn(runner: &Arc<futures::lock::Mutex<GameRunner>>, user_id: &str) {
+        let game = runner.lock().await.game.clone();
+        let card = game
+            .lock()
+            .await
+            .get_player(user_id)
+            .await
+            .unwrap()
+            .role_card
+            .clone();
+        GameRunner::start_turn(runner, RoleContext::new(game, user_id), &card).await;
+    }
+
+    async fn next_update(events: &mut GameEventReceiver, player_id: &str) -> String {
+        tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                if let GameEvent::UpdateWorkflow {
+                    player_id: updated,
+                    workflow,
+                } = events.recv().await.unwrap()
+                    && updated == player_id
+                {
+                    return workflow.current_node_id;
+                }
+            }
+        })
+        .await
+        .expect("no update for the player")
+    }
+
+    #[tokio::test]
+    async fn restored_waits_resume_once_the_game_listens() {
+        let runner = new_game().await;
+        let workflow = workflow_of(&runner).await;
+
+        start_turn(&runner, "sam").await;
+        let spy = first_instance(&workflow, "sam").await;
+        let args = ProcessWorkflowActionArgs::new(
+            spy.clone(),
+            "next".to_string(),
+            HashMap::from([("chosen_role".to_string(), json!("Seer"))]),
+        );
+        workflow.process_action("sam", args).await.unwrap();
+        start_turn(&runner, "sara").await;
+        let seer = first_instance(&workflow, "sara").await;
+
+        let mut snapshot = runner.lock().await.snapshot().await;
+        // Taken after the seer finished but before the spy was woken up.
+        snapshot
+            .workflows
+            .instances
+            .get_mut(&seer)
+            .unwrap()
+            .completed = true;
+        let logged = snapshot.log.len();
+        assert!(logged > 1);
+
+        let (events, mut received) = broadcast::channel(16);
+        let restored = GameRunner::restore(snapshot, events).await.unwrap();
+        let entries = restored.lock().await.game.lock().await.log.entries();
+        assert_eq!(entries.len(), logged);
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|entry| matches!(entry.event, GameLogEvent::Dealt { .. }))
+                .count(),
+            1
+        );
+
+        GameRunner::resume(&restored).await.unwrap();
+        assert_eq!(next_update(&mut received, "sam").await, "watch_complete");
+    }
+
+    #[tokio::test]
+    async fn restored_external_actions_are_asked_for_again() {
+        let runner = new_game().await;
+        let workflow = workflow_of(&runner).await;
+        let action_id = workflow
+            .register_external_server_action("oracle", "lookup")
+            .await
+            .unwrap();
+        let definition = CreateWorkflowDefinition::new("asker", "Asker")
+            .server_action(&action_id, "Lookup", "Answered by the oracle")
+            .node(
+                WorkflowNode::new(NodeId("ask"), "Ask")
+                    .action(WorkflowAction::run_server_action("go", "Go", &action_id)),
+            );
+        let definition_id = workflow
+            .register_workflow_definition("oracle", definition)
+            .await
+            .unwrap();
+        let instance_id = workflow
+            .start_command_workflow(&definition_id, "sam", HashMap::new())
+            .await
+            .unwrap()
+            .instance_id;
+        let args =
+            ProcessWorkflowActionArgs::new(instance_id.clone(), "go".to_string(), HashMap::new());
+        workflow.process_action("sam", args).await.unwrap();
+
+        let mut snapshot = runner.lock().await.snapshot().await;
+        for _ in 0..10 {
+            if !snapshot.external_actions.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            snapshot = runner.lock().await.snapshot().await;
+        }
+        assert_eq!(snapshot.external_actions.len(), 1);
+        let token = snapshot.external_actions[0].token.clone();
+
+        let (events, _) = broadcast::channel(16);
+        let restored = GameRunner::restore(snapshot, events).await.unwrap();
+        let workflow = workflow_of(&restored).await;
+        let (tokens, mut requested) = tokio::sync::mpsc::unbounded_channel();
+        workflow
+            .manager
+            .event_manager
+            .lock()
+            .await
+            .on_event(Box::new(move |event| {
+                if let WorkflowEvent::ExternalServerActionRequested { token, .. } = event {
+                    let _ = tokens.send(token);
+                }
+                Box::pin(async {})
+            }));
+
+        GameRunner::resume(&restored).await.unwrap();
+        let asked = tokio::time::timeout(Duration::from_secs(1), requested.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(asked, token);
+
+        workflow
+            .respond_server_action(
+                "oracle",
+                WorkflowRespondServerActionArgs {
+                    token,
+                    result: ServerActionResult::CompleteWorkflow {
+                        responses: HashMap::new(),
+                        message: "Answered".to_string(),
+                    },
+                },
+            )
+            .await
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !workflow
+                .get_workflow_resource(&instance_id)
+                .await
+                .unwrap()
+                .completed
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the answer was not applied");
+    }
+
+    #[tokio::test]
+    async fn reopening_a_stored_game_carries_on_without_dealing_again() {
+        let store: Arc<dyn WorkflowStore> = Arc::new(SqliteStore::open_in_memory("game").unwrap());
+        let (events, _) = broadcast::channel(16);
+        let runner = GameRunner::open(players(), store.clone(), GameLog::new(), events)
+            .await
+            .unwrap();
+        runner.lock().await.register_cards().await;
+        GameRunner::resume(&runner).await.unwrap();
+        let stages = runner.lock().await.stages.len();
+
+        // The spy takes its turn and starts watching the seer.
+        let (player_id, _) = runner.lock().await.stages.pop_front().unwrap();
+        assert_eq!(player_id, "sam");
+        start_turn(&runner, "sam").await;
+        let workflow = workflow_of(&runner).await;
+        let spy = first_instance(&workflow, "sam").await;
+        let args = ProcessWorkflowActionArgs::new(
+            spy.clone(),
+            "next".to_string(),
+            HashMap::from([("chosen_role".to_string(), json!("Seer"))]),
+        );
+        workflow.process_action("sam", args).await.unwrap();
+        runner.lock().await.save().await;
+
+        let (events, _) = broadcast::channel(16);
+        let reopened = GameRunner::open(players(), store, GameLog::new(), events)
+            .await
+            .unwrap();
+        GameRunner::resume(&reopened).await.unwrap();
+
+        let reopened = reopened.lock().await;
+        assert_eq!(reopened.stages.len(), stages - 1);
+        let entries = reopened.game.lock().await.log.entries();
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|entry| matches!(entry.event, GameLogEvent::Dealt { .. }))
+                .count(),
+            1
+        );
+        let workflow = reopened.game.lock().await.workflow.clone();
+        let spy = workflow.get_workflow_resource(&spy).await.unwrap();
+        assert!(spy.waiting);
+    }
+}
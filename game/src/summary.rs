@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::gamelog::{GameLogEntry, GameLogEvent};
+use crate::workflow::server_action::ServerActionResult;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+}
+
+impl Locale {
+    /// Pick a locale from a language tag such as `es-ES`, falling back to English.
+    pub fn from_tag(tag: &str) -> Self {
+        match tag.split(['-', '_']).next().unwrap_or_default() {
+            "es" => Locale::Es,
+            _ => Locale::En,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum NightAction {
+    Woke,
+    Selected { target_ids: Vec<String> },
+    Chose { input: String, value: Value },
+    Swapped { with_id: String, became: String },
+    Saw { holder: String, role: String },
+    Died,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NightStep {
+    pub sequence: u64,
+    pub player_id: String,
+    pub role: String,
+    pub action: NightAction,
+}
+
+/// What really happened during the night, in log order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NightSummary {
+    pub steps: Vec<NightStep>,
+    pub locale: Locale,
+    pub text: Vec<String>,
+    #[serde(skip)]
+    names: HashMap<String, String>,
+}
+
+impl NightSummary {
+    pub fn from_log(entries: &[GameLogEntry], locale: Locale) -> Self {
+        let mut names = HashMap::new();
+        let mut roles: HashMap<String, String> = HashMap::new();
+        let mut turn: Option<(String, String)> = None;
+        let mut owners: HashMap<String, String> = HashMap::new();
+        let mut steps = Vec::new();
+
+        for entry in entries {
+            let step = |player_id: &str, role: &str, action| NightStep {
+                sequence: entry.sequence,
+                player_id: player_id.to_string(),
+                role: role.to_string(),
+                action,
+            };
+
+            match &entry.event {
+                GameLogEvent::Dealt { players, .. } => {
+                    for player in players {
+                        names.insert(player.id.clone(), player.name.clone());
+                        roles.insert(player.id.clone(), player.role.clone());
+                    }
+                }
+                GameLogEvent::TurnStarted { player_id, role } => {
+                    steps.push(step(player_id, role, NightAction::Woke));
+                    turn = Some((player_id.clone(), role.clone()));
+                }
+                GameLogEvent::WorkflowStarted {
+                    instance_id,
+                    user_id,
+                    ..
+                } => {
+                    owners.insert(instance_id.clone(), user_id.clone());
+                }
+                GameLogEvent::ActionProcessed {
+                    user_id, inputs, ..
+                } => {
+                    let role = role_of(&turn, &roles, user_id);
+
+                    let mut inputs: Vec<_> = inputs.iter().collect();
+                    inputs.sort_by_key(|(key, _)| *key);
+                    let mut target_ids = Vec::new();
+                    for (key, value) in inputs {
+                        match selected_card_id(value) {
+                            Some(id) => target_ids.push(id),
+                            None => steps.push(step(
+                                user_id,
+                                &role,
+                                NightAction::Chose {
+                                    input: key.clone(),
+                                    value: value.clone(),
+                                },
+                            )),
+                        }
+                    }
+                    if !target_ids.is_empty() {
+                        steps.push(step(user_id, &role, NightAction::Selected { target_ids }));
+                    }
+                }
+                GameLogEvent::CardsSwapped {
+                    first_player_id,
+                    second_player_id,
+                } => {
+                    let first = roles.get(first_player_id).cloned().unwrap_or_default();
+                    let second = roles.get(second_player_id).cloned().unwrap_or_default();
+                    roles.insert(first_player_id.clone(), second.clone());
+                    roles.insert(second_player_id.clone(), first.clone());
+
+                    let (actor, other, role, became) = match &turn {
+                        Some((player_id, role)) if player_id == second_player_id => {
+                            (second_player_id, first_player_id, role.clone(), first)
+                        }
+                        Some((_, role)) => {
+                            (first_player_id, second_player_id, role.clone(), second)
+                        }
+                        None => (first_player_id, second_player_id, first, second),
+                    };
+                    steps.push(step(
+                        actor,
+                        &role,
+                        NightAction::Swapped {
+                            with_id: other.clone(),
+                            became,
+                        },
+                    ));
+                }
+                GameLogEvent::PlayerDied { player_id } => {
+                    let role = roles.get(player_id).cloned().unwrap_or_default();
+                    steps.push(step(player_id, &role, NightAction::Died));
+                }
+                GameLogEvent::ServerActionCompleted {
+                    instance_id,
+                    result,
+                    ..
+                } => {
+                    let responses = match result {
+                        ServerActionResult::UpdateResponses(responses)
+                        | ServerActionResult::CompleteWorkflow { responses, .. } => responses,
+                        _ => continue,
+                    };
+                    let Some(user_id) = owners.get(instance_id) else {
+                        continue;
+                    };
+                    let role = role_of(&turn, &roles, user_id);
+
+                    let mut responses: Vec<_> = responses.iter().collect();
+                    responses.sort_by_key(|(key, _)| *key);
+                    for (holder, seen) in responses
+                        .into_iter()
+                        .flat_map(|(_, value)| revealed_cards(value))
+                    {
+                        steps.push(step(
+                            user_id,
+                            &role,
+                            NightAction::Saw { holder, role: seen },
+                        ));
+                    }
+                }
+                GameLogEvent::VotesCast { .. } => {}
+            }
+        }
+
+        let mut summary = NightSummary {
+            steps,
+            locale,
+            text: Vec::new(),
+            names,
+        };
+        summary.text = summary.render(locale);
+        summary
+    }
+
+    pub fn render(&self, locale: Locale) -> Vec<String> {
+        self.steps
+            .iter()
+            .map(|step| self.describe(step, locale))
+            .collect()
+    }
+
+    fn name<'a>(&'a self, player_id: &'a str) -> &'a str {
+        self.names.get(player_id).map_or(player_id, String::as_str)
+    }
+
+    fn describe(&self, step: &NightStep, locale: Locale) -> String {
+        let actor = format!("{} ({})", step.role, self.name(&step.player_id));
+        match (&step.action, locale) {
+            (NightAction::Woke, Locale::En) => format!("{actor} woke up"),
+            (NightAction::Woke, Locale::Es) => format!("{actor} despertó"),
+            (NightAction::Selected { target_ids }, locale) => {
+                let targets = target_ids
+                    .iter()
+                    .map(|id| self.name(id))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                match locale {
+                    Locale::En => format!("{actor} looked at {targets}"),
+                    Locale::Es => format!("{actor} miró a {targets}"),
+                }
+            }
+            (NightAction::Chose { value, .. }, locale) => {
+                let value = value
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| value.to_string());
+                match locale {
+                    Locale::En => format!("{actor} chose {value}"),
+                    Locale::Es => format!("{actor} eligió {value}"),
+                }
+            }
+            (NightAction::Swapped { with_id, became }, Locale::En) => {
+                format!(
+                    "{actor} swapped with {} and became {became}",
+                    self.name(with_id)
+                )
+            }
+            (NightAction::Swapped { with_id, became }, Locale::Es) => {
+                format!(
+                    "{actor} intercambió con {} y se convirtió en {became}",
+                    self.name(with_id)
+                )
+            }
+            (NightAction::Saw { holder, role }, Locale::En) => {
+                format!("{actor} saw that {holder} is the {role}")
+            }
+            (NightAction::Saw { holder, role }, Locale::Es) => {
+                format!("{actor} vio que {holder} es {role}")
+            }
+            (NightAction::Died, Locale::En) => format!("{actor} died"),
+            (NightAction::Died, Locale::Es) => format!("{actor} murió"),
+        }
+    }
+}
+
+/// The id of a card picked through a `SelectCard` input, e.g.
+/// `{"type": "Player", "Player": {"id": "alice"}}`.
+fn selected_card_id(value: &Value) -> Option<String> {
+    let kind = value.get("type")?.as_str()?;
+    value.get(kind)?.get("id")?.as_str().map(str::to_string)
+}
+
+/// The role a player acted as: the one whose turn it is, or else the card they
+/// hold now.
+fn role_of(
+    turn: &Option<(String, String)>,
+    roles: &HashMap<String, String>,
+    user_id: &str,
+) -> String {
+    match turn {
+        Some((player_id, role)) if player_id == user_id => role.clone(),
+        _ => roles.get(user_id).cloned().unwrap_or_default(),
+    }
+}
+
+/// The holder and role of each card a server action revealed, e.g.
+/// `[{"name": "Vince", "card": {"name": "Villager"}}]`.
+fn revealed_cards(value: &Value) -> Vec<(String, String)> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|card| {
+            let holder = card.get("name")?.as_str()?;
+            let role = card.get("card")?.get("name")?.as_str()?;
+            Some((holder.to_string(), role.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::{Locale, NightSummary};
+    use crate::gamelog::{GameLog, GameLogEvent};
+    use crate::snapshot::PlayerSnapshot;
+    use crate::workflow::server_action::ServerActionResult;
+
+    fn dealt(players: &[(&str, &str, &str)]) -> GameLogEvent {
+        GameLogEvent::Dealt {
+            players: players
+                .iter()
+                .map(|(id, name, role)| PlayerSnapshot {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    role: role.to_string(),
+                    copied_role: None,
+                    is_alive: true,
+                    middle_position: None,
+                })
+                .collect(),
+            seed: 0,
+        }
+    }
+
+    fn turn(player_id: &str, role: &str) -> GameLogEvent {
+        GameLogEvent::TurnStarted {
+            player_id: player_id.to_string(),
+            role: role.to_string(),
+        }
+    }
+
+    fn night() -> GameLog {
+        let log = GameLog::new();
+        log.append(dealt(&[
+            ("dopple", "Dopple Dan", "Doppelgänger"),
+            ("vince", "Vince", "Werewolf"),
+            ("sam", "Seer Sam", "Seer"),
+        ]));
+        log.append(turn("dopple", "Doppelgänger"));
+        log.append(GameLogEvent::CardsSwapped {
+            first_player_id: "dopple".to_string(),
+            second_player_id: "sam".to_string(),
+        });
+        log.append(turn("sam", "Seer"));
+        log.append(GameLogEvent::WorkflowStarted {
+            instance_id: "seer-instance".to_string(),
+            workflow_id: "seer_ability_workflow".to_string(),
+            user_id: "sam".to_string(),
+            inputs: HashMap::new(),
+        });
+        log.append(GameLogEvent::ActionProcessed {
+            instance_id: "seer-instance".to_string(),
+            user_id: "sam".to_string(),
+            action_id: "next".to_string(),
+            inputs: HashMap::from([(
+                "selected_card".to_string(),
+                json!({ "type": "Player", "Player": { "id": "vince" } }),
+            )]),
+        });
+        log.append(GameLogEvent::ServerActionCompleted {
+            instance_id: "seer-instance".to_string(),
+            action_id: "reveal_cards".to_string(),
+            result: ServerActionResult::UpdateResponses(HashMap::from([(
+                "revealed_cards".to_string(),
+                json!([{ "name": "Vince", "card": { "name": "Werewolf" } }]),
+            )])),
+        });
+        log.append(GameLogEvent::VotesCast {
+            votes: HashMap::from([("sam".to_string(), "vince".to_string())]),
+            eliminated: vec!["vince".to_string()],
+        });
+        log.append(GameLogEvent::PlayerDied {
+            player_id: "vince".to_string(),
+        });
+        log
+    }
+
+    #[test]
+    fn the_night_is_told_in_log_order() {
+        let summary = NightSummary::from_log(&night().entries(), Locale::En);
+        assert_eq!(
+            summary.text,
+            [
+                "Doppelgänger (Dopple Dan) woke up",
+                "Doppelgänger (Dopple Dan) swapped with Seer Sam and became Seer",
+                "Seer (Seer Sam) woke up",
+                "Seer (Seer Sam) looked at Vince",
+                "Seer (Seer Sam) saw that Vince is the Werewolf",
+                "Werewolf (Vince) died",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_same_night_renders_in_spanish() {
+        let summary = NightSummary::from_log(&night().entries(), Locale::from_tag("es-ES"));
+        assert_eq!(summary.locale, Locale::Es);
+        assert_eq!(
+            summary.text[1],
+            "Doppelgänger (Dopple Dan) intercambió con Seer Sam y se convirtió en Seer"
+        );
+        assert_eq!(summary.render(Locale::En)[5], "Werewolf (Vince) died");
+    }
+
+    #[test]
+    fn choices_other_than_cards_are_told_by_value() {
+        let log = GameLog::new();
+        log.append(dealt(&[("violet", "Violet", "Spy")]));
+        log.append(turn("violet", "Spy"));
+        log.append(GameLogEvent::ActionProcessed {
+            instance_id: "spy-instance".to_string(),
+            user_id: "violet".to_string(),
+            action_id: "next".to_string(),
+            inputs: HashMap::from([("chosen_role".to_string(), json!("Seer"))]),
+        });
+
+        let summary = NightSummary::from_log(&log.entries(), Locale::En);
+        assert_eq!(
+            summary.text,
+            ["Spy (Violet) woke up", "Spy (Violet) chose Seer"]
+        );
+        assert_eq!(Locale::from_tag("fr"), Locale::En);
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use super::expression::Expression;
+use super::{
+    ActionType, CardFilter, CreateWorkflowDefinition, DisplayType, InputType, NodeCondition,
+    NodeTimeout, NodeTransition, ResponseVisibility, ServerActionDefinition, WorkflowAction,
+    WorkflowDisplay, WorkflowInput, WorkflowNode,
+};
+
+/// The id of a node, declared once per workflow and used wherever the node is
+/// referred to, so a misspelt parent or transition target does not compile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeId(pub &'static str);
+
+impl CreateWorkflowDefinition {
+    /// An empty definition, to be filled in with typed nodes rather than
+    /// parsed from JSON. The first node added is where it starts unless
+    /// [`CreateWorkflowDefinition::with_initial_node`] says otherwise.
+    pub fn new(id: &str, name: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            description: None,
+            initial_node_id: String::new(),
+            nodes: HashMap::new(),
+            responses: HashMap::new(),
+            server_actions: HashMap::new(),
+            response_visibility: HashMap::new(),
+        }
+    }
+
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn with_initial_node(mut self, node_id: NodeId) -> Self {
+        self.initial_node_id = node_id.0.to_string();
+        self
+    }
+
+    pub fn node(mut self, node: WorkflowNode) -> Self {
+        if self.initial_node_id.is_empty() {
+            self.initial_node_id = node.id.clone();
+        }
+        self.nodes.insert(node.id.clone(), node);
+        self
+    }
+
+    /// Declare a response and the value it starts with.
+    pub fn response(mut self, key: &str, value: Value) -> Self {
+        self.responses.insert(key.to_string(), value);
+        self
+    }
+
+    pub fn response_visibility(mut self, key: &str, visibility: ResponseVisibility) -> Self {
+        self.response_visibility.insert(key.to_string(), visibility);
+        self
+    }
+
+    pub fn server_action(mut self, id: &str, name: &str, description: &str) -> Self {
+        self.server_actions.insert(
+            id.to_string(),
+            ServerActionDefinition {
+                id: id.to_string(),
+                name: name.to_string(),
+                description: Some(description.to_string()),
+            },
+        );
+        self
+    }
+
+    /// The definition as it would be written by hand.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("workflow definitions are always valid JSON")
+    }
+}
+
+impl WorkflowNode {
+    pub fn new(id: NodeId, title: &str) -> Self {
+        Self {
+            id: id.0.to_string(),
+            title: title.to_string(),
+            description: None,
+            displays: Vec::new(),
+            inputs: Vec::new(),
+            actions: Vec::new(),
+            layout: None,
+            condition: None,
+            parent_id: None,
+            transitions: Vec::new(),
+            timeout: None,
+            compute: HashMap::new(),
+        }
+    }
+
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn with_layout(mut self, layout: &str) -> Self {
+        self.layout = Some(layout.to_string());
+        self
+    }
+
+    /// Only offer this node as a child of its parent when `condition` holds.
+    pub fn with_condition(mut self, condition: NodeCondition) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    pub fn with_parent(mut self, parent_id: NodeId) -> Self {
+        self.parent_id = Some(parent_id.0.to_string());
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: NodeTimeout) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn display(mut self, id: &str, display_type: DisplayType) -> Self {
+        self.displays.push(WorkflowDisplay {
+            id: id.to_string(),
+            display_type,
+        });
+        self
+    }
+
+    pub fn input(mut self, input: WorkflowInput) -> Self {
+        self.inputs.push(input);
+        self
+    }
+
+    pub fn action(mut self, action: WorkflowAction) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    /// Go to `target` when `condition` holds and no earlier transition did.
+    pub fn transition_if(mut self, target: NodeId, condition: NodeCondition) -> Self {
+        self.transitions.push(NodeTransition {
+            target: target.0.to_string(),
+            condition: Some(condition),
+        });
+        self
+    }
+
+    /// Go to `target` unconditionally; the last transition must be one.
+    pub fn transition(mut self, target: NodeId) -> Self {
+        self.transitions.push(NodeTransition {
+            target: target.0.to_string(),
+            condition: None,
+        });
+        self
+    }
+
+    /// Set the response `key` to `expression` whenever this node is entered.
+    pub fn compute(mut self, key: &str, expression: Expression) -> Self {
+        self.compute.insert(key.to_string(), expression);
+        self
+    }
+}
+
+impl WorkflowInput {
+    /// A required input of any type.
+    pub fn new(id: &str, label: &str, input_type: InputType) -> Self {
+        Self {
+            id: id.to_string(),
+            label: label.to_string(),
+            input_type,
+            default_value: None,
+            required: true,
+            width: None,
+        }
+    }
+
+    pub fn select_card(id: &str, label: &str, filter: CardFilter) -> Self {
+        Self::new(id, label, InputType::SelectCard { filter })
+    }
+
+    /// Pick one of the items in the response `items_key`.
+    pub fn select_from_list(id: &str, label: &str, items_key: &str) -> Self {
+        Self::new(
+            id,
+            label,
+            InputType::SelectFromList {
+                items_key: items_key.to_string(),
+                r#as: String::new(),
+                layout: HashMap::new(),
+                content: Vec::new(),
+            },
+        )
+    }
+
+    /// Filled in by the server action `target`.
+    pub fn server_action_loader(id: &str, label: &str, target: &str) -> Self {
+        Self::new(
+            id,
+            label,
+            InputType::ServerActionLoader {
+                target: target.to_string(),
+            },
+        )
+    }
+
+    pub fn optional(mut self) -> Self {
+        self.required = false;
+        self
+    }
+
+    pub fn with_default(mut self, value: Value) -> Self {
+        self.default_value = Some(value);
+        self
+    }
+
+    pub fn with_width(mut self, width: &str) -> Self {
+        self.width = Some(width.to_string());
+        self
+    }
+}
+
+impl WorkflowAction {
+    pub fn new(id: &str, label: &str, action_type: ActionType) -> Self {
+        Self {
+            id: id.to_string(),
+            label: label.to_string(),
+            action_type,
+            target: None,
+            style: None,
+        }
+    }
+
+    /// Follow the node's transitions, or its children without any.
+    pub fn next_node(id: &str, label: &str) -> Self {
+        Self::new(id, label, ActionType::NextNode)
+    }
+
+    pub fn go_to(id: &str, label: &str, node_id: NodeId) -> Self {
+        Self::new(id, label, ActionType::NextNode).with_target(node_id.0)
+    }
+
+    pub fn previous_node(id: &str, label: &str) -> Self {
+        Self::new(id, label, ActionType::PreviousNode)
+    }
+
+    pub fn submit(id: &str, label: &str) -> Self {
+        Self::new(id, label, ActionType::Submit)
+    }
+
+    pub fn cancel(id: &str, label: &str) -> Self {
+        Self::new(id, label, ActionType::Cancel)
+    }
+
+    pub fn run_server_action(id: &str, label: &str, action_id: &str) -> Self {
+        Self::new(id, label, ActionType::RunServerAction).with_target(action_id)
+    }
+
+    pub fn start_workflow(id: &str, label: &str, workflow_id: &str) -> Self {
+        Self::new(id, label, ActionType::StartWorkflow).with_target(workflow_id)
+    }
+
+    pub fn with_target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn with_style(mut self, style: &str) -> Self {
+        self.style = Some(style.to_string());
+        self
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use specta::Type;
+use thiserror::Error;
+
+use super::Comparison;
+use super::template::lookup;
+
+/// How many expressions a single evaluation may visit, lists included, so a
+/// definition cannot keep the server busy.
+pub const MAX_STEPS: usize = 10_000;
+
+/// A value computed from responses and game facts when a node is entered.
+/// Expressions only read; they cannot change the game or call out.
+#[derive(Type, Debug, Clone, Serialize, Deserialize)]
+pub enum Expression {
+    Literal(Value),
+    // Dotted path into the responses
+    Response(String),
+    // Dotted path into the game facts
+    Fact(String),
+    // Dotted path into the list item of the enclosing Filter or Map; empty for
+    // the item itself
+    Item(String),
+    Filter {
+        list: Box<Expression>,
+        condition: Box<Expression>,
+    },
+    Map {
+        list: Box<Expression>,
+        to: Box<Expression>,
+    },
+    Count(Box<Expression>),
+    Distinct(Box<Expression>),
+    Compare {
+        left: Box<Expression>,
+        comparison: Comparison,
+        right: Box<Expression>,
+    },
+    And(Vec<Expression>),
+    Or(Vec<Expression>),
+    Not(Box<Expression>),
+    Object(BTreeMap<String, Expression>),
+}
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum ExpressionError {
+    #[error("gave up after {MAX_STEPS} steps")]
+    TooExpensive,
+
+    #[error("Item is only available inside Filter or Map")]
+    NoItem,
+
+    #[error("expected a list, got {0}")]
+    NotAList(Value),
+}
+
+impl Expression {
+    pub fn uses_facts(&self) -> bool {
+        match self {
+            Expression::Fact(_) => true,
+            Expression::Filter {
+                list,
+                condition: other,
+            }
+            | Expression::Map { list, to: other } => list.uses_facts() || other.uses_facts(),
+            Expression::Compare { left, right, .. } => left.uses_facts() || right.uses_facts(),
+            Expression::Count(inner) | Expression::Distinct(inner) | Expression::Not(inner) => {
+                inner.uses_facts()
+            }
+            Expression::And(expressions) | Expression::Or(expressions) => {
+                expressions.iter().any(Expression::uses_facts)
+            }
+            Expression::Object(fields) => fields.values().any(Expression::uses_facts),
+            Expression::Literal(_) | Expression::Response(_) | Expression::Item(_) => false,
+        }
+    }
+
+    /// Whether `Item` appears where there is no list item to read.
+    pub fn has_stray_item(&self) -> bool {
+        match self {
+            Expression::Item(_) => true,
+            Expression::Filter { list, .. } | Expression::Map { list, .. } => list.has_stray_item(),
+            Expression::Compare { left, right, .. } => {
+                left.has_stray_item() || right.has_stray_item()
+            }
+            Expression::Count(inner) | Expression::Distinct(inner) | Expression::Not(inner) => {
+                inner.has_stray_item()
+            }
+            Expression::And(expressions) | Expression::Or(expressions) => {
+                expressions.iter().any(Expression::has_stray_item)
+            }
+            Expression::Object(fields) => fields.values().any(Expression::has_stray_item),
+            Expression::Literal(_) | Expression::Response(_) | Expression::Fact(_) => false,
+        }
+    }
+
+    pub fn evaluate(
+        &self,
+        responses: &HashMap<String, Value>,
+        facts: &HashMap<String, Value>,
+    ) -> Result<Value, ExpressionError> {
+        let mut evaluator = Evaluator {
+            responses,
+            facts,
+            steps: 0,
+        };
+        evaluator.evaluate(self, None)
+    }
+}
+
+struct Evaluator<'a> {
+    responses: &'a HashMap<String, Value>,
+    facts: &'a HashMap<String, Value>,
+    steps: usize,
+}
+
+impl Evaluator<'_> {
+    fn evaluate(
+        &mut self,
+        expression: &Expression,
+        item: Option<&Value>,
+    ) -> Result<Value, ExpressionError> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err(ExpressionError::TooExpensive);
+        }
+
+        Ok(match expression {
+            Expression::Literal(value) => value.clone(),
+            Expression::Response(path) => {
+                lookup(self.responses, path).cloned().unwrap_or(Value::Null)
+            }
+            Expression::Fact(path) => lookup(self.facts, path).cloned().unwrap_or(Value::Null),
+            Expression::Item(path) => {
+                let item = item.ok_or(ExpressionError::NoItem)?;
+                item_path(item, path).cloned().unwrap_or(Value::Null)
+            }
+            Expression::Filter { list, condition } => {
+                let mut kept = Vec::new();
+                for entry in self.list(list, item)? {
+                    if truthy(&self.evaluate(condition, Some(&entry))?) {
+                        kept.push(entry);
+                    }
+                }
+                Value::Array(kept)
+            }
+            Expression::Map { list, to } => {
+                let mut mapped = Vec::new();
+                for entry in self.list(list, item)? {
+                    mapped.push(self.evaluate(to, Some(&entry))?);
+                }
+                Value::Array(mapped)
+            }
+            Expression::Count(list) => Value::from(self.list(list, item)?.len()),
+            Expression::Distinct(list) => {
+                let mut unique: Vec<Value> = Vec::new();
+                for entry in self.list(list, item)? {
+                    if !unique.contains(&entry) {
+                        unique.push(entry);
+                    }
+                }
+                Value::Array(unique)
+            }
+            Expression::Compare {
+                left,
+                comparison,
+                right,
+            } => {
+                let left = self.evaluate(left, item)?;
+                let right = self.evaluate(right, item)?;
+                Value::Bool(compare(&left, *comparison, &right))
+            }
+            Expression::And(expressions) => {
+                for expression in expressions {
+                    if !truthy(&self.evaluate(expression, item)?) {
+                        return Ok(Value::Bool(false));
+                    }
+                }
+                Value::Bool(true)
+            }
+            Expression::Or(expressions) => {
+                for expression in expressions {
+                    if truthy(&self.evaluate(expression, item)?) {
+                        return Ok(Value::Bool(true));
+                    }
+                }
+                Value::Bool(false)
+            }
+            Expression::Not(expression) => Value::Bool(!truthy(&self.evaluate(expression, item)?)),
+            Expression::Object(fields) => {
+                let mut object = serde_json::Map::new();
+                for (key, expression) in fields {
+                    object.insert(key.clone(), self.evaluate(expression, item)?);
+                }
+                Value::Object(object)
+            }
+        })
+    }
+
+    /// Missing values count as an empty list.
+    fn list(
+        &mut self,
+        expression: &Expression,
+        item: Option<&Value>,
+    ) -> Result<Vec<Value>, ExpressionError> {
+        match self.evaluate(expression, item)? {
+            Value::Array(entries) => {
+                self.steps += entries.len();
+                if self.steps > MAX_STEPS {
+                    return Err(ExpressionError::TooExpensive);
+                }
+                Ok(entries)
+            }
+            Value::Null => Ok(Vec::new()),
+            other => Err(ExpressionError::NotAList(other)),
+        }
+    }
+}
+
+fn item_path<'a>(item: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return Some(item);
+    }
+
+    let mut current = item;
+    for segment in path.split('.') {
+        current = match current {
+            Value::Object(object) => object.get(segment)?,
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+/// `null`, `false`, zero, and empty strings, lists or objects are false.
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(text) => !text.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(fields) => !fields.is_empty(),
+    }
+}
+
+/// Any two values can be tested for equality; only numbers with numbers and
+/// strings with strings have an order.
+fn compare(left: &Value, comparison: Comparison, right: &Value) -> bool {
+    match (comparison, left, right) {
+        (Comparison::Equal, _, _) => left == right,
+        (Comparison::NotEqual, _, _) => left != right,
+        (_, Value::Number(a), Value::Number(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => comparison.compare(a, b),
+            _ => false,
+        },
+        (_, Value::String(a), Value::String(b)) => comparison.compare(a, b),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::{Value, json};
+
+    use super::{Expression, ExpressionError, MAX_STEPS};
+    use crate::workflow::Comparison;
+
+    fn evaluate(expression: &Expression, responses: Value) -> Result<Value, ExpressionError> {
+        let responses: HashMap<String, Value> = serde_json::from_value(responses).unwrap();
+        expression.evaluate(&responses, &HashMap::new())
+    }
+
+    fn item(path: &str) -> Box<Expression> {
+        Box::new(Expression::Item(path.to_string()))
+    }
+
+    fn response(path: &str) -> Box<Expression> {
+        Box::new(Expression::Response(path.to_string()))
+    }
+
+    fn literal(value: Value) -> Box<Expression> {
+        Box::new(Expression::Literal(value))
+    }
+
+    #[test]
+    fn filter_and_map_read_the_current_item() {
+        let werewolves = Expression::Map {
+            list: Box::new(Expression::Filter {
+                list: response("players"),
+                condition: Box::new(Expression::Compare {
+                    left: item("role"),
+                    comparison: Comparison::Equal,
+                    right: literal(json!("Werewolf")),
+                }),
+            }),
+            to: item("id"),
+        };
+        let responses = json!({ "players": [
+            { "id": "a", "role": "Werewolf" },
+            { "id": "b", "role": "Seer" },
+            { "id": "c", "role": "Werewolf" },
+        ]});
+
+        assert_eq!(evaluate(&werewolves, responses), Ok(json!(["a", "c"])));
+    }
+
+    #[test]
+    fn item_is_the_innermost_list_entry() {
+        let cases = [
+            (
+                Expression::Map {
+                    list: response("rows"),
+                    to: Box::new(Expression::Count(item(""))),
+                },
+                json!([2, 1]),
+            ),
+            (
+                Expression::Map {
+                    list: response("rows"),
+                    to: Box::new(Expression::Map {
+                        list: item(""),
+                        to: Box::new(Expression::Compare {
+                            left: item(""),
+                            comparison: Comparison::GreaterThan,
+                            right: literal(json!(1)),
+                        }),
+                    }),
+                },
+                json!([[false, true], [true]]),
+            ),
+            (
+                Expression::Map {
+                    list: response("rows"),
+                    to: item("0"),
+                },
+                json!([1, 3]),
+            ),
+        ];
+
+        for (expression, expected) in cases {
+            assert_eq!(
+                evaluate(&expression, json!({ "rows": [[1, 2], [3]] })),
+                Ok(expected),
+                "{expression:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn item_outside_a_list_is_stray() {
+        let cases = [
+            (Expression::Item("id".to_string()), true),
+            (
+                Expression::Filter {
+                    list: item(""),
+                    condition: literal(json!(true)),
+                },
+                true,
+            ),
+            (Expression::Count(item("")), true),
+            (
+                Expression::Filter {
+                    list: response("players"),
+                    condition: item("alive"),
+                },
+                false,
+            ),
+        ];
+
+        for (expression, stray) in cases {
+            assert_eq!(expression.has_stray_item(), stray, "{expression:?}");
+        }
+        assert_eq!(
+            evaluate(&Expression::Item("id".to_string()), json!({})),
+            Err(ExpressionError::NoItem)
+        );
+    }
+
+    #[test]
+    fn values_of_the_wrong_type() {
+        let cases = [
+            (
+                Expression::Count(literal(json!(3))),
+                Err(ExpressionError::NotAList(json!(3))),
+            ),
+            (
+                Expression::Map {
+                    list: response("name"),
+                    to: item(""),
+                },
+                Err(ExpressionError::NotAList(json!("Sam"))),
+            ),
+            // Missing lists are empty rather than an error.
+            (Expression::Count(response("missing")), Ok(json!(0))),
+            // Only numbers with numbers and strings with strings are ordered.
+            (
+                Expression::Compare {
+                    left: literal(json!("2")),
+                    comparison: Comparison::GreaterThan,
+                    right: literal(json!(1)),
+                },
+                Ok(json!(false)),
+            ),
+            (
+                Expression::Compare {
+                    left: literal(json!("b")),
+                    comparison: Comparison::GreaterThan,
+                    right: literal(json!("a")),
+                },
+                Ok(json!(true)),
+            ),
+            (Expression::Not(response("missing")), Ok(json!(true))),
+        ];
+
+        for (expression, expected) in cases {
+            assert_eq!(
+                evaluate(&expression, json!({ "name": "Sam" })),
+                expected,
+                "{expression:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn evaluation_is_bounded() {
+        let long = json!(vec![0; MAX_STEPS]);
+        assert_eq!(
+            evaluate(&Expression::Count(literal(long)), json!({})),
+            Err(ExpressionError::TooExpensive)
+        );
+
+        // Each list is small; together they are not.
+        let pairs = Expression::Map {
+            list: response("rows"),
+            to: Box::new(Expression::Map {
+                list: response("rows"),
+                to: item(""),
+            }),
+        };
+        assert_eq!(
+            evaluate(&pairs, json!({ "rows": vec![0; 200] })),
+            Err(ExpressionError::TooExpensive)
+        );
+
+        let within = Expression::Count(literal(json!(vec![0; MAX_STEPS / 2])));
+        assert_eq!(evaluate(&within, json!({})), Ok(json!(MAX_STEPS / 2)));
+    }
+}
@@ -0,0 +1,13 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+/// Source of the game facts that `FactEquals`/`FactIn` conditions and `Fact`
+/// expressions read. Facts are nested JSON looked up by dotted path, like
+/// responses. A `table` fact with `players` and `middle` id lists lets card
+/// selections be checked.
+#[async_trait]
+pub trait FactProvider: Send + Sync {
+    async fn facts(&self, user_id: &str) -> HashMap<String, Value>;
+}
@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use specta::Type;
+
+use super::server_action::ServerActionContext;
+use super::{ActionType, CardFilter, InputType, WorkflowAction, WorkflowNode};
+
+/// Why the value submitted for one input was refused.
+#[derive(Type, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+pub fn format_field_errors(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// The cards on the table, read from the `table` fact.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    pub players: Vec<String>,
+    pub middle: Vec<String>,
+}
+
+impl Table {
+    pub fn from_facts(facts: &HashMap<String, Value>) -> Option<Table> {
+        let table = facts.get("table")?;
+        let ids = |key: &str| -> Option<Vec<String>> {
+            table
+                .get(key)?
+                .as_array()?
+                .iter()
+                .map(|id| id.as_str().map(str::to_string))
+                .collect()
+        };
+
+        Some(Table {
+            players: ids("players")?,
+            middle: ids("middle")?,
+        })
+    }
+}
+
+/// Check what a client submitted with `action` against the inputs of `node`.
+/// Card selections are only checked against the table when one is known.
+pub fn validate_inputs(
+    node: &WorkflowNode,
+    action: &WorkflowAction,
+    inputs: &HashMap<String, Value>,
+    responses: &HashMap<String, Value>,
+    actor_id: &str,
+    table: Option<&Table>,
+) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let mut report = |field: &str, message: String| {
+        errors.push(FieldError {
+            field: field.to_string(),
+            message,
+        });
+    };
+
+    // Going back or cancelling must work with a half filled in node.
+    let enforce_required = !matches!(
+        action.action_type,
+        ActionType::PreviousNode | ActionType::Cancel
+    );
+
+    for input in &node.inputs {
+        let value = inputs.get(&input.id).filter(|value| !value.is_null());
+
+        if let InputType::ServerActionLoader { .. } = input.input_type {
+            if value.is_some() {
+                report(&input.id, "is filled in by the server".to_string());
+            }
+            continue;
+        }
+
+        let Some(value) = value else {
+            if input.required && enforce_required {
+                report(&input.id, "is required".to_string());
+            }
+            continue;
+        };
+
+        let problem = match &input.input_type {
+            InputType::SelectCard { filter } => check_card(value, filter, actor_id, table),
+            InputType::SelectFromList { items_key, .. } => {
+                check_listed(value, items_key, responses)
+            }
+            InputType::ServerActionLoader { .. } => None,
+        };
+        if let Some(message) = problem {
+            report(&input.id, message);
+        }
+    }
+
+    let mut unknown: Vec<&String> = inputs
+        .keys()
+        .filter(|key| !node.inputs.iter().any(|input| &input.id == *key))
+        .collect();
+    unknown.sort();
+    for key in unknown {
+        report(key, "is not an input of this node".to_string());
+    }
+
+    errors
+}
+
+/// Cards are submitted as `{"type": "Player", "Player": {"id": ...}}`, or the
+/// same with `Middle`.
+fn check_card(
+    value: &Value,
+    filter: &CardFilter,
+    actor_id: &str,
+    table: Option<&Table>,
+) -> Option<String> {
+    let Some(kind) = value.get("type").and_then(Value::as_str) else {
+        return Some("is not a card".to_string());
+    };
+    let Some(id) = value
+        .get(kind)
+        .and_then(|card| card.get("id"))
+        .and_then(Value::as_str)
+    else {
+        return Some(format!("is missing the id of the {kind} card"));
+    };
+
+    let (players_allowed, middle_allowed, allow_self) = match filter {
+        CardFilter::PlayerOnly { allow_self } => (true, false, *allow_self),
+        CardFilter::MiddleOnly => (false, true, false),
+        CardFilter::PlayerOrMiddle { allow_self } => (true, true, *allow_self),
+    };
+
+    match kind {
+        "Player" if !players_allowed => Some("must be a middle card".to_string()),
+        "Middle" if !middle_allowed => Some("must be a player's card".to_string()),
+        "Player" if !allow_self && id == actor_id => Some("may not be your own card".to_string()),
+        "Player" if table.is_some_and(|table| !table.players.iter().any(|p| p == id)) => {
+            Some(format!("'{id}' is not a player at this table"))
+        }
+        "Middle" if table.is_some_and(|table| !table.middle.iter().any(|m| m == id)) => {
+            Some(format!("'{id}' is not a middle card"))
+        }
+        "Player" | "Middle" => None,
+        _ => Some(format!("'{kind}' is not a kind of card")),
+    }
+}
+
+/// List items are either the values themselves or options with a `value`.
+fn check_listed(
+    value: &Value,
+    items_key: &str,
+    responses: &HashMap<String, Value>,
+) -> Option<String> {
+    let Some(items) =
+        ServerActionContext::get_nested_value(responses, items_key).and_then(Value::as_array)
+    else {
+        return Some(format!("has no options to pick from in '{items_key}'"));
+    };
+
+    if items
+        .iter()
+        .any(|item| item == value || item.get("value") == Some(value))
+    {
+        None
+    } else {
+        Some(format!("{value} is not one of the options"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::{Value, json};
+
+    use super::{FieldError, Table, validate_inputs};
+    use crate::workflow::builder::NodeId;
+    use crate::workflow::{CardFilter, WorkflowAction, WorkflowInput, WorkflowNode};
+
+    fn card(kind: &str, id: &str) -> Value {
+        let mut card = json!({ "type": kind });
+        card[kind] = json!({ "id": id });
+        card
+    }
+
+    fn node() -> WorkflowNode {
+        WorkflowNode::new(NodeId("pick"), "Pick")
+            .input(WorkflowInput::select_card(
+                "player",
+                "Player",
+                CardFilter::PlayerOnly { allow_self: false },
+            ))
+            .input(
+                WorkflowInput::select_card("middle", "Middle", CardFilter::MiddleOnly).optional(),
+            )
+            .input(WorkflowInput::select_from_list("option", "Option", "options").optional())
+            .input(WorkflowInput::server_action_loader(
+                "loaded", "Loaded", "load",
+            ))
+            .action(WorkflowAction::submit("submit", "Submit"))
+            .action(WorkflowAction::previous_node("back", "Back"))
+    }
+
+    #[test]
+    fn checks_submitted_inputs() {
+        let table = Table {
+            players: vec!["p1".to_string(), "p2".to_string()],
+            middle: vec!["m1".to_string()],
+        };
+        let cases = vec![
+            (
+                "submit",
+                json!({ "player": card("Player", "p2"), "middle": card("Middle", "m1"), "option": "a" }),
+                true,
+                vec![],
+            ),
+            (
+                "submit",
+                json!({ "option": "b" }),
+                true,
+                vec![("player", "is required")],
+            ),
+            // Going back does not need the node filled in.
+            ("back", json!({}), true, vec![]),
+            (
+                "submit",
+                json!({ "player": card("Player", "p1") }),
+                true,
+                vec![("player", "may not be your own card")],
+            ),
+            (
+                "submit",
+                json!({ "player": card("Middle", "m1"), "middle": card("Player", "p2") }),
+                true,
+                vec![
+                    ("player", "must be a player's card"),
+                    ("middle", "must be a middle card"),
+                ],
+            ),
+            (
+                "submit",
+                json!({ "player": card("Player", "p9"), "middle": card("Middle", "m9") }),
+                true,
+                vec![
+                    ("player", "'p9' is not a player at this table"),
+                    ("middle", "'m9' is not a middle card"),
+                ],
+            ),
+            // Without a table only the shape of the card is checked.
+            (
+                "submit",
+                json!({ "player": card("Player", "p9") }),
+                false,
+                vec![],
+            ),
+            (
+                "submit",
+                json!({ "player": "p2" }),
+                true,
+                vec![("player", "is not a card")],
+            ),
+            (
+                "submit",
+                json!({ "player": { "type": "Player", "Player": {} } }),
+                true,
+                vec![("player", "is missing the id of the Player card")],
+            ),
+            (
+                "submit",
+                json!({ "player": card("Dragon", "d1") }),
+                true,
+                vec![("player", "'Dragon' is not a kind of card")],
+            ),
+            (
+                "submit",
+                json!({ "player": card("Player", "p2"), "option": "z" }),
+                true,
+                vec![("option", "\"z\" is not one of the options")],
+            ),
+            (
+                "submit",
+                json!({ "player": card("Player", "p2"), "loaded": "forged" }),
+                true,
+                vec![("loaded", "is filled in by the server")],
+            ),
+            (
+                "submit",
+                json!({ "player": card("Player", "p2"), "zeta": 1, "alpha": 2 }),
+                true,
+                vec![
+                    ("alpha", "is not an input of this node"),
+                    ("zeta", "is not an input of this node"),
+                ],
+            ),
+        ];
+
+        let node = node();
+        let responses: HashMap<String, Value> =
+            serde_json::from_value(json!({ "options": ["a", { "value": "b", "label": "B" }] }))
+                .unwrap();
+        for (action_id, inputs, with_table, expected) in cases {
+            let action = node.actions.iter().find(|a| a.id == action_id).unwrap();
+            let inputs: HashMap<String, Value> = serde_json::from_value(inputs).unwrap();
+            let errors = validate_inputs(
+                &node,
+                action,
+                &inputs,
+                &responses,
+                "p1",
+                with_table.then_some(&table),
+            );
+
+            let expected: Vec<FieldError> = expected
+                .into_iter()
+                .map(|(field, message)| FieldError {
+                    field: field.to_string(),
+                    message: message.to_string(),
+                })
+                .collect();
+            assert_eq!(errors, expected, "{inputs:?}");
+        }
+    }
+
+    #[test]
+    fn reads_the_table_from_facts() {
+        let facts: HashMap<String, Value> = serde_json::from_value(json!({
+            "table": { "players": ["p1", "p2"], "middle": ["m1"] },
+        }))
+        .unwrap();
+        let table = Table::from_facts(&facts).unwrap();
+        assert_eq!(table.players, vec!["p1", "p2"]);
+        assert_eq!(table.middle, vec!["m1"]);
+
+        let malformed: HashMap<String, Value> =
+            serde_json::from_value(json!({ "table": { "players": [1], "middle": [] } })).unwrap();
+        assert!(Table::from_facts(&malformed).is_none());
+        assert!(Table::from_facts(&HashMap::new()).is_none());
+    }
+}
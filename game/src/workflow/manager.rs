@@ -1,16 +1,26 @@
 use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 use tokio::sync::Mutex;
+use tokio::time::Instant;
 
+use crate::error::ServicesError;
 use crate::workflow::WorkflowPredicate;
 
+use super::expression::{Expression, ExpressionError};
+use super::facts::FactProvider;
+use super::input_validator::{FieldError, Table, format_field_errors, validate_inputs};
 use super::server_action::{ServerActionContext, ServerActionHandler, ServerActionResult};
-use super::service::WorkflowResource;
+use super::service::{WorkflowResource, WorkflowTree};
+use super::store::{InMemoryStore, WorkflowStore};
+use super::validator::{Diagnostic, format_diagnostics, validate_definition};
 use super::{
-    ActionType, CreateWorkflowDefinition, NodeCondition, UserWorkflowPreferences,
-    WorkflowDefinition, WorkflowNode, WorkflowState,
+    ActionType, CancelReason, CreateWorkflowDefinition, InputType, NodeCondition, OnComplete,
+    PendingWait, ResponseVisibility, TimeoutAction, UserWorkflowPreferences, WorkflowDefinition,
+    WorkflowNode, WorkflowState,
 };
 
 #[derive(Debug, Error)]
@@ -38,12 +48,67 @@ pub enum WorkflowError {
 
     #[error("Invalid state")]
     InvalidState,
+
+    #[error("User {0} is not allowed to act on this workflow")]
+    Forbidden(String),
+
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    #[error("Invalid workflow definition: {}", format_diagnostics(.0))]
+    InvalidDefinition(Vec<Diagnostic>),
+
+    #[error("Workflow {0} would end up waiting on itself")]
+    WaitCycle(String),
+
+    #[error("Invalid input: {}", format_field_errors(.0))]
+    InvalidInput(Vec<FieldError>),
+
+    #[error("Could not compute {0}: {1}")]
+    Expression(String, ExpressionError),
+}
+
+fn storage_error(e: ServicesError) -> WorkflowError {
+    WorkflowError::Storage(e.to_string())
 }
 
 #[derive(Debug, Clone)]
 pub enum WorkflowEvent {
-    WorkflowStarted { resource: WorkflowResource },
-    WorkflowUpdated { resource: WorkflowResource },
+    WorkflowStarted {
+        resource: WorkflowResource,
+    },
+    WorkflowUpdated {
+        resource: WorkflowResource,
+    },
+    ExternalServerActionRequested {
+        token: String,
+        action_id: String,
+        resource: WorkflowResource,
+    },
+    ActionProcessed {
+        instance_id: String,
+        user_id: String,
+        action_id: String,
+        inputs: HashMap<String, serde_json::Value>,
+    },
+    ServerActionCompleted {
+        instance_id: String,
+        action_id: String,
+        result: ServerActionResult,
+    },
+    WorkflowCancelled {
+        resource: WorkflowResource,
+    },
+    NodeReminder {
+        resource: WorkflowResource,
+        remaining_seconds: u64,
+    },
+    NodeExpired {
+        instance_id: String,
+        user_id: String,
+        action_id: String,
+        inputs: HashMap<String, serde_json::Value>,
+    },
 }
 
 #[derive(Debug)]
@@ -111,6 +176,129 @@ impl EventManager {
         let event = WorkflowEvent::WorkflowUpdated { resource };
         self.emit_event(event);
     }
+
+    pub fn workflow_cancelled(&self, resource: WorkflowResource) {
+        let event = WorkflowEvent::WorkflowCancelled { resource };
+        self.emit_event(event);
+    }
+
+    pub fn node_reminder(&self, resource: WorkflowResource, remaining_seconds: u64) {
+        let event = WorkflowEvent::NodeReminder {
+            resource,
+            remaining_seconds,
+        };
+        self.emit_event(event);
+    }
+
+    pub fn node_expired(
+        &self,
+        instance_id: String,
+        user_id: String,
+        action_id: String,
+        inputs: HashMap<String, serde_json::Value>,
+    ) {
+        let event = WorkflowEvent::NodeExpired {
+            instance_id,
+            user_id,
+            action_id,
+            inputs,
+        };
+        self.emit_event(event);
+    }
+
+    pub fn external_server_action_requested(
+        &self,
+        token: String,
+        action_id: String,
+        resource: WorkflowResource,
+    ) {
+        let event = WorkflowEvent::ExternalServerActionRequested {
+            token,
+            action_id,
+            resource,
+        };
+        self.emit_event(event);
+    }
+
+    pub fn action_processed(&self, action: ProcessedAction) {
+        let event = WorkflowEvent::ActionProcessed {
+            instance_id: action.instance_id,
+            user_id: action.user_id,
+            action_id: action.action_id,
+            inputs: action.inputs,
+        };
+        self.emit_event(event);
+    }
+
+    pub fn server_action_completed(
+        &self,
+        instance_id: String,
+        action_id: String,
+        result: ServerActionResult,
+    ) {
+        let event = WorkflowEvent::ServerActionCompleted {
+            instance_id,
+            action_id,
+            result,
+        };
+        self.emit_event(event);
+    }
+}
+
+/// An action a player took, reported once everything it set off went through.
+#[derive(Debug, Clone)]
+pub struct ProcessedAction {
+    pub instance_id: String,
+    pub user_id: String,
+    pub action_id: String,
+    pub inputs: HashMap<String, serde_json::Value>,
+}
+
+/// One stay of a workflow on a node. Going back and forth through the history
+/// can repeat a visit, anything else makes a new one.
+#[derive(Debug, Clone, PartialEq)]
+struct NodeVisit {
+    node_id: String,
+    depth: usize,
+}
+
+impl NodeVisit {
+    fn of(state: &WorkflowState) -> Self {
+        NodeVisit {
+            node_id: state.current_node_id.clone(),
+            depth: state.node_history.len(),
+        }
+    }
+}
+
+/// How a waiting workflow picks up once what it waits for completes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WaitResume {
+    pub inject_workflow_as: Option<String>,
+    pub on_complete: Option<OnComplete>,
+    /// Whether the injected workflow keeps the responses only its owner may
+    /// see.
+    #[serde(default)]
+    pub observe: bool,
+}
+
+/// Waiting workflows by the instance they wait on, with how to resume them.
+pub type ResponseWaits = HashMap<String, (String, WaitResume)>;
+
+/// Waiting workflows by their own instance, with what they wait for.
+pub type PredicateWaits = HashMap<String, (WorkflowPredicate, WaitResume)>;
+
+/// Everything the manager needs to pick a game's workflows back up: the
+/// definitions, the running instances, who is waiting on what and who answers
+/// which external server action.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkflowSnapshot {
+    pub definitions: HashMap<String, WorkflowDefinition>,
+    pub instances: HashMap<String, WorkflowState>,
+    pub waiting_for_response: ResponseWaits,
+    pub waiting_for_predicate: PredicateWaits,
+    #[serde(default)]
+    pub external_server_actions: HashSet<(String, String)>,
 }
 
 pub struct WorkflowManager {
@@ -118,10 +306,14 @@ pub struct WorkflowManager {
     pub(crate) active_workflows: Arc<Mutex<HashMap<String, WorkflowState>>>,
     user_preferences: Arc<Mutex<HashMap<(String, String), UserWorkflowPreferences>>>,
     server_action_handlers: Arc<Mutex<HashMap<String, ServerActionHandler>>>,
-    waiting_for_response: Arc<Mutex<HashMap<String, (String, Option<String>)>>>,
-    waiting_for_predicate: Arc<Mutex<HashMap<String, (WorkflowPredicate, Option<String>)>>>,
+    waiting_for_response: Arc<Mutex<ResponseWaits>>,
+    waiting_for_predicate: Arc<Mutex<PredicateWaits>>,
     pub(crate) external_server_actions: Arc<Mutex<HashSet<(String, String)>>>,
+    // The node visit each workflow's timeout was last scheduled for.
+    node_visits: Arc<Mutex<HashMap<String, NodeVisit>>>,
     pub event_manager: Arc<Mutex<EventManager>>, // Add event manager to WorkflowManager
+    pub(crate) store: Arc<dyn WorkflowStore>,
+    fact_provider: Arc<Mutex<Option<Arc<dyn FactProvider>>>>,
 }
 
 impl std::fmt::Debug for WorkflowManager {
@@ -155,152 +347,452 @@ impl WorkflowManager {
     }
 
     pub fn new() -> Self {
+        Self::with_store(Arc::new(InMemoryStore::default()))
+    }
+
+    pub fn with_store(store: Arc<dyn WorkflowStore>) -> Self {
         WorkflowManager {
             workflows: Arc::new(Mutex::new(HashMap::new())),
             active_workflows: Arc::new(Mutex::new(HashMap::new())),
             user_preferences: Arc::new(Mutex::new(HashMap::new())),
             server_action_handlers: Arc::new(Mutex::new(HashMap::new())),
             external_server_actions: Arc::new(Mutex::new(HashSet::new())),
+            node_visits: Arc::new(Mutex::new(HashMap::new())),
             waiting_for_response: Arc::new(Mutex::new(HashMap::new())),
             waiting_for_predicate: Arc::new(Mutex::new(HashMap::new())),
             event_manager: Arc::new(Mutex::new(EventManager::new())),
+            store,
+            fact_provider: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub async fn set_fact_provider(&self, provider: Arc<dyn FactProvider>) {
+        *self.fact_provider.lock().await = Some(provider);
+    }
+
+    /// Load definitions, instances and preferences saved by a previous run,
+    /// along with what the waiting instances wait for. Nothing runs until
+    /// [`WorkflowManager::resume_restored`].
+    pub async fn restore(&self) -> Result<(), WorkflowError> {
+        let definitions = self.store.load_definitions().await.map_err(storage_error)?;
+        let instances = self.store.load_instances().await.map_err(storage_error)?;
+        let preferences = self.store.load_preferences().await.map_err(storage_error)?;
+
+        println!(
+            "Restored {} workflow definitions and {} workflow instances",
+            definitions.len(),
+            instances.len()
+        );
+
+        {
+            let mut waiting_for_response = self.waiting_for_response.lock().await;
+            let mut waiting_for_predicate = self.waiting_for_predicate.lock().await;
+            for state in instances.values() {
+                if state.completed || !state.waiting {
+                    continue;
+                }
+                match state.wait.clone() {
+                    Some(PendingWait::Workflow {
+                        instance_id,
+                        resume,
+                    }) => {
+                        waiting_for_response
+                            .insert(instance_id, (state.instance_id.clone(), resume));
+                    }
+                    Some(PendingWait::Predicate { predicate, resume }) => {
+                        waiting_for_predicate
+                            .insert(state.instance_id.clone(), (predicate, resume));
+                    }
+                    None => eprintln!(
+                        "Workflow {} was waiting but did not record on what",
+                        state.instance_id
+                    ),
+                }
+            }
+        }
+
+        self.workflows.lock().await.extend(definitions);
+        self.active_workflows.lock().await.extend(instances);
+        self.user_preferences.lock().await.extend(preferences);
+
+        Ok(())
+    }
+
+    pub async fn snapshot(&self) -> WorkflowSnapshot {
+        WorkflowSnapshot {
+            definitions: self.workflows.lock().await.clone(),
+            instances: self.active_workflows.lock().await.clone(),
+            waiting_for_response: self.waiting_for_response.lock().await.clone(),
+            waiting_for_predicate: self.waiting_for_predicate.lock().await.clone(),
+            external_server_actions: self.external_server_actions.lock().await.clone(),
+        }
+    }
+
+    /// Replace the in-memory workflows with `snapshot`. Server action
+    /// handlers are code and must be registered separately.
+    pub async fn restore_snapshot(&self, snapshot: WorkflowSnapshot) -> Result<(), WorkflowError> {
+        for state in snapshot.instances.values() {
+            self.persist_state(state).await?;
         }
+
+        *self.workflows.lock().await = snapshot.definitions;
+        *self.active_workflows.lock().await = snapshot.instances;
+        *self.waiting_for_response.lock().await = snapshot.waiting_for_response;
+        *self.waiting_for_predicate.lock().await = snapshot.waiting_for_predicate;
+        *self.external_server_actions.lock().await = snapshot.external_server_actions;
+
+        Ok(())
+    }
+
+    pub(crate) async fn persist_state(&self, state: &WorkflowState) -> Result<(), WorkflowError> {
+        self.store.save_instance(state).await.map_err(storage_error)
     }
 
     pub async fn check_for_waiting(&self, instance_id: &str) {
-        let resource = self
-            .get_workflow_resource(instance_id)
-            .await
-            .expect("Not found??");
+        let Some(resource) = self.get_workflow_resource(instance_id).await else {
+            return;
+        };
 
         if !resource.completed {
             return;
         }
-        println!("checking for waiting within {:?}", resource);
 
         let response = self.waiting_for_response.lock().await.remove(instance_id);
-        println!("Response: {:?}", response);
-        if let Some((waiting_instance_id, input_key)) = response {
-            {
-                let resource = self
-                    .get_workflow_resource(instance_id)
-                    .await
-                    .expect("Not found??");
-                let state = {
-                    if let Some(state) = self
-                        .active_workflows
-                        .lock()
-                        .await
-                        .get_mut(&waiting_instance_id)
-                    {
-                        state.waiting = false;
-
-                        if let Some(key) = input_key {
-                            let resource_value = serde_json::to_value(&resource)
-                                .expect("Failed to serialize WorkflowResource");
-                            state.responses.insert(key, resource_value);
-                        }
-
-                        let workflow_definition = {
-                            let wf = self.workflows.lock().await;
-                            wf.get(&state.workflow_id)
-                                .expect("Unable to find workflow definition")
-                                .clone()
-                        };
-                        let current_node = workflow_definition
-                            .nodes
-                            .get(&state.current_node_id)
-                            .expect("what");
-                        let valid_child = self
-                            .find_valid_child_node(&workflow_definition, current_node, &state)
-                            .expect("No next child found");
-                        state.node_history.push(state.current_node_id.clone());
-                        state.current_node_id = valid_child.id.clone();
-                        state.updated_at = chrono::Utc::now();
+        if let Some((waiting_instance_id, resume)) = response {
+            self.wake_waiting(&waiting_instance_id, resume, &resource)
+                .await;
+        }
 
-                        Some(state.clone())
-                    } else {
-                        None
-                    }
+        for (waiting_instance_id, resume) in self.take_predicate_waits(&resource).await {
+            self.wake_waiting(&waiting_instance_id, resume, &resource)
+                .await;
+        }
+    }
+
+    /// Remove the wait of every workflow whose predicate matches `resource`,
+    /// so a later completion cannot resume it a second time.
+    async fn take_predicate_waits(&self, resource: &WorkflowResource) -> Vec<(String, WaitResume)> {
+        let mut waiting = self.waiting_for_predicate.lock().await;
+        let ids: Vec<String> = waiting
+            .iter()
+            .filter(|(waiting_id, (predicate, _))| {
+                **waiting_id != resource.instance_id && Self::predicate_matches(predicate, resource)
+            })
+            .map(|(waiting_id, _)| waiting_id.clone())
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| waiting.remove(&id).map(|(_, resume)| (id, resume)))
+            .collect()
+    }
+
+    /// Start the clock for the node `instance_id` is on, unless it is already
+    /// running for this visit. Reminders and the expiry are skipped once the
+    /// workflow has moved on.
+    pub async fn schedule_node_timeout(self: &Arc<Self>, instance_id: &str) {
+        let Some(state) = self.active_workflows.lock().await.get(instance_id).cloned() else {
+            return;
+        };
+        if state.completed {
+            self.node_visits.lock().await.remove(instance_id);
+            return;
+        }
+
+        let visit = NodeVisit::of(&state);
+        {
+            let mut visits = self.node_visits.lock().await;
+            if visits.get(instance_id) == Some(&visit) {
+                return;
+            }
+            visits.insert(instance_id.to_string(), visit.clone());
+        }
+
+        let timeout = self
+            .workflows
+            .lock()
+            .await
+            .get(&state.workflow_id)
+            .and_then(|workflow| workflow.nodes.get(&visit.node_id))
+            .and_then(|node| node.timeout.clone());
+        let Some(timeout) = timeout else {
+            return;
+        };
+
+        let manager = Arc::clone(self);
+        let instance_id = instance_id.to_string();
+        tokio::spawn(async move {
+            let deadline = Instant::now() + Duration::from_secs(timeout.after_seconds);
+            loop {
+                let next = match timeout.remind_every_seconds.filter(|every| *every > 0) {
+                    Some(every) => (Instant::now() + Duration::from_secs(every)).min(deadline),
+                    None => deadline,
                 };
+                tokio::time::sleep_until(next).await;
 
-                if let Some(state) = state {
-                    self.update_state(&waiting_instance_id, state)
-                        .await
-                        .expect("unable to update state");
+                if !manager.is_current_visit(&instance_id, &visit).await {
+                    return;
                 }
+                if next >= deadline {
+                    break;
+                }
+                manager
+                    .remind(&instance_id, (deadline - next).as_secs())
+                    .await;
             }
 
-            let resource = self
-                .get_workflow_resource(&waiting_instance_id)
-                .await
-                .expect("Not found??");
-            self.event_manager.lock().await.workflow_updated(resource);
-            println!("Refreshed {waiting_instance_id}");
+            if let Err(e) = manager.expire_node(&instance_id, &timeout.on_expiry).await {
+                eprintln!("Unable to time out workflow {instance_id}: {}", e);
+            }
+        });
+    }
+
+    /// Start the node clocks again after a restore, and wake the waiters whose
+    /// workflow completed before they could be woken.
+    pub async fn resume_restored(self: &Arc<Self>) {
+        self.schedule_all_node_timeouts().await;
+
+        // Any workflow that finished may be what another one waits on, by
+        // instance or by predicate.
+        let finished: Vec<String> = self
+            .active_workflows
+            .lock()
+            .await
+            .values()
+            .filter(|state| state.completed)
+            .map(|state| state.instance_id.clone())
+            .collect();
+        for instance_id in finished {
+            self.check_for_waiting(&instance_id).await;
+        }
+    }
+
+    /// Schedule the timeouts of every running workflow, for instance after a
+    /// restore. Clocks start over from the full timeout.
+    pub async fn schedule_all_node_timeouts(self: &Arc<Self>) {
+        let instance_ids: Vec<String> =
+            self.active_workflows.lock().await.keys().cloned().collect();
+        for instance_id in instance_ids {
+            self.schedule_node_timeout(&instance_id).await;
         }
+    }
+
+    async fn is_current_visit(&self, instance_id: &str, visit: &NodeVisit) -> bool {
+        match self.active_workflows.lock().await.get(instance_id) {
+            Some(state) => !state.completed && NodeVisit::of(state) == *visit,
+            None => false,
+        }
+    }
 
-        let response = self
-            .waiting_for_predicate
+    async fn remind(&self, instance_id: &str, remaining_seconds: u64) {
+        let Some(resource) = self.get_workflow_resource(instance_id).await else {
+            return;
+        };
+        // Nothing for the player to do while the workflow waits.
+        if resource.waiting {
+            return;
+        }
+        self.event_manager
             .lock()
             .await
-            .clone()
-            .into_iter()
-            .find(|(key, (predicate, response_key))| match predicate {
-                WorkflowPredicate::ByUserId(user_id) => &resource.user_id == user_id,
-            });
+            .node_reminder(resource, remaining_seconds);
+    }
 
-        println!("found predicate? {:?}", response);
+    /// Do what the node asks for once its time is up. Actions are handed to
+    /// whoever listens for `NodeExpired` so they run like the owner's own.
+    async fn expire_node(
+        &self,
+        instance_id: &str,
+        on_expiry: &TimeoutAction,
+    ) -> Result<(), WorkflowError> {
+        let state = self
+            .active_workflows
+            .lock()
+            .await
+            .get(instance_id)
+            .cloned()
+            .ok_or(WorkflowError::WorkflowInstanceNotFound)?;
+        println!(
+            "workflow {instance_id} timed out on {}",
+            state.current_node_id
+        );
 
-        if let Some((waiting_instance_id, (_, input_key))) = response {
-            let state = {
-                if let Some(state) = self
-                    .active_workflows
-                    .lock()
-                    .await
-                    .get_mut(&waiting_instance_id)
-                {
-                    state.waiting = false;
+        if let TimeoutAction::Cancel = on_expiry {
+            return self
+                .cancel_workflow(instance_id, CancelReason::TimedOut)
+                .await;
+        }
+        if state.waiting {
+            return Err(WorkflowError::InvalidState);
+        }
 
-                    if let Some(key) = input_key {
-                        let resource_value = serde_json::to_value(&resource)
-                            .expect("Failed to serialize WorkflowResource");
-                        state.responses.insert(key, resource_value);
-                    }
+        let node = self
+            .workflows
+            .lock()
+            .await
+            .get(&state.workflow_id)
+            .ok_or(WorkflowError::WorkflowNotFound)?
+            .nodes
+            .get(&state.current_node_id)
+            .cloned()
+            .ok_or(WorkflowError::NodeNotFound)?;
 
-                    let workflow_definition = {
-                        let wf = self.workflows.lock().await;
-                        wf.get(&state.workflow_id)
-                            .expect("Unable to find workflow definition")
-                            .clone()
-                    };
-                    let current_node = workflow_definition
-                        .nodes
-                        .get(&state.current_node_id)
-                        .expect("what");
-                    let valid_child = self
-                        .find_valid_child_node(&workflow_definition, current_node, &state)
-                        .expect("No next child found");
-                    state.node_history.push(state.current_node_id.clone());
-                    state.current_node_id = valid_child.id.clone();
-                    state.updated_at = chrono::Utc::now();
+        let action_id = match on_expiry {
+            TimeoutAction::TakeAction(action_id) => action_id.clone(),
+            _ => node
+                .actions
+                .first()
+                .ok_or(WorkflowError::ActionNotFound)?
+                .id
+                .clone(),
+        };
+        let inputs = node
+            .inputs
+            .iter()
+            .filter_map(|input| {
+                input
+                    .default_value
+                    .clone()
+                    .map(|value| (input.id.clone(), value))
+            })
+            .collect();
+
+        self.event_manager.lock().await.node_expired(
+            instance_id.to_string(),
+            state.user_id,
+            action_id,
+            inputs,
+        );
 
-                    Some(state.clone())
-                } else {
-                    None
-                }
+        Ok(())
+    }
+
+    fn predicate_matches(predicate: &WorkflowPredicate, resource: &WorkflowResource) -> bool {
+        match predicate {
+            WorkflowPredicate::ByUserId(user_id) => &resource.user_id == user_id,
+            WorkflowPredicate::ByNodeId(node_id) => &resource.current_node_id == node_id,
+            WorkflowPredicate::ByWorkflowId(instance_id) => &resource.instance_id == instance_id,
+            WorkflowPredicate::ByDefinitionId(workflow_id) => &resource.workflow_id == workflow_id,
+            WorkflowPredicate::ByResponse { field, value } => {
+                Self::get_nested_value(&resource.responses, field) == Some(value)
+            }
+            WorkflowPredicate::And(predicates) => predicates
+                .iter()
+                .all(|predicate| Self::predicate_matches(predicate, resource)),
+            WorkflowPredicate::Or(predicates) => predicates
+                .iter()
+                .any(|predicate| Self::predicate_matches(predicate, resource)),
+        }
+    }
+
+    /// Resume a workflow that was waiting on `resource`, injecting it when
+    /// asked to and then doing what `on_complete` says.
+    async fn wake_waiting(
+        &self,
+        waiting_instance_id: &str,
+        resume: WaitResume,
+        resource: &WorkflowResource,
+    ) {
+        match self
+            .resume_waiting(waiting_instance_id, resume, resource)
+            .await
+        {
+            Ok(()) => println!("Refreshed {waiting_instance_id}"),
+            Err(e) => eprintln!("Unable to resume workflow {waiting_instance_id}: {}", e),
+        }
+    }
+
+    async fn resume_waiting(
+        &self,
+        waiting_instance_id: &str,
+        resume: WaitResume,
+        resource: &WorkflowResource,
+    ) -> Result<(), WorkflowError> {
+        let mut state = self
+            .active_workflows
+            .lock()
+            .await
+            .get(waiting_instance_id)
+            .cloned()
+            .ok_or(WorkflowError::WorkflowInstanceNotFound)?;
+
+        state.waiting = false;
+        state.wait = None;
+
+        if let Some(key) = resume.inject_workflow_as {
+            let recipient_id = if resume.observe {
+                &resource.user_id
+            } else {
+                &state.user_id
             };
-            if let Some(state) = state {
-                self.update_state(&waiting_instance_id, state)
-                    .await
-                    .expect("unable to update state");
+            let resource_value = serde_json::to_value(resource.for_recipient(recipient_id))
+                .map_err(|e| WorkflowError::ServerActionFailed(e.to_string()))?;
+            state.responses.insert(key.clone(), resource_value);
+            state
+                .response_visibility
+                .insert(key, ResponseVisibility::Owner);
+        }
+
+        let workflow_definition = self
+            .workflows
+            .lock()
+            .await
+            .get(&state.workflow_id)
+            .cloned()
+            .ok_or(WorkflowError::WorkflowNotFound)?;
+        let mut server_action = None;
+        match resume.on_complete.unwrap_or(OnComplete::NextNode) {
+            OnComplete::NextNode => {
+                let current_node = workflow_definition
+                    .nodes
+                    .get(&state.current_node_id)
+                    .ok_or(WorkflowError::NodeNotFound)?;
+                let valid_child = self
+                    .find_valid_child_node(&workflow_definition, current_node, &state)
+                    .await?;
+                state.node_history.push(state.current_node_id.clone());
+                state.current_node_id = valid_child.id.clone();
+                self.enter_node(&workflow_definition, &mut state).await?;
             }
-            let resource = self
-                .get_workflow_resource(&waiting_instance_id)
-                .await
-                .expect("Not found??");
-            self.event_manager.lock().await.workflow_updated(resource);
-            println!("Refreshed {waiting_instance_id}");
+            OnComplete::GoToNode(node_id) => {
+                if !workflow_definition.nodes.contains_key(&node_id) {
+                    return Err(WorkflowError::NodeNotFound);
+                }
+                state.node_history.push(state.current_node_id.clone());
+                state.current_node_id = node_id;
+                self.enter_node(&workflow_definition, &mut state).await?;
+            }
+            OnComplete::Submit => state.completed = true,
+            OnComplete::Cancel => {
+                self.update_state(waiting_instance_id, state).await?;
+                return self
+                    .cancel_workflow(waiting_instance_id, CancelReason::Cancelled)
+                    .await;
+            }
+            OnComplete::RunServerAction(action_id) => server_action = Some(action_id),
+        }
+        state.updated_at = chrono::Utc::now();
+
+        let workflow_id = state.workflow_id.clone();
+        self.update_state(waiting_instance_id, state).await?;
+
+        if let Some(action_id) = server_action {
+            // Refreshes the workflow itself once the action has run.
+            self.execute_server_action(
+                waiting_instance_id.to_string(),
+                &workflow_id,
+                &action_id,
+                None,
+            )
+            .await?;
+            return Ok(());
         }
+
+        let resource = self
+            .get_workflow_resource(waiting_instance_id)
+            .await
+            .ok_or(WorkflowError::WorkflowInstanceNotFound)?;
+        self.event_manager.lock().await.workflow_updated(resource);
+
+        Ok(())
     }
 
     pub async fn process_external_server_action(
@@ -319,11 +811,86 @@ impl WorkflowManager {
         Ok((token, state.user_id.clone()))
     }
 
+    pub async fn authorize_user(
+        &self,
+        instance_id: &str,
+        user_id: &str,
+    ) -> Result<(), WorkflowError> {
+        let active_workflows = self.active_workflows.lock().await;
+        let state = active_workflows
+            .get(instance_id)
+            .ok_or(WorkflowError::WorkflowInstanceNotFound)?;
+
+        if state.user_id == user_id || state.delegates.iter().any(|id| id == user_id) {
+            Ok(())
+        } else {
+            Err(WorkflowError::Forbidden(user_id.to_string()))
+        }
+    }
+
+    pub async fn delegate_workflow(
+        &self,
+        instance_id: &str,
+        owner_id: &str,
+        delegate_id: &str,
+    ) -> Result<(), WorkflowError> {
+        let mut active_workflows = self.active_workflows.lock().await;
+        let state = active_workflows
+            .get_mut(instance_id)
+            .ok_or(WorkflowError::WorkflowInstanceNotFound)?;
+
+        if state.user_id != owner_id {
+            return Err(WorkflowError::Forbidden(owner_id.to_string()));
+        }
+
+        if !state.delegates.iter().any(|id| id == delegate_id) {
+            state.delegates.push(delegate_id.to_string());
+            state.updated_at = chrono::Utc::now();
+        }
+
+        let state = state.clone();
+        drop(active_workflows);
+        self.persist_state(&state).await
+    }
+
+    pub async fn revoke_delegation(
+        &self,
+        instance_id: &str,
+        owner_id: &str,
+        delegate_id: &str,
+    ) -> Result<(), WorkflowError> {
+        let mut active_workflows = self.active_workflows.lock().await;
+        let state = active_workflows
+            .get_mut(instance_id)
+            .ok_or(WorkflowError::WorkflowInstanceNotFound)?;
+
+        if state.user_id != owner_id {
+            return Err(WorkflowError::Forbidden(owner_id.to_string()));
+        }
+
+        state.delegates.retain(|id| id != delegate_id);
+        state.updated_at = chrono::Utc::now();
+
+        let state = state.clone();
+        drop(active_workflows);
+        self.persist_state(&state).await
+    }
+
     pub async fn register_workflow_definition(
         &self,
         user_id: &str,
         workflow: CreateWorkflowDefinition,
     ) -> Result<String, WorkflowError> {
+        let (errors, warnings): (Vec<_>, Vec<_>) = validate_definition(&workflow)
+            .into_iter()
+            .partition(Diagnostic::is_error);
+        for warning in &warnings {
+            println!("Workflow definition {}: {}", workflow.id, warning);
+        }
+        if !errors.is_empty() {
+            return Err(WorkflowError::InvalidDefinition(errors));
+        }
+
         {
             let server_action_handlers = self.server_action_handlers.lock().await;
             let external_server_actions = self.external_server_actions.lock().await;
@@ -350,20 +917,26 @@ impl WorkflowManager {
             initial_node_id: workflow.initial_node_id.clone(),
             nodes: workflow.nodes.clone(),
             server_actions: workflow.server_actions.clone(),
+            response_visibility: workflow.response_visibility.clone(),
         };
 
         let final_id = format!("user-{}-wf-{}", user_id, workflow.id);
         if let Some(workflow) = workflows.get_mut(&final_id) {
             if workflow.owner_id == Some(user_id.to_string()) {
-                *workflow = definition;
+                *workflow = definition.clone();
             } else {
                 return Err(WorkflowError::ServerActionFailed(
                     "You are not the owner of that workflow.".to_string(),
                 ));
             }
         } else {
-            workflows.insert(final_id.clone(), definition);
+            workflows.insert(final_id.clone(), definition.clone());
         }
+        drop(workflows);
+        self.store
+            .save_definition(&final_id, &definition)
+            .await
+            .map_err(storage_error)?;
         println!("Registered workflow definition with ID {}", final_id);
 
         Ok(final_id)
@@ -399,15 +972,21 @@ impl WorkflowManager {
         instance_id: &str,
         state: WorkflowState,
     ) -> Result<(), WorkflowError> {
-        let mut workflows = self.active_workflows.lock().await;
-        if let Some(old_state) = workflows.get_mut(instance_id) {
-            *old_state = state.clone();
-            Ok(())
-        } else {
-            Err(WorkflowError::WorkflowNotFound)
+        {
+            let mut workflows = self.active_workflows.lock().await;
+            if let Some(old_state) = workflows.get_mut(instance_id) {
+                *old_state = state.clone();
+            } else {
+                return Err(WorkflowError::WorkflowNotFound);
+            }
         }
+
+        self.persist_state(&state).await
     }
 
+    /// Put the workflow on `target_node_id` and tell its players. The node is
+    /// not entered again: [`WorkflowManager::process_action`] has done that
+    /// for the node it moved to.
     pub async fn show_node(
         &self,
         instance_id: &str,
@@ -448,41 +1027,59 @@ impl WorkflowManager {
         Ok(())
     }
 
-    pub async fn start_workflow(
+    pub async fn start_workflow(
+        &self,
+        workflow_id: &str,
+        user_id: &str,
+        inputs: HashMap<String, serde_json::Value>,
+    ) -> Result<String, WorkflowError> {
+        self.start_linked_workflow(workflow_id, user_id, inputs, None)
+            .await
+    }
+
+    /// Start a workflow as a child of `parent_instance_id`. Only the child
+    /// records the link; the caller adds it to the parent's children.
+    async fn start_linked_workflow(
         &self,
         workflow_id: &str,
         user_id: &str,
         inputs: HashMap<String, serde_json::Value>,
+        parent_instance_id: Option<&str>,
     ) -> Result<String, WorkflowError> {
-        let state = {
-            let hash_map = self.workflows.lock().await;
-            let workflow = hash_map
-                .get(workflow_id)
-                .ok_or(WorkflowError::WorkflowNotFound)?;
-
-            let mut responses = workflow.responses.clone();
-            responses.extend(inputs);
-
-            let instance_id = ulid::Ulid::new().to_string();
-            let state = WorkflowState {
-                workflow_id: workflow_id.to_string(),
-                instance_id: instance_id.clone(),
-                user_id: user_id.to_string(),
-                current_node_id: workflow.initial_node_id.clone(),
-                node_history: Vec::new(),
-                responses,
-                message_id: None,
-                complete_message: None,
-                completed: false,
-                waiting: false,
-                created_at: chrono::Utc::now(),
-                updated_at: chrono::Utc::now(),
-            };
+        let workflow = self
+            .workflows
+            .lock()
+            .await
+            .get(workflow_id)
+            .ok_or(WorkflowError::WorkflowNotFound)?
+            .clone();
+        let mut responses = workflow.responses.clone();
+        responses.extend(inputs);
 
-            state
+        let instance_id = ulid::Ulid::new().to_string();
+        let mut state = WorkflowState {
+            workflow_id: workflow_id.to_string(),
+            instance_id: instance_id.clone(),
+            user_id: user_id.to_string(),
+            current_node_id: workflow.initial_node_id.clone(),
+            node_history: Vec::new(),
+            responses,
+            message_id: None,
+            complete_message: None,
+            completed: false,
+            cancelled: None,
+            parent_instance_id: parent_instance_id.map(str::to_string),
+            child_instance_ids: Vec::new(),
+            waiting: false,
+            delegates: Vec::new(),
+            response_visibility: workflow.response_visibility.clone(),
+            wait: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
         };
+        self.enter_node(&workflow, &mut state).await?;
 
-        let instance_id = state.instance_id.clone();
+        self.persist_state(&state).await?;
         {
             let mut active_workflows = self.active_workflows.lock().await;
             active_workflows.insert(instance_id.clone(), state);
@@ -498,12 +1095,234 @@ impl WorkflowManager {
         Ok(instance_id)
     }
 
+    /// Start a workflow on behalf of `parent_instance_id`, so cancelling the
+    /// parent cancels it as well.
+    pub async fn start_child_workflow(
+        &self,
+        parent_instance_id: &str,
+        workflow_id: &str,
+        user_id: &str,
+        inputs: HashMap<String, serde_json::Value>,
+    ) -> Result<String, WorkflowError> {
+        let mut parent = self
+            .active_workflows
+            .lock()
+            .await
+            .get(parent_instance_id)
+            .cloned()
+            .ok_or(WorkflowError::WorkflowInstanceNotFound)?;
+
+        let instance_id = self
+            .start_linked_workflow(workflow_id, user_id, inputs, Some(parent_instance_id))
+            .await?;
+        parent.child_instance_ids.push(instance_id.clone());
+        self.update_state(parent_instance_id, parent).await?;
+
+        Ok(instance_id)
+    }
+
+    /// The tree of workflows `instance_id` belongs to, from its root down.
+    pub async fn workflow_tree(&self, instance_id: &str) -> Result<WorkflowTree, WorkflowError> {
+        let states = self.active_workflows.lock().await.clone();
+
+        let mut root_id = instance_id;
+        let mut visited = HashSet::new();
+        while let Some(parent_id) = states
+            .get(root_id)
+            .ok_or(WorkflowError::WorkflowInstanceNotFound)?
+            .parent_instance_id
+            .as_deref()
+        {
+            if !visited.insert(root_id) || !states.contains_key(parent_id) {
+                break;
+            }
+            root_id = parent_id;
+        }
+
+        let mut resources = HashMap::new();
+        let mut pending = vec![root_id];
+        while let Some(id) = pending.pop() {
+            if resources.contains_key(id) {
+                continue;
+            }
+            let resource = self
+                .get_workflow_resource(id)
+                .await
+                .ok_or(WorkflowError::WorkflowInstanceNotFound)?;
+            resources.insert(id, resource);
+            if let Some(state) = states.get(id) {
+                pending.extend(state.child_instance_ids.iter().map(String::as_str));
+            }
+        }
+
+        fn build(
+            id: &str,
+            states: &HashMap<String, WorkflowState>,
+            resources: &mut HashMap<&str, WorkflowResource>,
+        ) -> Option<WorkflowTree> {
+            // Taking the resource out means a node can only appear once.
+            let workflow = resources.remove(id)?;
+            let children = states
+                .get(id)
+                .map(|state| {
+                    state
+                        .child_instance_ids
+                        .iter()
+                        .filter_map(|child_id| build(child_id, states, resources))
+                        .collect()
+                })
+                .unwrap_or_default();
+            Some(WorkflowTree { workflow, children })
+        }
+
+        build(root_id, &states, &mut resources).ok_or(WorkflowError::WorkflowInstanceNotFound)
+    }
+
+    /// Cancel a workflow together with its children and the workflows waiting
+    /// on it, whether they wait on the instance or on a predicate it matches.
+    /// Workflows that already completed keep their result but are no longer
+    /// waited on.
+    pub async fn cancel_workflow(
+        &self,
+        instance_id: &str,
+        reason: CancelReason,
+    ) -> Result<(), WorkflowError> {
+        let mut pending = vec![instance_id.to_string()];
+        let mut visited = HashSet::new();
+
+        while let Some(id) = pending.pop() {
+            if !visited.insert(id.clone()) {
+                continue;
+            }
+            pending.extend(self.unlink(&id).await);
+
+            let Some(mut state) = self.active_workflows.lock().await.get(&id).cloned() else {
+                continue;
+            };
+            if state.completed {
+                continue;
+            }
+            state.completed = true;
+            state.cancelled = Some(reason);
+            state.waiting = false;
+            state.wait = None;
+            state.updated_at = chrono::Utc::now();
+            self.update_state(&id, state).await?;
+
+            let resource = self
+                .get_workflow_resource(&id)
+                .await
+                .ok_or(WorkflowError::WorkflowInstanceNotFound)?;
+            println!("cancelled workflow {id}");
+            pending.extend(
+                self.take_predicate_waits(&resource)
+                    .await
+                    .into_iter()
+                    .map(|(waiting_id, _)| waiting_id),
+            );
+            self.event_manager.lock().await.workflow_cancelled(resource);
+        }
+
+        Ok(())
+    }
+
+    /// Drop every wait involving `instance_id` and return the workflows on
+    /// the other end along with its children.
+    async fn unlink(&self, instance_id: &str) -> Vec<String> {
+        let mut linked = Vec::new();
+
+        self.waiting_for_predicate.lock().await.remove(instance_id);
+
+        {
+            let mut waiting = self.waiting_for_response.lock().await;
+            if let Some((waiting_id, _)) = waiting.remove(instance_id) {
+                linked.push(waiting_id);
+            }
+            let awaited: Vec<String> = waiting
+                .iter()
+                .filter(|(_, (waiting_id, _))| waiting_id == instance_id)
+                .map(|(awaited_id, _)| awaited_id.clone())
+                .collect();
+            for awaited_id in awaited {
+                waiting.remove(&awaited_id);
+                linked.push(awaited_id);
+            }
+        }
+
+        if let Some(state) = self.active_workflows.lock().await.get(instance_id) {
+            linked.extend(state.child_instance_ids.iter().cloned());
+        }
+
+        linked
+    }
+
+    /// Whether letting `instance_id` wait on `predicate` could never resolve,
+    /// because every running workflow the predicate matches is the workflow
+    /// itself or one already waiting on it, directly or through others.
+    async fn wait_would_cycle(&self, instance_id: &str, predicate: &WorkflowPredicate) -> bool {
+        let running: Vec<String> = self
+            .active_workflows
+            .lock()
+            .await
+            .values()
+            .filter(|state| !state.completed)
+            .map(|state| state.instance_id.clone())
+            .collect();
+        let mut resources = HashMap::new();
+        for id in running {
+            if let Some(resource) = self.get_workflow_resource(&id).await {
+                resources.insert(id, resource);
+            }
+        }
+
+        let candidates: Vec<&str> = resources
+            .values()
+            .filter(|resource| Self::predicate_matches(predicate, resource))
+            .map(|resource| resource.instance_id.as_str())
+            .chain(predicate.instance_ids())
+            .collect();
+        if candidates.is_empty() {
+            return false;
+        }
+
+        let waiting_for_response = self.waiting_for_response.lock().await;
+        let waiting_for_predicate = self.waiting_for_predicate.lock().await;
+
+        let mut waiters = HashSet::new();
+        let mut pending = vec![instance_id.to_string()];
+        while let Some(id) = pending.pop() {
+            if !waiters.insert(id.clone()) {
+                continue;
+            }
+            if let Some((waiting_id, _)) = waiting_for_response.get(&id) {
+                pending.push(waiting_id.clone());
+            }
+            let resource = resources.get(&id);
+            pending.extend(
+                waiting_for_predicate
+                    .iter()
+                    .filter(|(_, (predicate, _))| {
+                        predicate.instance_ids().contains(&id.as_str())
+                            || resource.is_some_and(|resource| {
+                                Self::predicate_matches(predicate, resource)
+                            })
+                    })
+                    .map(|(waiting_id, _)| waiting_id.clone()),
+            );
+        }
+
+        candidates.iter().all(|id| waiters.contains(*id))
+    }
+
+    /// Apply an action to the workflow. What the action sets off is left to
+    /// the caller, which reports the returned [`ProcessedAction`] once that
+    /// went through as well.
     pub async fn process_action(
         &self,
         instance_id: String,
         action_id: &str,
         inputs: HashMap<String, serde_json::Value>,
-    ) -> Result<ActionProcessResult, WorkflowError> {
+    ) -> Result<(ActionProcessResult, ProcessedAction), WorkflowError> {
         let state = &mut {
             let mut active_workflows = self.active_workflows.lock().await;
             active_workflows
@@ -535,6 +1354,34 @@ impl WorkflowManager {
             .find(|a| a.id == action_id)
             .ok_or(WorkflowError::ActionNotFound)?;
 
+        let selects_cards = current_node
+            .inputs
+            .iter()
+            .any(|input| matches!(input.input_type, InputType::SelectCard { .. }));
+        let table = if selects_cards && !inputs.is_empty() {
+            Table::from_facts(&self.facts_for(&state.user_id).await)
+        } else {
+            None
+        };
+        let errors = validate_inputs(
+            current_node,
+            action,
+            &inputs,
+            &state.responses,
+            &state.user_id,
+            table.as_ref(),
+        );
+        if !errors.is_empty() {
+            return Err(WorkflowError::InvalidInput(errors));
+        }
+
+        let processed = ProcessedAction {
+            instance_id: instance_id.clone(),
+            user_id: state.user_id.clone(),
+            action_id: action_id.to_string(),
+            inputs: inputs.clone(),
+        };
+
         // Save inputs to state
         for (key, value) in inputs {
             state.responses.insert(key, value);
@@ -552,13 +1399,13 @@ impl WorkflowManager {
                             node_id: target_node.id.clone(),
                         })
                     } else {
-                        println!("lookn hre");
                         Err(WorkflowError::NodeNotFound)
                     }
                 } else {
                     // Find first valid child node
-                    let valid_child =
-                        self.find_valid_child_node(&workflow, current_node, &state)?;
+                    let valid_child = self
+                        .find_valid_child_node(&workflow, current_node, &state)
+                        .await?;
                     state.node_history.push(state.current_node_id.clone());
                     state.current_node_id = valid_child.id.clone();
                     state.updated_at = chrono::Utc::now();
@@ -594,7 +1441,7 @@ impl WorkflowManager {
                 ))
             }
             ActionType::Cancel => {
-                state.completed = true;
+                // Completed by `cancel_workflow` along with everything linked.
                 state.updated_at = chrono::Utc::now();
                 Ok(ActionProcessResult::WorkflowCancelled)
             }
@@ -661,18 +1508,69 @@ impl WorkflowManager {
             }
         }?;
 
+        if let ActionProcessResult::ShowNode { .. } = response {
+            self.enter_node(&workflow, state).await?;
+        }
+
         self.update_state(&instance_id, state.clone()).await?;
 
-        Ok(response)
+        Ok((response, processed))
+    }
+
+    /// Add the responses the current node computes on entry. Every expression
+    /// sees the responses as they were before the node was entered.
+    async fn enter_node(
+        &self,
+        definition: &WorkflowDefinition,
+        state: &mut WorkflowState,
+    ) -> Result<(), WorkflowError> {
+        let Some(node) = definition.nodes.get(&state.current_node_id) else {
+            return Ok(());
+        };
+        if node.compute.is_empty() {
+            return Ok(());
+        }
+
+        let facts = if node.compute.values().any(Expression::uses_facts) {
+            self.facts_for(&state.user_id).await
+        } else {
+            HashMap::new()
+        };
+
+        let mut computed = Vec::with_capacity(node.compute.len());
+        for (key, expression) in &node.compute {
+            let value = expression
+                .evaluate(&state.responses, &facts)
+                .map_err(|e| WorkflowError::Expression(key.clone(), e))?;
+            computed.push((key.clone(), value));
+        }
+        state.responses.extend(computed);
+
+        Ok(())
+    }
+
+    async fn facts_for(&self, user_id: &str) -> HashMap<String, serde_json::Value> {
+        let provider = self.fact_provider.lock().await.clone();
+        match provider {
+            Some(provider) => provider.facts(user_id).await,
+            None => HashMap::new(),
+        }
     }
 
-    fn evaluate_node_condition(&self, node: &WorkflowNode, state: &WorkflowState) -> bool {
-        match &node.condition {
-            Some(NodeCondition::ResponseExists(field)) => {
+    fn evaluate_condition(
+        &self,
+        condition: &NodeCondition,
+        state: &WorkflowState,
+        facts: &HashMap<String, serde_json::Value>,
+    ) -> bool {
+        let response = |field: &str| Self::get_nested_value(&state.responses, field);
+
+        match condition {
+            NodeCondition::ResponseExists(field) => {
                 Self::get_nested_value(&state.responses, field).is_some()
             }
 
-            Some(NodeCondition::ResponseEquals { field, value }) => {
+            NodeCondition::ResponseEquals { field, value } => {
                 match Self::get_nested_value(&state.responses, field) {
                     Some(response_value) => response_value == value,
                     None => {
@@ -682,14 +1580,56 @@ impl WorkflowManager {
                 }
             }
 
-            Some(NodeCondition::ResponseListNotEmpty(field)) => {
+            NodeCondition::ResponseListNotEmpty(field) => {
                 match Self::get_nested_value(&state.responses, field) {
                     Some(serde_json::Value::Array(arr)) => !arr.is_empty(),
                     _ => false,
                 }
             }
 
-            Some(NodeCondition::Always) | None => true,
+            NodeCondition::Always => true,
+
+            NodeCondition::And(conditions) => conditions
+                .iter()
+                .all(|condition| self.evaluate_condition(condition, state, facts)),
+
+            NodeCondition::Or(conditions) => conditions
+                .iter()
+                .any(|condition| self.evaluate_condition(condition, state, facts)),
+
+            NodeCondition::Not(condition) => !self.evaluate_condition(condition, state, facts),
+
+            NodeCondition::NumberCompare {
+                field,
+                comparison,
+                value,
+            } => response(field)
+                .and_then(serde_json::Value::as_f64)
+                .is_some_and(|number| comparison.compare(number, *value)),
+
+            NodeCondition::In { field, values } => {
+                response(field).is_some_and(|value| values.contains(value))
+            }
+
+            NodeCondition::ListLength {
+                field,
+                comparison,
+                length,
+            } => response(field)
+                .and_then(serde_json::Value::as_array)
+                .is_some_and(|list| comparison.compare(list.len(), *length)),
+
+            NodeCondition::StringMatches { field, mode, value } => response(field)
+                .and_then(serde_json::Value::as_str)
+                .is_some_and(|text| mode.matches(text, value)),
+
+            NodeCondition::FactEquals { fact, value } => {
+                Self::get_nested_value(facts, fact) == Some(value)
+            }
+
+            NodeCondition::FactIn { fact, values } => {
+                Self::get_nested_value(facts, fact).is_some_and(|value| values.contains(value))
+            }
         }
     }
 
@@ -716,6 +1656,8 @@ impl WorkflowManager {
             (current_node.clone(), completed)
         };
 
+        // Templates are left as written: who reads the resource decides which
+        // responses may fill them in, see `WorkflowResource::for_recipient`.
         Some(WorkflowResource {
             instance_id: state.instance_id.clone(),
             current_node_id: current_node.id.clone(),
@@ -724,6 +1666,8 @@ impl WorkflowManager {
             description: current_node.description.clone(),
             responses: state.responses.clone(),
             completed,
+            cancelled: state.cancelled,
+            parent_instance_id: state.parent_instance_id.clone(),
             complete_message: state.complete_message.clone(),
             inputs: current_node.inputs.clone(),
             actions: current_node.actions.clone(),
@@ -731,18 +1675,24 @@ impl WorkflowManager {
             layout: current_node.layout.clone(),
             user_id: state.user_id.clone(),
             waiting: state.waiting.clone(),
+            response_visibility: state.response_visibility.clone(),
         })
     }
 
     pub async fn list_user_workflow_resources(&self, user_id: &str) -> Vec<WorkflowResource> {
-        let active_workflows = self.active_workflows.lock().await;
+        let instance_ids: Vec<String> = {
+            let active_workflows = self.active_workflows.lock().await;
+            active_workflows
+                .values()
+                .filter(|state| state.user_id == user_id && !state.completed)
+                .map(|state| state.instance_id.clone())
+                .collect()
+        };
 
         let mut resources = Vec::new();
-        for state in active_workflows.values() {
-            if state.user_id == user_id && !state.completed {
-                if let Some(resource) = self.get_workflow_resource(&state.instance_id).await {
-                    resources.push(resource);
-                }
+        for instance_id in instance_ids {
+            if let Some(resource) = self.get_workflow_resource(&instance_id).await {
+                resources.push(resource);
             }
         }
         resources
@@ -761,16 +1711,29 @@ impl WorkflowManager {
                 inject_workflow_as,
                 predicate,
                 on_complete,
+                observe,
             } => {
+                if self.wait_would_cycle(workflow_id, predicate).await {
+                    return Err(WorkflowError::WaitCycle(workflow_id.to_string()));
+                }
                 println!(
                     "going to wait for {:?} before continuing with workflow {workflow_id}",
                     predicate
                 );
+                let resume = WaitResume {
+                    inject_workflow_as: inject_workflow_as.clone(),
+                    on_complete: on_complete.clone(),
+                    observe: *observe,
+                };
                 state.waiting = true;
-                self.waiting_for_predicate.lock().await.insert(
-                    workflow_id.to_string(),
-                    (predicate.clone(), inject_workflow_as.clone()),
-                );
+                state.wait = Some(PendingWait::Predicate {
+                    predicate: predicate.clone(),
+                    resume: resume.clone(),
+                });
+                self.waiting_for_predicate
+                    .lock()
+                    .await
+                    .insert(workflow_id.to_string(), (predicate.clone(), resume));
                 send_refresh = true;
             }
             ServerActionResult::StartAndWaitWorkflow {
@@ -780,17 +1743,32 @@ impl WorkflowManager {
                 on_complete,
             } => {
                 match self
-                    .start_workflow(workflow_definition_id, &state.user_id, inputs.clone())
+                    .start_linked_workflow(
+                        workflow_definition_id,
+                        &state.user_id,
+                        inputs.clone(),
+                        Some(workflow_id),
+                    )
                     .await
                 {
                     Ok(started_workflow_id) => {
                         println!(
                             "going to wait for {started_workflow_id} to finish before continuing with workflow {workflow_id}"
                         );
+                        let resume = WaitResume {
+                            inject_workflow_as: inject_workflow_as.clone(),
+                            on_complete: on_complete.clone(),
+                            observe: false,
+                        };
+                        state.child_instance_ids.push(started_workflow_id.clone());
                         state.waiting = true;
+                        state.wait = Some(PendingWait::Workflow {
+                            instance_id: started_workflow_id.clone(),
+                            resume: resume.clone(),
+                        });
                         self.waiting_for_response.lock().await.insert(
                             started_workflow_id.to_string(),
-                            (workflow_id.to_string(), inject_workflow_as.clone()),
+                            (workflow_id.to_string(), resume),
                         );
                         send_refresh = true;
                     }
@@ -804,9 +1782,9 @@ impl WorkflowManager {
                 if let Some(node) = workflow_definition.nodes.get(page_id) {
                     state.node_history.push(state.current_node_id.clone());
                     state.current_node_id = page_id.clone();
+                    self.enter_node(workflow_definition, state).await?;
                     send_refresh = true;
                 } else {
-                    println!("ooo");
                     return Err(WorkflowError::NodeNotFound);
                 }
             }
@@ -820,10 +1798,12 @@ impl WorkflowManager {
                     .get(&state.current_node_id)
                     .ok_or(WorkflowError::NodeNotFound)?;
 
-                let valid_child =
-                    self.find_valid_child_node(&workflow_definition, current_node, state)?;
+                let valid_child = self
+                    .find_valid_child_node(&workflow_definition, current_node, state)
+                    .await?;
                 state.node_history.push(state.current_node_id.clone());
                 state.current_node_id = valid_child.id.clone();
+                self.enter_node(workflow_definition, state).await?;
                 send_refresh = true;
             }
             ServerActionResult::CompleteWorkflow { message, responses } => {
@@ -834,17 +1814,38 @@ impl WorkflowManager {
                 state.completed = true;
                 send_refresh = true;
             }
-            _ => {}
+            ServerActionResult::StartNewWorkflow {
+                workflow_id: started_definition_id,
+                inputs,
+            } => {
+                let started_workflow_id = self
+                    .start_linked_workflow(
+                        started_definition_id,
+                        &state.user_id,
+                        inputs.clone(),
+                        Some(workflow_id),
+                    )
+                    .await?;
+                state.child_instance_ids.push(started_workflow_id);
+                send_refresh = true;
+            }
+            // Left to the caller, which cancels once the state is stored so the
+            // cascade sees it.
+            ServerActionResult::CancelWorkflow => {}
         }
 
         Ok(send_refresh)
     }
 
+    /// Run a server action and apply its result. `processed` is the action
+    /// that asked for it, reported just before the result so the two stay in
+    /// order, and only if both went through.
     pub async fn execute_server_action(
         &self,
         instance_id: String,
         workflow_id: &str,
         action_id: &str,
+        processed: Option<ProcessedAction>,
     ) -> Result<ServerActionResult, WorkflowError> {
         let handlers = self.server_action_handlers.lock().await;
         let handler = handlers
@@ -866,7 +1867,6 @@ impl WorkflowManager {
                 .ok_or(WorkflowError::WorkflowNotFound)?
                 .clone()
         };
-        println!("state {:?}", state);
 
         let context = ServerActionContext {
             action_id: action_id.to_string(),
@@ -880,23 +1880,29 @@ impl WorkflowManager {
             .await
             .map_err(|e| WorkflowError::ServerActionFailed(e.to_string()))?;
 
-        // Process server action result
-
         state.updated_at = chrono::Utc::now();
         let send_refresh = self
             .process_server_action_results(&result, &workflow_definition, &instance_id, state)
             .await?;
 
+        self.update_state(&instance_id, state.clone()).await?;
+
         {
-            let mut workflows = self.active_workflows.lock().await;
-            if let Some(old_state) = workflows.get_mut(&instance_id) {
-                *old_state = state.clone();
-            } else {
-                return Err(WorkflowError::WorkflowNotFound);
+            let event_manager = self.event_manager.lock().await;
+            if let Some(processed) = processed {
+                event_manager.action_processed(processed);
             }
+            event_manager.server_action_completed(
+                instance_id.clone(),
+                action_id.to_string(),
+                result.clone(),
+            );
         }
 
-        if send_refresh {
+        if let ServerActionResult::CancelWorkflow = result {
+            self.cancel_workflow(&instance_id, CancelReason::Cancelled)
+                .await?;
+        } else if send_refresh {
             let resource = self
                 .get_workflow_resource(&instance_id)
                 .await
@@ -934,7 +1940,12 @@ impl WorkflowManager {
             updated_at: chrono::Utc::now(),
         };
 
-        prefs.insert(key, pref);
+        prefs.insert(key, pref.clone());
+        drop(prefs);
+
+        if let Err(e) = self.store.save_preferences(&pref).await {
+            eprintln!("Failed to persist preferences for {user_id}: {}", e);
+        }
     }
 
     pub async fn get_write_lock(&self) -> tokio::sync::MutexGuard<()> {
@@ -955,22 +1966,472 @@ impl WorkflowManager {
         }
     }
 
-    fn find_valid_child_node<'a>(
-        &'a self,
+    async fn find_valid_child_node<'a>(
+        &self,
         workflow: &'a WorkflowDefinition,
         parent_node: &'a WorkflowNode,
         state: &WorkflowState,
     ) -> Result<&'a WorkflowNode, WorkflowError> {
-        for child_node in workflow.nodes.values() {
-            if let Some(parent_id) = &child_node.parent_id {
-                if parent_id == &parent_node.id && self.evaluate_node_condition(child_node, state) {
-                    return Ok(child_node);
+        let candidates: Vec<(&str, Option<&NodeCondition>)> = if parent_node.transitions.is_empty()
+        {
+            // Without transitions the children are tried by id so the same
+            // responses always lead to the same node.
+            let mut children: Vec<&WorkflowNode> = workflow
+                .nodes
+                .values()
+                .filter(|child| child.parent_id.as_deref() == Some(parent_node.id.as_str()))
+                .collect();
+            children.sort_by(|a, b| a.id.cmp(&b.id));
+            children
+                .into_iter()
+                .map(|child| (child.id.as_str(), child.condition.as_ref()))
+                .collect()
+        } else {
+            parent_node
+                .transitions
+                .iter()
+                .map(|transition| (transition.target.as_str(), transition.condition.as_ref()))
+                .collect()
+        };
+
+        let needs_facts = candidates
+            .iter()
+            .any(|(_, condition)| condition.is_some_and(NodeCondition::uses_facts));
+        let facts = if needs_facts {
+            self.facts_for(&state.user_id).await
+        } else {
+            HashMap::new()
+        };
+
+        let (target, _) = candidates
+            .into_iter()
+            .find(|(_, condition)| {
+                condition.is_none_or(|condition| self.evaluate_condition(condition, state, &facts))
+            })
+            .ok_or(WorkflowError::NodeNotFound)?;
+
+        workflow
+            .nodes
+            .get(target)
+            .ok_or(WorkflowError::NodeNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use async_trait::async_trait;
+    use serde_json::Value;
+
+    use super::{ActionProcessResult, WorkflowError, WorkflowManager};
+    use crate::workflow::builder::NodeId;
+    use crate::workflow::facts::FactProvider;
+    use crate::workflow::server_action::ServerActionResult;
+    use crate::workflow::{
+        CancelReason, Comparison, CreateWorkflowDefinition, NodeCondition, ResponseVisibility,
+        StringMatch, WorkflowAction, WorkflowNode, WorkflowPredicate,
+    };
+
+    /// A manager with a "watch" server action that waits on the workflows of
+    /// the user named by the "watched" response, observing them when the
+    /// "observe" response is true and carrying on as the "on_complete"
+    /// response says, and definitions for a workflow using it and one that is
+    /// simply submitted.
+    async fn watching_manager() -> (Arc<WorkflowManager>, String, String) {
+        let manager = Arc::new(WorkflowManager::new());
+        manager
+            .register_server_action(
+                "watch",
+                Box::new(|context| {
+                    Box::pin(async move {
+                        let watched = context.get_required_input_as_str("watched")?;
+                        let observe = context.get_input("observe") == Some(&json!(true));
+                        let on_complete = context
+                            .get_input("on_complete")
+                            .and_then(|value| serde_json::from_value(value.clone()).ok());
+                        Ok(ServerActionResult::WaitForPredicate {
+                            predicate: WorkflowPredicate::ByUserId(watched.to_string()),
+                            inject_workflow_as: Some("seen".to_string()),
+                            on_complete,
+                            observe,
+                        })
+                    })
+                }),
+            )
+            .await
+            .unwrap();
+        manager
+            .register_server_action(
+                "tally",
+                Box::new(|_| {
+                    Box::pin(async {
+                        Ok(ServerActionResult::UpdateResponses(HashMap::from([(
+                            "tallied".to_string(),
+                            json!(true),
+                        )])))
+                    })
+                }),
+            )
+            .await
+            .unwrap();
+
+        let watcher = CreateWorkflowDefinition::new("watcher", "Watcher")
+            .server_action("watch", "Watch", "Wait for someone else")
+            .server_action("tally", "Tally", "Count what was seen")
+            .node(
+                WorkflowNode::new(NodeId("watch"), "Watch")
+                    .action(WorkflowAction::run_server_action("go", "Go", "watch"))
+                    .transition(NodeId("seen")),
+            )
+            .node(
+                WorkflowNode::new(NodeId("seen"), "Seen").action(WorkflowAction::go_to(
+                    "leave",
+                    "Leave",
+                    NodeId("elsewhere"),
+                )),
+            )
+            .node(WorkflowNode::new(NodeId("elsewhere"), "Elsewhere"));
+        let watched = CreateWorkflowDefinition::new("watched", "Watched").node(
+            WorkflowNode::new(NodeId("act"), "Act").action(WorkflowAction::submit("done", "Done")),
+        );
+
+        let watcher_id = manager
+            .register_workflow_definition("bot", watcher)
+            .await
+            .unwrap();
+        let watched_id = manager
+            .register_workflow_definition("bot", watched)
+            .await
+            .unwrap();
+        (manager, watcher_id, watched_id)
+    }
+
+    /// Start a watcher for `user_id` and have it wait on `watched`.
+    async fn watch(
+        manager: &WorkflowManager,
+        watcher_id: &str,
+        user_id: &str,
+        watched: &str,
+    ) -> Result<String, WorkflowError> {
+        let inputs = HashMap::from([("watched".to_string(), json!(watched))]);
+        watch_with(manager, watcher_id, user_id, inputs).await
+    }
+
+    async fn watch_with(
+        manager: &WorkflowManager,
+        watcher_id: &str,
+        user_id: &str,
+        inputs: HashMap<String, Value>,
+    ) -> Result<String, WorkflowError> {
+        let instance_id = manager.start_workflow(watcher_id, user_id, inputs).await?;
+        let (action, _) = manager
+            .process_action(instance_id.clone(), "go", HashMap::new())
+            .await?;
+        let ActionProcessResult::ServerActionStarted {
+            workflow_id,
+            action_id,
+        } = action
+        else {
+            panic!("expected the watch action to start, got {action:?}");
+        };
+        manager
+            .execute_server_action(instance_id.clone(), &workflow_id, &action_id, None)
+            .await?;
+        Ok(instance_id)
+    }
+
+    #[tokio::test]
+    async fn cancelling_cancels_predicate_waiters() {
+        let (manager, watcher_id, watched_id) = watching_manager().await;
+        let seer = manager
+            .start_workflow(&watched_id, "seer", HashMap::new())
+            .await
+            .unwrap();
+        let spy = watch(&manager, &watcher_id, "spy", "seer").await.unwrap();
+        assert!(manager.get_workflow_resource(&spy).await.unwrap().waiting);
+
+        manager
+            .cancel_workflow(&seer, CancelReason::TimedOut)
+            .await
+            .unwrap();
+
+        let spy = manager.get_workflow_resource(&spy).await.unwrap();
+        assert!(spy.completed);
+        assert_eq!(spy.cancelled, Some(CancelReason::TimedOut));
+        assert!(!spy.waiting);
+    }
+
+    #[tokio::test]
+    async fn waits_only_their_own_waiters_could_end_are_refused() {
+        let (manager, watcher_id, _) = watching_manager().await;
+        assert!(matches!(
+            watch(&manager, &watcher_id, "narcissus", "narcissus").await,
+            Err(WorkflowError::WaitCycle(_))
+        ));
+
+        // Nobody else is around yet, so someone may still come along.
+        watch(&manager, &watcher_id, "alice", "bob").await.unwrap();
+        assert!(matches!(
+            watch(&manager, &watcher_id, "bob", "alice").await,
+            Err(WorkflowError::WaitCycle(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn observers_see_what_the_owner_sees() {
+        let (manager, watcher_id, _) = watching_manager().await;
+        let seer = CreateWorkflowDefinition::new("seer", "Seer")
+            .node(
+                WorkflowNode::new(NodeId("act"), "Act")
+                    .action(WorkflowAction::submit("done", "Done")),
+            )
+            .response_visibility("reveal", ResponseVisibility::Owner);
+        let seer_id = manager
+            .register_workflow_definition("bot", seer)
+            .await
+            .unwrap();
+        let seer = manager
+            .start_workflow(
+                &seer_id,
+                "seer",
+                HashMap::from([("reveal".to_string(), json!("Werewolf"))]),
+            )
+            .await
+            .unwrap();
+
+        let mut watchers = Vec::new();
+        for (user_id, observe) in [("spy", true), ("nosy", false)] {
+            let inputs = HashMap::from([
+                ("watched".to_string(), json!("seer")),
+                ("observe".to_string(), json!(observe)),
+            ]);
+            let instance_id = manager
+                .start_workflow(&watcher_id, user_id, inputs)
+                .await
+                .unwrap();
+            let (action, processed) = manager
+                .process_action(instance_id.clone(), "go", HashMap::new())
+                .await
+                .unwrap();
+            let ActionProcessResult::ServerActionStarted {
+                workflow_id,
+                action_id,
+            } = action
+            else {
+                panic!("expected the watch action to start, got {action:?}");
+            };
+            manager
+                .execute_server_action(
+                    instance_id.clone(),
+                    &workflow_id,
+                    &action_id,
+                    Some(processed),
+                )
+                .await
+                .unwrap();
+            watchers.push((user_id, instance_id));
+        }
+
+        manager
+            .process_action(seer.clone(), "done", HashMap::new())
+            .await
+            .unwrap();
+        manager.check_for_waiting(&seer).await;
+
+        for (user_id, instance_id) in watchers {
+            let watcher = manager.get_workflow_resource(&instance_id).await.unwrap();
+            let seen = &watcher.for_recipient(user_id).responses["seen"];
+            let reveal = seen["responses"].get("reveal");
+            if user_id == "spy" {
+                assert_eq!(reveal, Some(&json!("Werewolf")));
+            } else {
+                assert_eq!(reveal, None);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn waiters_carry_on_as_on_complete_says() {
+        let (manager, watcher_id, watched_id) = watching_manager().await;
+        let cases = [
+            ("next", Value::Null),
+            ("submit", json!("Submit")),
+            ("cancel", json!("Cancel")),
+            ("tally", json!({ "RunServerAction": "tally" })),
+            ("jump", json!({ "GoToNode": "elsewhere" })),
+        ];
+        let mut watchers = Vec::new();
+        for (user_id, on_complete) in cases {
+            let inputs = HashMap::from([
+                ("watched".to_string(), json!("seer")),
+                ("on_complete".to_string(), on_complete),
+            ]);
+            let instance_id = watch_with(&manager, &watcher_id, user_id, inputs)
+                .await
+                .unwrap();
+            watchers.push((user_id, instance_id));
+        }
+
+        let seer = manager
+            .start_workflow(&watched_id, "seer", HashMap::new())
+            .await
+            .unwrap();
+        manager
+            .process_action(seer.clone(), "done", HashMap::new())
+            .await
+            .unwrap();
+        manager.check_for_waiting(&seer).await;
+
+        for (user_id, instance_id) in watchers {
+            let watcher = manager.get_workflow_resource(&instance_id).await.unwrap();
+            assert!(!watcher.waiting, "{user_id} still waits");
+            assert!(watcher.responses.contains_key("seen"), "{user_id}");
+            let node = watcher.current_node_id.as_str();
+            match user_id {
+                "next" => assert_eq!(node, "seen"),
+                "submit" => assert!(watcher.completed && watcher.cancelled.is_none()),
+                "cancel" => assert_eq!(watcher.cancelled, Some(CancelReason::Cancelled)),
+                "tally" => {
+                    assert_eq!(watcher.responses.get("tallied"), Some(&json!(true)));
+                    assert_eq!(node, "seen");
                 }
+                _ => assert_eq!(node, "elsewhere"),
             }
         }
-        println!("oh yeah we are here");
+    }
+
+    /// Facts saying which alliance each player is in.
+    struct Alliances;
+
+    #[async_trait]
+    impl FactProvider for Alliances {
+        async fn facts(&self, user_id: &str) -> HashMap<String, Value> {
+            let alliance = if user_id == "vince" {
+                "Werewolf"
+            } else {
+                "Village"
+            };
+            HashMap::from([
+                ("actor".to_string(), json!({ "alliance": alliance })),
+                ("alive_players".to_string(), json!(3)),
+            ])
+        }
+    }
+
+    /// A manager with a workflow whose "choose" node leads somewhere else for
+    /// each kind of condition, knowing the alliances when `with_facts` is set.
+    async fn routing_manager(with_facts: bool) -> (WorkflowManager, String) {
+        let manager = WorkflowManager::new();
+        if with_facts {
+            manager.set_fact_provider(Arc::new(Alliances)).await;
+        }
+
+        let majority = NodeCondition::And(vec![
+            NodeCondition::NumberCompare {
+                field: "votes".to_string(),
+                comparison: Comparison::GreaterOrEqual,
+                value: 2.0,
+            },
+            NodeCondition::Not(Box::new(NodeCondition::StringMatches {
+                field: "name".to_string(),
+                mode: StringMatch::StartsWith,
+                value: "x".to_string(),
+            })),
+        ]);
+        let colorful = NodeCondition::Or(vec![
+            NodeCondition::In {
+                field: "color".to_string(),
+                values: vec![json!("red"), json!("blue")],
+            },
+            NodeCondition::ListLength {
+                field: "picks".to_string(),
+                comparison: Comparison::GreaterThan,
+                length: 1,
+            },
+        ]);
+        let wolf = NodeCondition::FactEquals {
+            fact: "actor.alliance".to_string(),
+            value: json!("Werewolf"),
+        };
+        let crowded = NodeCondition::FactIn {
+            fact: "alive_players".to_string(),
+            values: vec![json!(3), json!(4)],
+        };
+
+        let mut definition = CreateWorkflowDefinition::new("router", "Router").node(
+            WorkflowNode::new(NodeId("choose"), "Choose")
+                .action(WorkflowAction::next_node("next", "Next"))
+                .transition_if(NodeId("majority"), majority)
+                .transition_if(NodeId("colorful"), colorful)
+                .transition_if(NodeId("wolf"), wolf)
+                .transition_if(NodeId("crowded"), crowded)
+                .transition(NodeId("elsewhere")),
+        );
+        for id in ["majority", "colorful", "wolf", "crowded", "elsewhere"] {
+            definition = definition.node(WorkflowNode::new(NodeId(id), id));
+        }
+        let definition_id = manager
+            .register_workflow_definition("bot", definition)
+            .await
+            .unwrap();
+        (manager, definition_id)
+    }
+
+    /// Where `user_id` ends up choosing from a workflow started with `inputs`.
+    async fn route(
+        manager: &WorkflowManager,
+        definition_id: &str,
+        user_id: &str,
+        inputs: Value,
+    ) -> Result<String, WorkflowError> {
+        let inputs = serde_json::from_value(inputs).unwrap();
+        let instance_id = manager
+            .start_workflow(definition_id, user_id, inputs)
+            .await?;
+        manager
+            .process_action(instance_id.clone(), "next", HashMap::new())
+            .await?;
+        Ok(manager
+            .get_workflow_resource(&instance_id)
+            .await
+            .unwrap()
+            .current_node_id)
+    }
+
+    #[tokio::test]
+    async fn transitions_follow_the_first_condition_that_holds() {
+        let (manager, router) = routing_manager(true).await;
+        let cases = [
+            (json!({ "votes": 2, "name": "anna" }), "majority"),
+            (json!({ "votes": 2, "name": "xavier" }), "crowded"),
+            (json!({ "votes": 1, "color": "blue" }), "colorful"),
+            (json!({ "color": "green", "picks": [1, 2] }), "colorful"),
+            (json!({ "color": "green", "picks": [1] }), "crowded"),
+            (json!({ "votes": "many" }), "crowded"),
+        ];
+        for (inputs, expected) in cases {
+            let node = route(&manager, &router, "sam", inputs.clone())
+                .await
+                .unwrap();
+            assert_eq!(node, expected, "{inputs}");
+        }
+    }
 
-        // If no valid child found, return error
-        Err(WorkflowError::NodeNotFound)
+    #[tokio::test]
+    async fn fact_conditions_read_the_actor() {
+        let (manager, router) = routing_manager(true).await;
+        let node = route(&manager, &router, "vince", json!({})).await.unwrap();
+        assert_eq!(node, "wolf");
+        let node = route(&manager, &router, "sam", json!({})).await.unwrap();
+        assert_eq!(node, "crowded");
+
+        // Without facts no fact condition holds.
+        let (manager, router) = routing_manager(false).await;
+        let node = route(&manager, &router, "vince", json!({})).await.unwrap();
+        assert_eq!(node, "elsewhere");
     }
 }
@@ -2,10 +2,20 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use std::collections::HashMap;
 
+use expression::Expression;
+use manager::WaitResume;
+
 // pub(crate) mod bot;
+pub mod builder;
+pub mod expression;
+pub mod facts;
+pub mod input_validator;
 pub(crate) mod manager;
 pub(crate) mod server_action;
 pub mod service;
+pub mod store;
+pub mod template;
+pub mod validator;
 
 #[derive(Type, Debug, Clone, Serialize, Deserialize)]
 pub enum CardFilter {
@@ -96,7 +106,11 @@ pub enum DisplayType {
         card_id_keys: Vec<String>,
     },
     Text {
+        /// A response key, or a template such as `You saw {{reveal.0.name}}`.
         text_key: String,
+        /// `text_key` rendered by the server when it is a template.
+        #[serde(default)]
+        text: Option<String>,
     },
     Page {
         title_key: String,
@@ -179,12 +193,102 @@ pub enum ActionType {
     StartWorkflow,
 }
 
+/// What a waiting workflow does once the workflow it waits for completes.
+#[derive(Type, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OnComplete {
+    // Follow the current node's transitions
+    NextNode,
+    // Complete the workflow
+    Submit,
+    // Cancel the workflow
+    Cancel,
+    // Run another server action of the workflow
+    RunServerAction(String),
+    // Jump to the named node
+    GoToNode(String),
+}
+
+#[derive(Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CancelReason {
+    // Cancelled by the player, a server action or a linked workflow
+    Cancelled,
+    // Ran out of time
+    TimedOut,
+}
+
 #[derive(Type, Debug, Clone, Serialize, Deserialize)]
 pub enum WorkflowPredicate {
+    // A workflow of this user completed
     ByUserId(String),
-    // ByNodeId(String),
-    // And(Vec<WorkflowPredicate>),
-    // Or(Vec<WorkflowPredicate>),
+    // A workflow completed on this node
+    ByNodeId(String),
+    // This workflow instance completed
+    ByWorkflowId(String),
+    // A workflow of this definition completed
+    ByDefinitionId(String),
+    // A workflow completed with this response
+    ByResponse {
+        field: String,
+        value: serde_json::Value,
+    },
+    And(Vec<WorkflowPredicate>),
+    Or(Vec<WorkflowPredicate>),
+}
+
+impl WorkflowPredicate {
+    /// Every workflow instance named through `ByWorkflowId`.
+    pub fn instance_ids(&self) -> Vec<&str> {
+        match self {
+            WorkflowPredicate::ByWorkflowId(instance_id) => vec![instance_id.as_str()],
+            WorkflowPredicate::And(predicates) | WorkflowPredicate::Or(predicates) => predicates
+                .iter()
+                .flat_map(WorkflowPredicate::instance_ids)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    GreaterThan,
+    GreaterOrEqual,
+    LessThan,
+    LessOrEqual,
+}
+
+impl Comparison {
+    pub fn compare<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::GreaterThan => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+            Comparison::LessThan => left < right,
+            Comparison::LessOrEqual => left <= right,
+        }
+    }
+}
+
+#[derive(Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StringMatch {
+    Contains,
+    StartsWith,
+    EndsWith,
+    EqualsIgnoreCase,
+}
+
+impl StringMatch {
+    pub fn matches(&self, text: &str, pattern: &str) -> bool {
+        match self {
+            StringMatch::Contains => text.contains(pattern),
+            StringMatch::StartsWith => text.starts_with(pattern),
+            StringMatch::EndsWith => text.ends_with(pattern),
+            StringMatch::EqualsIgnoreCase => text.to_lowercase() == pattern.to_lowercase(),
+        }
+    }
 }
 
 #[derive(Type, Debug, Clone, Serialize, Deserialize)]
@@ -200,6 +304,92 @@ pub enum NodeCondition {
     ResponseListNotEmpty(String),
     // Always true
     Always,
+    // All, any or none of the nested conditions
+    And(Vec<NodeCondition>),
+    Or(Vec<NodeCondition>),
+    Not(Box<NodeCondition>),
+    // Compare a numeric response with value
+    NumberCompare {
+        field: String,
+        comparison: Comparison,
+        value: f64,
+    },
+    // Check if response field is one of values
+    In {
+        field: String,
+        values: Vec<serde_json::Value>,
+    },
+    // Compare the length of a response list with length
+    ListLength {
+        field: String,
+        comparison: Comparison,
+        length: usize,
+    },
+    // Match a string response against value
+    StringMatches {
+        field: String,
+        mode: StringMatch,
+        value: String,
+    },
+    // Check a fact about the game, e.g. `actor.alliance`
+    FactEquals {
+        fact: String,
+        value: serde_json::Value,
+    },
+    // Check if a game fact is one of values
+    FactIn {
+        fact: String,
+        values: Vec<serde_json::Value>,
+    },
+}
+
+impl NodeCondition {
+    /// Whether evaluating this condition needs facts about the game.
+    pub fn uses_facts(&self) -> bool {
+        match self {
+            NodeCondition::FactEquals { .. } | NodeCondition::FactIn { .. } => true,
+            NodeCondition::And(conditions) | NodeCondition::Or(conditions) => {
+                conditions.iter().any(NodeCondition::uses_facts)
+            }
+            NodeCondition::Not(condition) => condition.uses_facts(),
+            _ => false,
+        }
+    }
+}
+
+/// An edge to another node. Transitions are tried in order and the first
+/// whose condition holds is taken; the last one must have no condition so
+/// there is always somewhere to go.
+#[derive(Type, Debug, Clone, Serialize, Deserialize)]
+pub struct NodeTransition {
+    pub target: String,
+    #[serde(default)]
+    pub condition: Option<NodeCondition>,
+}
+
+impl NodeTransition {
+    pub fn is_fallback(&self) -> bool {
+        matches!(self.condition, None | Some(NodeCondition::Always))
+    }
+}
+
+/// Who may see a response once the resource leaves the server.
+#[derive(Type, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResponseVisibility {
+    #[default]
+    Public,
+    Owner,
+    Server,
+}
+
+impl ResponseVisibility {
+    pub fn is_visible_to(&self, owner_id: &str, recipient_id: &str) -> bool {
+        match self {
+            ResponseVisibility::Public => true,
+            ResponseVisibility::Owner => owner_id == recipient_id,
+            ResponseVisibility::Server => false,
+        }
+    }
 }
 
 #[derive(Type, Debug, Clone, Serialize, Deserialize)]
@@ -213,6 +403,37 @@ pub struct WorkflowNode {
     pub layout: Option<String>,
     pub condition: Option<NodeCondition>,
     pub parent_id: Option<String>,
+    /// Ordered transitions used by `NextNode` without a target. Nodes without
+    /// any fall back to their children, ordered by id.
+    #[serde(default)]
+    pub transitions: Vec<NodeTransition>,
+    #[serde(default)]
+    pub timeout: Option<NodeTimeout>,
+    /// Responses computed when the node is entered, from the responses given
+    /// so far and the game facts.
+    #[serde(default)]
+    pub compute: HashMap<String, Expression>,
+}
+
+/// How long a player may stay on a node and what happens once that time is
+/// up. The clock restarts every time the node is entered.
+#[derive(Type, Debug, Clone, Serialize, Deserialize)]
+pub struct NodeTimeout {
+    pub after_seconds: u64,
+    pub on_expiry: TimeoutAction,
+    /// Emit a reminder this often until the node times out.
+    #[serde(default)]
+    pub remind_every_seconds: Option<u64>,
+}
+
+#[derive(Type, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TimeoutAction {
+    // Submit every input's default value through the node's first action
+    UseDefaults,
+    // Take this action with every input's default value
+    TakeAction(String),
+    // Cancel the workflow
+    Cancel,
 }
 
 #[derive(Type, Debug, Clone, Serialize, Deserialize)]
@@ -224,6 +445,8 @@ pub struct CreateWorkflowDefinition {
     pub nodes: HashMap<String, WorkflowNode>,
     pub responses: HashMap<String, serde_json::Value>,
     pub server_actions: HashMap<String, ServerActionDefinition>,
+    #[serde(default)]
+    pub response_visibility: HashMap<String, ResponseVisibility>,
 }
 
 #[derive(Type, Debug, Clone, Serialize, Deserialize)]
@@ -236,6 +459,8 @@ pub struct WorkflowDefinition {
     pub initial_node_id: String,
     pub nodes: HashMap<String, WorkflowNode>,
     pub server_actions: HashMap<String, ServerActionDefinition>,
+    #[serde(default)]
+    pub response_visibility: HashMap<String, ResponseVisibility>,
 }
 
 #[derive(Type, Debug, Clone, Serialize, Deserialize)]
@@ -257,17 +482,46 @@ pub struct WorkflowState {
     pub completed: bool,
     pub waiting: bool,
     pub complete_message: Option<String>,
-    #[serde(skip_serializing, skip_deserializing)]
+    /// Set together with `completed` when the workflow was cancelled rather
+    /// than finished.
+    #[serde(default)]
+    pub cancelled: Option<CancelReason>,
+    /// The workflow that started this one, if any.
+    #[serde(default)]
+    pub parent_instance_id: Option<String>,
+    #[serde(default)]
+    pub child_instance_ids: Vec<String>,
+    /// Users other than the owner who may act on this workflow.
+    #[serde(default)]
+    pub delegates: Vec<String>,
+    #[serde(default)]
+    pub response_visibility: HashMap<String, ResponseVisibility>,
+    /// What the workflow waits for while `waiting`, so the wait survives a
+    /// restart.
+    #[serde(default)]
+    pub wait: Option<PendingWait>,
     pub created_at: chrono::DateTime<chrono::Utc>,
-    #[serde(skip_serializing, skip_deserializing)]
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PendingWait {
+    // This workflow instance to complete
+    Workflow {
+        instance_id: String,
+        resume: WaitResume,
+    },
+    // Any workflow matching the predicate to complete
+    Predicate {
+        predicate: WorkflowPredicate,
+        resume: WaitResume,
+    },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserWorkflowPreferences {
     pub user_id: String,
     pub workflow_id: String,
     pub saved_responses: HashMap<String, serde_json::Value>,
-    #[serde(skip_serializing, skip_deserializing)]
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
@@ -7,7 +7,7 @@ use specta::Type;
 
 use crate::{
     error::{AppResult, ServicesError},
-    workflow::{ActionType, WorkflowPredicate},
+    workflow::{OnComplete, WorkflowPredicate},
 };
 
 #[derive(Debug, Clone)]
@@ -81,12 +81,17 @@ pub enum ServerActionResult {
         inputs: HashMap<String, Value>,
         definition_id: String,
         inject_workflow_as: Option<String>,
-        on_complete: Option<ActionType>,
+        on_complete: Option<OnComplete>,
     },
     WaitForPredicate {
         predicate: WorkflowPredicate,
         inject_workflow_as: Option<String>,
-        on_complete: Option<ActionType>,
+        on_complete: Option<OnComplete>,
+        /// Inject the workflow as its owner sees it, rather than as the
+        /// waiting player may. Only the server can grant this, to roles that
+        /// watch over another player's shoulder.
+        #[serde(default)]
+        observe: bool,
     },
 }
 
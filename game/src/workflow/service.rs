@@ -4,17 +4,19 @@ use futures::lock::Mutex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use specta::Type;
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
 
 use crate::{
     error::{AppResult, ServicesError},
-    workflow::{manager::WorkflowError, server_action::ServerActionHandler},
+    workflow::{manager::WorkflowError, server_action::ServerActionHandler, store::WorkflowStore},
 };
 
 use super::{
-    CreateWorkflowDefinition, WorkflowAction, WorkflowDisplay, WorkflowInput,
+    CancelReason, CreateWorkflowDefinition, ResponseVisibility, WorkflowAction, WorkflowDisplay,
+    WorkflowInput,
     manager::{ActionProcessResult, WorkflowManager},
     server_action::ServerActionResult,
+    template,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
@@ -25,6 +27,8 @@ pub struct WorkflowResource {
     pub description: Option<String>,
     pub responses: HashMap<String, serde_json::Value>,
     pub completed: bool,
+    #[serde(default)]
+    pub cancelled: Option<CancelReason>,
     pub complete_message: Option<String>,
     pub inputs: Vec<WorkflowInput>,
     pub actions: Vec<WorkflowAction>,
@@ -33,12 +37,71 @@ pub struct WorkflowResource {
     pub user_id: String,
     pub current_node_id: String,
     pub waiting: bool,
+    #[serde(default)]
+    pub response_visibility: HashMap<String, ResponseVisibility>,
+    #[serde(default)]
+    pub parent_instance_id: Option<String>,
+}
+
+/// A workflow together with every workflow it started, recursively.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct WorkflowTree {
+    pub workflow: WorkflowResource,
+    pub children: Vec<WorkflowTree>,
+}
+
+impl WorkflowTree {
+    pub fn for_recipient(&self, recipient_id: &str) -> WorkflowTree {
+        WorkflowTree {
+            workflow: self.workflow.for_recipient(recipient_id),
+            children: self
+                .children
+                .iter()
+                .map(|child| child.for_recipient(recipient_id))
+                .collect(),
+        }
+    }
+}
+
+impl WorkflowResource {
+    /// Strip every response `recipient_id` is not allowed to see and render
+    /// the node's templates from what is left.
+    pub fn for_recipient(&self, recipient_id: &str) -> WorkflowResource {
+        let mut resource = self.clone();
+        resource.responses.retain(|key, _| {
+            self.response_visibility
+                .get(key)
+                .copied()
+                .unwrap_or_default()
+                .is_visible_to(&self.user_id, recipient_id)
+        });
+        resource
+            .response_visibility
+            .retain(|key, _| resource.responses.contains_key(key));
+        resource.render_templates();
+        resource
+    }
+
+    fn render_templates(&mut self) {
+        let values = &self.responses;
+        self.name = template::render(&self.name, values);
+        self.description = self
+            .description
+            .as_ref()
+            .map(|description| template::render(description, values));
+        for input in &mut self.inputs {
+            input.label = template::render(&input.label, values);
+        }
+        for display in &mut self.displays {
+            template::render_display(&mut display.display_type, values);
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 pub struct WorkflowRespondServerActionArgs {
-    token: String,
-    result: ServerActionResult,
+    pub token: String,
+    pub result: ServerActionResult,
 }
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 pub struct ProcessWorkflowActionArgs {
@@ -66,8 +129,50 @@ pub struct WorkflowService {
     pub(crate) manager: Arc<WorkflowManager>,
 
     waiting_for_response: Arc<Mutex<HashMap<String, (String, Option<String>)>>>,
-    external_action_responses:
-        Arc<tokio::sync::Mutex<HashMap<String, tokio::sync::oneshot::Sender<serde_json::Value>>>>,
+    external_action_responses: Arc<tokio::sync::Mutex<HashMap<String, PendingExternalAction>>>,
+    // Tokens already answered, with their workflow instance, so late responses
+    // are told apart from unknown ones until the workflow is done
+    resolved_external_actions: Arc<Mutex<HashMap<String, String>>>,
+    external_action_configs: Arc<Mutex<HashMap<String, ExternalActionConfig>>>,
+}
+
+/// An external server action waiting for its response, by token.
+#[derive(Debug)]
+struct PendingExternalAction {
+    instance_id: String,
+    action_id: String,
+    sender: tokio::sync::oneshot::Sender<serde_json::Value>,
+}
+
+/// An external server action still waiting for its response when a snapshot
+/// was taken. Its request goes out again, under the same token, on resume.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingExternalActionSnapshot {
+    pub token: String,
+    pub instance_id: String,
+    pub action_id: String,
+}
+
+/// How long to wait for an external server action, how often to republish
+/// the request, and what to apply if nobody ever answers.
+#[derive(Debug, Clone)]
+pub struct ExternalActionConfig {
+    pub timeout: Duration,
+    pub max_attempts: u32,
+    pub fallback: ServerActionResult,
+}
+
+impl Default for ExternalActionConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            max_attempts: 3,
+            fallback: ServerActionResult::CompleteWorkflow {
+                responses: HashMap::new(),
+                message: "No response from external action.".to_string(),
+            },
+        }
+    }
 }
 
 #[async_trait]
@@ -78,17 +183,32 @@ pub trait WorkflowPlugin: Send + Sync {
 }
 
 impl WorkflowService {
-    pub async fn new() -> Self {
-        let manager = WorkflowManager::new();
+    pub async fn new() -> Arc<Self> {
+        Self::with_manager(WorkflowManager::new()).await
+    }
 
-        let service = Self {
+    /// Build a service on top of `store`, loading whatever it already holds.
+    /// The loaded workflows only run again with [`WorkflowService::resume`].
+    pub async fn with_store(store: Arc<dyn WorkflowStore>) -> AppResult<Arc<Self>> {
+        let manager = WorkflowManager::with_store(store);
+        manager.restore().await?;
+
+        Ok(Self::with_manager(manager).await)
+    }
+
+    async fn with_manager(manager: WorkflowManager) -> Arc<Self> {
+        let service = Arc::new(Self {
             manager: Arc::new(manager),
 
             external_action_responses: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            resolved_external_actions: Arc::new(Mutex::new(HashMap::new())),
+            external_action_configs: Arc::new(Mutex::new(HashMap::new())),
             waiting_for_response: Arc::new(Mutex::new(HashMap::new())),
-        };
+        });
 
         let manager = service.manager.clone();
+        // Weak, as the service owns the callbacks through its manager.
+        let service_ref = Arc::downgrade(&service);
         service
             .manager
             .event_manager
@@ -97,10 +217,47 @@ impl WorkflowService {
             .on_event(Box::new(move |event| {
                 let event = event.clone();
                 let manager_inner = manager.clone();
+                let service_ref = service_ref.clone();
                 Box::pin(async move {
                     match &event {
+                        WorkflowEvent::WorkflowStarted { resource } => {
+                            manager_inner
+                                .schedule_node_timeout(&resource.instance_id)
+                                .await
+                        }
                         WorkflowEvent::WorkflowUpdated { resource } => {
-                            manager_inner.check_for_waiting(&resource.instance_id).await
+                            manager_inner
+                                .schedule_node_timeout(&resource.instance_id)
+                                .await;
+                            manager_inner.check_for_waiting(&resource.instance_id).await;
+                            if resource.completed
+                                && let Some(service) = service_ref.upgrade()
+                            {
+                                service.forget_resolved_actions(&resource.instance_id).await;
+                            }
+                        }
+                        WorkflowEvent::WorkflowCancelled { resource } => {
+                            if let Some(service) = service_ref.upgrade() {
+                                service.forget_resolved_actions(&resource.instance_id).await;
+                            }
+                        }
+                        WorkflowEvent::NodeExpired {
+                            instance_id,
+                            user_id,
+                            action_id,
+                            inputs,
+                        } => {
+                            let Some(service) = service_ref.upgrade() else {
+                                return;
+                            };
+                            let args = ProcessWorkflowActionArgs::new(
+                                instance_id.clone(),
+                                action_id.clone(),
+                                inputs.clone(),
+                            );
+                            if let Err(e) = service.process_action(user_id, args).await {
+                                eprintln!("Timeout action for {instance_id} failed: {}", e);
+                            }
                         }
                         _ => {}
                     }
@@ -160,14 +317,8 @@ impl WorkflowService {
             .await
             .map_err(ServicesError::from)?;
 
-        let workflow = self.get_workflow_resource(&id).await?;
-        self.manager
-            .event_manager
-            .lock()
-            .await
-            .workflow_started(workflow.clone());
-
-        Ok(workflow)
+        // Starting the workflow already told everyone about it.
+        self.get_workflow_resource(&id).await
     }
 
     pub async fn get_workflow_resource(&self, instance_id: &str) -> AppResult<WorkflowResource> {
@@ -182,32 +333,52 @@ impl WorkflowService {
         token: &str,
         response: serde_json::Value,
     ) -> AppResult<()> {
-        let tx = {
-            let mut response_channels = self.external_action_responses.lock().await;
-            response_channels.remove(token)
-        };
+        let pending = self.external_action_responses.lock().await.remove(token);
 
-        if let Some(tx) = tx {
-            let _ = tx.send(response);
+        if let Some(pending) = pending {
+            self.resolved_external_actions
+                .lock()
+                .await
+                .insert(token.to_string(), pending.instance_id);
+            let _ = pending.sender.send(response);
         }
 
         Ok(())
     }
 
+    /// Override the timeout, retries and fallback of one external server action.
+    pub async fn configure_external_server_action(
+        &self,
+        action_id: &str,
+        config: ExternalActionConfig,
+    ) {
+        self.external_action_configs
+            .lock()
+            .await
+            .insert(action_id.to_string(), config);
+    }
+
     pub async fn process_action(
         &self,
         user_id: &str,
         args: ProcessWorkflowActionArgs,
     ) -> AppResult<()> {
+        self.manager
+            .authorize_user(&args.instance_id, user_id)
+            .await
+            .map_err(ServicesError::from)?;
+
         // Check if this is an external server action
 
-        let action = self
+        let (action, processed) = self
             .manager
             .process_action(args.instance_id.clone(), &args.action_id, args.inputs)
             .await
             .map_err(ServicesError::from)?;
         println!("Executing action {:?}", action);
 
+        // The action is only reported once what it set off went through.
+        let mut processed = Some(processed);
         match action {
             ActionProcessResult::ExternalServerActionStarted { action_id, id, .. } => {
                 let resource = self.get_workflow_resource(&args.instance_id).await?;
@@ -225,23 +396,73 @@ impl WorkflowService {
                 workflow_id,
                 user_id,
             } => {
-                let workflow = self
-                    .manager
-                    .start_workflow(&workflow_id, &user_id, HashMap::new())
+                self.manager
+                    .start_child_workflow(&args.instance_id, &workflow_id, &user_id, HashMap::new())
+                    .await?;
+            }
+            ActionProcessResult::WorkflowCancelled => {
+                self.manager
+                    .cancel_workflow(&args.instance_id, CancelReason::Cancelled)
                     .await?;
             }
+            ActionProcessResult::WorkflowCompleted(_) => {
+                // Lets whoever waits on the workflow, and its players, know.
+                let resource = self.get_workflow_resource(&args.instance_id).await?;
+                self.manager
+                    .event_manager
+                    .lock()
+                    .await
+                    .workflow_updated(resource);
+            }
             ActionProcessResult::ServerActionStarted {
                 workflow_id,
                 action_id,
             } => {
-                let result = self
-                    .manager
-                    .execute_server_action(args.instance_id.clone(), &workflow_id, &action_id)
+                self.manager
+                    .execute_server_action(
+                        args.instance_id.clone(),
+                        &workflow_id,
+                        &action_id,
+                        processed.take(),
+                    )
                     .await?;
             }
-            _ => {}
         };
 
+        if let Some(processed) = processed {
+            self.manager
+                .event_manager
+                .lock()
+                .await
+                .action_processed(processed);
+        }
+
+        Ok(())
+    }
+
+    pub async fn delegate_workflow(
+        &self,
+        owner_id: &str,
+        instance_id: &str,
+        delegate_id: &str,
+    ) -> AppResult<()> {
+        self.manager
+            .delegate_workflow(instance_id, owner_id, delegate_id)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn revoke_delegation(
+        &self,
+        owner_id: &str,
+        instance_id: &str,
+        delegate_id: &str,
+    ) -> AppResult<()> {
+        self.manager
+            .revoke_delegation(instance_id, owner_id, delegate_id)
+            .await?;
+
         Ok(())
     }
 
@@ -250,21 +471,46 @@ impl WorkflowService {
         user_id: &str,
         args: WorkflowRespondServerActionArgs,
     ) -> AppResult<()> {
-        // Find the channel associated with this token
-        let tx = {
-            let mut response_channels = self.external_action_responses.lock().await;
-            response_channels.remove(&args.token)
+        let pending = {
+            let response_channels = self.external_action_responses.lock().await;
+            response_channels
+                .get(&args.token)
+                .map(|pending| pending.action_id.clone())
         };
+        if let Some(action_id) = pending {
+            self.authorize_responder(user_id, &action_id).await?;
+        }
+
+        // Find the channel associated with this token
+        let pending = self
+            .external_action_responses
+            .lock()
+            .await
+            .remove(&args.token);
 
         // If we found a waiting channel, send the response
-        if let Some(tx) = tx {
+        if let Some(pending) = pending {
+            self.resolved_external_actions
+                .lock()
+                .await
+                .insert(args.token.clone(), pending.instance_id);
+
             // Convert the HashMap to a JSON Value
             let response_value = serde_json::to_value(args.result).map_err(|e| {
                 ServicesError::InternalError(format!("JSON serialization error: {}", e))
             })?;
 
             // Send the response through the channel
-            let _ = tx.send(response_value);
+            let _ = pending.sender.send(response_value);
+            Ok(())
+        } else if self
+            .resolved_external_actions
+            .lock()
+            .await
+            .contains_key(&args.token)
+        {
+            // Duplicate or late response, the action already moved on
+            println!("Ignoring duplicate response for token {}", args.token);
             Ok(())
         } else {
             // No waiting receiver found for this token
@@ -275,6 +521,66 @@ impl WorkflowService {
         }
     }
 
+    pub async fn pending_external_actions(&self) -> Vec<PendingExternalActionSnapshot> {
+        let mut pending: Vec<PendingExternalActionSnapshot> = self
+            .external_action_responses
+            .lock()
+            .await
+            .iter()
+            .map(|(token, pending)| PendingExternalActionSnapshot {
+                token: token.clone(),
+                instance_id: pending.instance_id.clone(),
+                action_id: pending.action_id.clone(),
+            })
+            .collect();
+        pending.sort_by(|a, b| a.token.cmp(&b.token));
+        pending
+    }
+
+    /// Pick restored workflows back up. Only call this once the server
+    /// actions and callbacks they rely on are registered: their timeouts are
+    /// scheduled, their waits checked and the external actions in `pending`
+    /// asked for again.
+    pub async fn resume(&self, pending: Vec<PendingExternalActionSnapshot>) -> AppResult<()> {
+        self.manager.resume_restored().await;
+
+        for pending in pending {
+            let resource = self.get_workflow_resource(&pending.instance_id).await?;
+            self.handle_external_server_action(
+                pending.token,
+                pending.instance_id,
+                resource,
+                pending.action_id,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Stop remembering the answered actions of a workflow that is done.
+    async fn forget_resolved_actions(&self, instance_id: &str) {
+        self.resolved_external_actions
+            .lock()
+            .await
+            .retain(|_, resolved_instance_id| resolved_instance_id != instance_id);
+    }
+
+    /// Only whoever registered the external action may answer it. Players on
+    /// the workflow could otherwise hand themselves any result.
+    async fn authorize_responder(&self, user_id: &str, action_id: &str) -> AppResult<()> {
+        let registered = self
+            .manager
+            .external_server_actions
+            .lock()
+            .await
+            .contains(&(user_id.to_string(), action_id.to_string()));
+        if registered {
+            Ok(())
+        } else {
+            Err(WorkflowError::Forbidden(user_id.to_string()).into())
+        }
+    }
+
     fn handle_external_server_action(
         &self,
         token: String,
@@ -284,86 +590,668 @@ impl WorkflowService {
     ) {
         // Clone what we need from self
         let external_action_responses = self.external_action_responses.clone();
+        let resolved_external_actions = self.resolved_external_actions.clone();
+        let external_action_configs = self.external_action_configs.clone();
         let manager = self.manager.clone();
 
         println!("Looking for action id {action_id}");
 
         tokio::spawn(async move {
+            let config = external_action_configs
+                .lock()
+                .await
+                .get(&action_id)
+                .cloned()
+                .unwrap_or_default();
+
             // Create a new oneshot channel
-            let (tx, rx) = tokio::sync::oneshot::channel();
+            let (tx, mut rx) = tokio::sync::oneshot::channel();
 
             // Store the sender
             {
                 let mut response_channels = external_action_responses.lock().await;
-                response_channels.insert(token.clone(), tx);
+                response_channels.insert(
+                    token.clone(),
+                    PendingExternalAction {
+                        instance_id: instance_id.clone(),
+                        action_id: action_id.clone(),
+                        sender: tx,
+                    },
+                );
             }
 
-            // Set up the timeout
-            let timeout_future = tokio::time::timeout(std::time::Duration::from_secs(10), rx);
-            let mut refresh = false;
-
-            match timeout_future.await {
-                Ok(Ok(result)) => {
-                    println!("0");
-                    if let Ok(result) = serde_json::from_value::<ServerActionResult>(result) {
-                        println!("1");
-                        let workflow_definition = manager
-                            .workflows
-                            .lock()
-                            .await
-                            .get(&workflow.workflow_id)
-                            .unwrap()
-                            .clone();
-
-                        println!("2");
-                        if let Some(mut state) = {
-                            let mut active_workflows = manager.active_workflows.lock().await;
-                            active_workflows.remove(&instance_id)
-                        } {
-                            println!("3");
-                            match manager
-                                .process_server_action_results(
-                                    &result,
-                                    &workflow_definition,
-                                    &instance_id,
-                                    &mut state,
-                                )
-                                .await
-                            {
-                                Ok(_) => println!("success"),
-                                Err(e) => println!("uh oh!!! {}", e),
-                            };
-                            refresh = true;
+            let mut result = None;
+            for attempt in 1..=config.max_attempts.max(1) {
+                manager
+                    .event_manager
+                    .lock()
+                    .await
+                    .external_server_action_requested(
+                        token.clone(),
+                        action_id.clone(),
+                        workflow.clone(),
+                    );
 
-                            println!("4");
-                            let mut active_workflows = manager.active_workflows.lock().await;
-                            active_workflows.insert(instance_id.clone(), state);
+                match tokio::time::timeout(config.timeout, &mut rx).await {
+                    Ok(Ok(response)) => {
+                        match serde_json::from_value::<ServerActionResult>(response) {
+                            Ok(response) => result = Some(response),
+                            Err(e) => eprintln!("Invalid external action response: {}", e),
                         }
+                        break;
+                    }
+                    Ok(Err(_)) => {
+                        // Handle error from response handling
+                        eprintln!("Error processing external action response");
+                        break;
+                    }
+                    Err(_) => {
+                        eprintln!(
+                            "Timeout waiting for external action response: {} (attempt {}/{})",
+                            token, attempt, config.max_attempts
+                        );
                     }
                 }
-                Ok(Err(_)) => {
-                    // Handle error from response handling
-                    eprintln!("Error processing external action response");
+            }
+
+            // From here on any response for this token is a duplicate.
+            external_action_responses.lock().await.remove(&token);
+            resolved_external_actions
+                .lock()
+                .await
+                .insert(token.clone(), instance_id.clone());
+
+            let result = result.unwrap_or_else(|| {
+                println!("Applying fallback for external action {action_id}");
+                config.fallback.clone()
+            });
+
+            let workflow_definition = manager
+                .workflows
+                .lock()
+                .await
+                .get(&workflow.workflow_id)
+                .unwrap()
+                .clone();
+
+            let Some(original) = ({
+                let active_workflows = manager.active_workflows.lock().await;
+                active_workflows.get(&instance_id).cloned()
+            }) else {
+                eprintln!("Workflow {instance_id} disappeared while waiting for {token}");
+                return;
+            };
+
+            // A response that cannot be applied is treated like no response,
+            // so the workflow still moves on.
+            let mut state = original.clone();
+            let result = match manager
+                .process_server_action_results(
+                    &result,
+                    &workflow_definition,
+                    &instance_id,
+                    &mut state,
+                )
+                .await
+            {
+                Ok(_) => result,
+                Err(e) => {
+                    eprintln!(
+                        "Unable to apply the response to {action_id} for workflow {instance_id}, applying its fallback: {}",
+                        e
+                    );
+                    state = original;
+                    if let Err(e) = manager
+                        .process_server_action_results(
+                            &config.fallback,
+                            &workflow_definition,
+                            &instance_id,
+                            &mut state,
+                        )
+                        .await
+                    {
+                        eprintln!(
+                            "Unable to apply the fallback for {action_id} to workflow {instance_id}: {}",
+                            e
+                        );
+                    }
+                    config.fallback.clone()
                 }
-                Err(_) => {
-                    // Timeout occurred
-                    eprintln!("Timeout waiting for external action response: {}", token);
+            };
+
+            manager.event_manager.lock().await.server_action_completed(
+                instance_id.clone(),
+                action_id.clone(),
+                result.clone(),
+            );
+
+            if let Err(e) = manager.persist_state(&state).await {
+                eprintln!("Failed to persist workflow {instance_id}: {}", e);
+            }
+            {
+                let mut active_workflows = manager.active_workflows.lock().await;
+                active_workflows.insert(instance_id.clone(), state);
+            }
+
+            if let ServerActionResult::CancelWorkflow = result {
+                if let Err(e) = manager
+                    .cancel_workflow(&instance_id, CancelReason::Cancelled)
+                    .await
+                {
+                    eprintln!("Failed to cancel workflow {instance_id}: {}", e);
                 }
+                return;
             }
 
             let updated = manager.get_workflow_resource(&instance_id).await.unwrap();
-            {
-                let active_workflows = manager.active_workflows.lock().await;
-                let wf = active_workflows.get(&instance_id);
-                println!(
-                    "sending update for instance id {}, current node id: {:?}",
-                    instance_id,
-                    wf.and_then(|w| Some(w.current_node_id.clone()))
+            println!(
+                "sending update for instance id {}, current node id: {:?}",
+                instance_id, updated.current_node_id
+            );
+            manager.event_manager.lock().await.workflow_updated(updated);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{ProcessWorkflowActionArgs, WorkflowRespondServerActionArgs, WorkflowService};
+    use crate::error::ServicesError;
+    use crate::workflow::builder::NodeId;
+    use crate::workflow::manager::{WorkflowError, WorkflowEvent};
+    use crate::workflow::server_action::{ServerActionHandler, ServerActionResult};
+    use crate::workflow::store::sqlite::SqliteStore;
+    use crate::workflow::{
+        CancelReason, CreateWorkflowDefinition, NodeTimeout, TimeoutAction, WorkflowAction,
+        WorkflowInput, WorkflowNode,
+    };
+
+    fn finish() -> ServerActionHandler {
+        Box::new(|_| {
+            Box::pin(async {
+                Ok(ServerActionResult::CompleteWorkflow {
+                    responses: HashMap::new(),
+                    message: "Done".to_string(),
+                })
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn restored_waits_resume_when_the_awaited_workflow_completes() {
+        let store = Arc::new(SqliteStore::open_in_memory("game").unwrap());
+        let service = WorkflowService::with_store(store.clone()).await.unwrap();
+
+        service
+            .register_server_action("finish", finish())
+            .await
+            .unwrap();
+        let child = CreateWorkflowDefinition::new("child", "Child")
+            .server_action("finish", "Finish", "Complete the workflow")
+            .node(
+                WorkflowNode::new(NodeId("ask"), "Ask")
+                    .action(WorkflowAction::run_server_action("done", "Done", "finish")),
+            );
+        let child_id = service
+            .register_workflow_definition("bot", child)
+            .await
+            .unwrap();
+        service
+            .register_server_action(
+                "wait_for_child",
+                Box::new(move |_| {
+                    let child_id = child_id.clone();
+                    Box::pin(async move {
+                        Ok(ServerActionResult::StartAndWaitWorkflow {
+                            inputs: HashMap::new(),
+                            definition_id: child_id,
+                            inject_workflow_as: Some("child".to_string()),
+                            on_complete: None,
+                        })
+                    })
+                }),
+            )
+            .await
+            .unwrap();
+
+        let parent = CreateWorkflowDefinition::new("parent", "Parent")
+            .server_action("wait_for_child", "Wait", "Wait for the child")
+            .node(
+                WorkflowNode::new(NodeId("start"), "Start")
+                    .action(WorkflowAction::run_server_action(
+                        "go",
+                        "Go",
+                        "wait_for_child",
+                    ))
+                    .transition(NodeId("after")),
+            )
+            .node(
+                WorkflowNode::new(NodeId("after"), "After")
+                    .action(WorkflowAction::submit("done", "Done")),
+            );
+        let parent_id = service
+            .register_workflow_definition("bot", parent)
+            .await
+            .unwrap();
+        let parent = service
+            .start_command_workflow(&parent_id, "owner", HashMap::new())
+            .await
+            .unwrap();
+        service
+            .process_action(
+                "owner",
+                ProcessWorkflowActionArgs::new(
+                    parent.instance_id.clone(),
+                    "go".to_string(),
+                    HashMap::new(),
+                ),
+            )
+            .await
+            .unwrap();
+        drop(service);
+
+        let restored = WorkflowService::with_store(store).await.unwrap();
+        restored
+            .register_server_action("finish", finish())
+            .await
+            .unwrap();
+        restored.resume(Vec::new()).await.unwrap();
+        let child = restored
+            .manager
+            .list_user_workflow_resources("owner")
+            .await
+            .into_iter()
+            .find(|resource| resource.parent_instance_id.as_ref() == Some(&parent.instance_id))
+            .expect("the child was saved");
+        assert!(
+            restored
+                .get_workflow_resource(&parent.instance_id)
+                .await
+                .unwrap()
+                .waiting
+        );
+
+        restored
+            .process_action(
+                "owner",
+                ProcessWorkflowActionArgs::new(
+                    child.instance_id,
+                    "done".to_string(),
+                    HashMap::new(),
+                ),
+            )
+            .await
+            .unwrap();
+
+        for _ in 0..50 {
+            let parent = restored
+                .get_workflow_resource(&parent.instance_id)
+                .await
+                .unwrap();
+            if parent.current_node_id == "after" {
+                assert!(!parent.waiting);
+                assert!(parent.responses.contains_key("child"));
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the restored parent was never woken");
+    }
+
+    #[tokio::test]
+    async fn games_sharing_a_database_only_load_their_own_workflows() {
+        let path = std::env::temp_dir().join(format!("workflows-{}.db", ulid::Ulid::new()));
+        for game_id in ["first", "second"] {
+            let store = Arc::new(SqliteStore::open(&path, game_id).unwrap());
+            let service = WorkflowService::with_store(store).await.unwrap();
+            let definition = CreateWorkflowDefinition::new(game_id, game_id).node(
+                WorkflowNode::new(NodeId("only"), "Only")
+                    .action(WorkflowAction::submit("done", "Done")),
+            );
+            let definition_id = service
+                .register_workflow_definition("bot", definition)
+                .await
+                .unwrap();
+            service
+                .start_command_workflow(&definition_id, "owner", HashMap::new())
+                .await
+                .unwrap();
+        }
+
+        for game_id in ["first", "second"] {
+            let store = Arc::new(SqliteStore::open(&path, game_id).unwrap());
+            let service = WorkflowService::with_store(store).await.unwrap();
+            let workflows = service.manager.list_user_workflow_resources("owner").await;
+            assert_eq!(workflows.len(), 1, "{game_id}");
+            assert_eq!(workflows[0].workflow_id, format!("user-bot-wf-{game_id}"));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_actions_are_not_reported() {
+        let service = WorkflowService::new().await;
+        service
+            .register_server_action(
+                "fail",
+                Box::new(|_| Box::pin(async { Err("broken".into()) })),
+            )
+            .await
+            .unwrap();
+        service
+            .register_server_action("finish", finish())
+            .await
+            .unwrap();
+        let definition = CreateWorkflowDefinition::new("flaky", "Flaky")
+            .server_action("fail", "Fail", "Always fails")
+            .server_action("finish", "Finish", "Complete the workflow")
+            .node(
+                WorkflowNode::new(NodeId("ask"), "Ask")
+                    .action(WorkflowAction::run_server_action(
+                        "broken", "Broken", "fail",
+                    ))
+                    .action(WorkflowAction::run_server_action("done", "Done", "finish")),
+            );
+        let definition_id = service
+            .register_workflow_definition("bot", definition)
+            .await
+            .unwrap();
+
+        let (events, mut reported) = tokio::sync::mpsc::unbounded_channel();
+        service
+            .manager
+            .event_manager
+            .lock()
+            .await
+            .on_event(Box::new(move |event| {
+                match event {
+                    WorkflowEvent::ActionProcessed { action_id, .. } => {
+                        let _ = events.send(format!("action {action_id}"));
+                    }
+                    WorkflowEvent::ServerActionCompleted { action_id, .. } => {
+                        let _ = events.send(format!("result {action_id}"));
+                    }
+                    _ => {}
+                }
+                Box::pin(async {})
+            }));
+
+        let workflow = service
+            .start_command_workflow(&definition_id, "owner", HashMap::new())
+            .await
+            .unwrap();
+        let act = |action_id: &str| {
+            service.process_action(
+                "owner",
+                ProcessWorkflowActionArgs::new(
+                    workflow.instance_id.clone(),
+                    action_id.to_string(),
+                    HashMap::new(),
+                ),
+            )
+        };
+        assert!(act("broken").await.is_err());
+        act("done").await.unwrap();
+
+        assert_eq!(reported.recv().await.unwrap(), "action done");
+        assert_eq!(reported.recv().await.unwrap(), "result finish");
+        assert!(reported.try_recv().is_err());
+    }
+
+    /// Start a workflow owned by "owner" and ask the "oracle" for its external
+    /// action, returning the instance and the request's token.
+    async fn ask_oracle(service: &WorkflowService) -> (String, String) {
+        let action_id = service
+            .register_external_server_action("oracle", "lookup")
+            .await
+            .unwrap();
+        let definition = CreateWorkflowDefinition::new("asker", "Asker")
+            .server_action(&action_id, "Lookup", "Answered by the oracle")
+            .node(
+                WorkflowNode::new(NodeId("ask"), "Ask")
+                    .action(WorkflowAction::run_server_action("go", "Go", &action_id)),
+            );
+        let definition_id = service
+            .register_workflow_definition("oracle", definition)
+            .await
+            .unwrap();
+
+        let (tokens, mut requested) = tokio::sync::mpsc::unbounded_channel();
+        service
+            .manager
+            .event_manager
+            .lock()
+            .await
+            .on_event(Box::new(move |event| {
+                if let WorkflowEvent::ExternalServerActionRequested { token, .. } = event {
+                    let _ = tokens.send(token);
+                }
+                Box::pin(async {})
+            }));
+
+        let workflow = service
+            .start_command_workflow(&definition_id, "owner", HashMap::new())
+            .await
+            .unwrap();
+        service
+            .process_action(
+                "owner",
+                ProcessWorkflowActionArgs::new(
+                    workflow.instance_id.clone(),
+                    "go".to_string(),
+                    HashMap::new(),
+                ),
+            )
+            .await
+            .unwrap();
+        let token = tokio::time::timeout(Duration::from_secs(1), requested.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        (workflow.instance_id, token)
+    }
+
+    #[tokio::test]
+    async fn only_the_registered_responder_may_answer() {
+        let service = WorkflowService::new().await;
+        let (instance_id, token) = ask_oracle(&service).await;
+        service
+            .delegate_workflow("owner", &instance_id, "friend")
+            .await
+            .unwrap();
+
+        for user_id in ["owner", "friend"] {
+            let forged = service
+                .respond_server_action(
+                    user_id,
+                    WorkflowRespondServerActionArgs {
+                        token: token.clone(),
+                        result: ServerActionResult::CompleteWorkflow {
+                            responses: HashMap::new(),
+                            message: "Forged".to_string(),
+                        },
+                    },
+                )
+                .await;
+            assert!(
+                matches!(
+                    forged,
+                    Err(ServicesError::WorkflowError(WorkflowError::Forbidden(_)))
+                ),
+                "{user_id} answered the oracle's action: {forged:?}"
+            );
+        }
+        assert!(
+            !service
+                .get_workflow_resource(&instance_id)
+                .await
+                .unwrap()
+                .completed
+        );
+    }
+
+    #[tokio::test]
+    async fn responses_that_cannot_be_applied_fall_back() {
+        let service = WorkflowService::new().await;
+        let (instance_id, token) = ask_oracle(&service).await;
+
+        service
+            .respond_server_action(
+                "oracle",
+                WorkflowRespondServerActionArgs {
+                    token,
+                    result: ServerActionResult::NextPage {
+                        page_id: "nowhere".to_string(),
+                    },
+                },
+            )
+            .await
+            .unwrap();
+
+        for _ in 0..50 {
+            let workflow = service.get_workflow_resource(&instance_id).await.unwrap();
+            if workflow.completed {
+                assert_eq!(
+                    workflow.complete_message.as_deref(),
+                    Some("No response from external action.")
                 );
-            };
-            if refresh {
-                manager.event_manager.lock().await.workflow_updated(updated);
+                return;
             }
-        });
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the fallback was never applied");
+    }
+
+    #[tokio::test]
+    async fn answered_actions_are_forgotten_once_their_workflow_is_done() {
+        let service = WorkflowService::new().await;
+        let (instance_id, token) = ask_oracle(&service).await;
+
+        let respond = || {
+            service.respond_server_action(
+                "oracle",
+                WorkflowRespondServerActionArgs {
+                    token: token.clone(),
+                    result: ServerActionResult::CompleteWorkflow {
+                        responses: HashMap::new(),
+                        message: "Done".to_string(),
+                    },
+                },
+            )
+        };
+        respond().await.unwrap();
+
+        for _ in 0..50 {
+            if service.resolved_external_actions.lock().await.is_empty() {
+                let workflow = service.get_workflow_resource(&instance_id).await.unwrap();
+                assert!(workflow.completed);
+                assert!(matches!(respond().await, Err(ServicesError::NotFound(_))));
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the answered action was never forgotten");
+    }
+
+    #[tokio::test]
+    async fn timeouts_take_their_action_with_the_defaults() {
+        let service = WorkflowService::new().await;
+        let definition = CreateWorkflowDefinition::new("vote", "Vote")
+            .response("options", serde_json::json!(["alice", "bob"]))
+            .node(
+                WorkflowNode::new(NodeId("pick"), "Pick")
+                    .input(
+                        WorkflowInput::select_from_list("target", "Target", "options")
+                            .with_default(serde_json::json!("alice")),
+                    )
+                    .action(WorkflowAction::submit("vote", "Vote"))
+                    .with_timeout(NodeTimeout {
+                        after_seconds: 1,
+                        on_expiry: TimeoutAction::TakeAction("vote".to_string()),
+                        remind_every_seconds: None,
+                    }),
+            );
+        let definition_id = service
+            .register_workflow_definition("game", definition)
+            .await
+            .unwrap();
+        let workflow = service
+            .start_command_workflow(&definition_id, "voter", HashMap::new())
+            .await
+            .unwrap();
+
+        for _ in 0..100 {
+            let workflow = service
+                .get_workflow_resource(&workflow.instance_id)
+                .await
+                .unwrap();
+            if workflow.completed {
+                assert_eq!(workflow.responses["target"], "alice");
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("the timeout never submitted the vote");
+    }
+
+    #[tokio::test]
+    async fn timeouts_only_cancel_players_still_on_the_node() {
+        let service = WorkflowService::new().await;
+        let definition = CreateWorkflowDefinition::new("hurry", "Hurry")
+            .node(
+                WorkflowNode::new(NodeId("first"), "First")
+                    .action(WorkflowAction::next_node("next", "Next"))
+                    .transition(NodeId("second"))
+                    .with_timeout(NodeTimeout {
+                        after_seconds: 1,
+                        on_expiry: TimeoutAction::Cancel,
+                        remind_every_seconds: None,
+                    }),
+            )
+            .node(
+                WorkflowNode::new(NodeId("second"), "Second")
+                    .action(WorkflowAction::submit("done", "Done")),
+            );
+        let definition_id = service
+            .register_workflow_definition("game", definition)
+            .await
+            .unwrap();
+
+        let mut instance_ids = Vec::new();
+        for user_id in ["idle", "busy"] {
+            let workflow = service
+                .start_command_workflow(&definition_id, user_id, HashMap::new())
+                .await
+                .unwrap();
+            instance_ids.push(workflow.instance_id);
+        }
+        service
+            .process_action(
+                "busy",
+                ProcessWorkflowActionArgs::new(
+                    instance_ids[1].clone(),
+                    "next".to_string(),
+                    HashMap::new(),
+                ),
+            )
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let idle = service
+            .get_workflow_resource(&instance_ids[0])
+            .await
+            .unwrap();
+        assert_eq!(idle.cancelled, Some(CancelReason::TimedOut));
+        let busy = service
+            .get_workflow_resource(&instance_ids[1])
+            .await
+            .unwrap();
+        assert!(!busy.completed);
+        assert_eq!(busy.current_node_id, "second");
     }
 }
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use futures::lock::Mutex;
+
+use crate::error::AppResult;
+use crate::snapshot::GameSnapshot;
+
+use super::{UserWorkflowPreferences, WorkflowDefinition, WorkflowState};
+
+pub mod sqlite;
+
+/// Durable home of everything [`super::manager::WorkflowManager`] keeps in
+/// memory, for a single game. The manager writes through on every change and
+/// loads it all back on start.
+#[async_trait]
+pub trait WorkflowStore: Send + Sync {
+    async fn save_definition(&self, id: &str, definition: &WorkflowDefinition) -> AppResult<()>;
+
+    async fn load_definitions(&self) -> AppResult<HashMap<String, WorkflowDefinition>>;
+
+    async fn save_instance(&self, state: &WorkflowState) -> AppResult<()>;
+
+    async fn load_instances(&self) -> AppResult<HashMap<String, WorkflowState>>;
+
+    async fn save_preferences(&self, preferences: &UserWorkflowPreferences) -> AppResult<()>;
+
+    async fn load_preferences(
+        &self,
+    ) -> AppResult<HashMap<(String, String), UserWorkflowPreferences>>;
+
+    /// The game itself, without the workflows saved above, so a restart
+    /// carries on with it instead of dealing a new one.
+    async fn save_game(&self, game: &GameSnapshot) -> AppResult<()>;
+
+    async fn load_game(&self) -> AppResult<Option<GameSnapshot>>;
+}
+
+#[derive(Default)]
+pub struct InMemoryStore {
+    definitions: Mutex<HashMap<String, WorkflowDefinition>>,
+    instances: Mutex<HashMap<String, WorkflowState>>,
+    preferences: Mutex<HashMap<(String, String), UserWorkflowPreferences>>,
+    game: Mutex<Option<GameSnapshot>>,
+}
+
+#[async_trait]
+impl WorkflowStore for InMemoryStore {
+    async fn save_definition(&self, id: &str, definition: &WorkflowDefinition) -> AppResult<()> {
+        self.definitions
+            .lock()
+            .await
+            .insert(id.to_string(), definition.clone());
+        Ok(())
+    }
+
+    async fn load_definitions(&self) -> AppResult<HashMap<String, WorkflowDefinition>> {
+        Ok(self.definitions.lock().await.clone())
+    }
+
+    async fn save_instance(&self, state: &WorkflowState) -> AppResult<()> {
+        self.instances
+            .lock()
+            .await
+            .insert(state.instance_id.clone(), state.clone());
+        Ok(())
+    }
+
+    async fn load_instances(&self) -> AppResult<HashMap<String, WorkflowState>> {
+        Ok(self.instances.lock().await.clone())
+    }
+
+    async fn save_preferences(&self, preferences: &UserWorkflowPreferences) -> AppResult<()> {
+        self.preferences.lock().await.insert(
+            (preferences.user_id.clone(), preferences.workflow_id.clone()),
+            preferences.clone(),
+        );
+        Ok(())
+    }
+
+    async fn load_preferences(
+        &self,
+    ) -> AppResult<HashMap<(String, String), UserWorkflowPreferences>> {
+        Ok(self.preferences.lock().await.clone())
+    }
+
+    async fn save_game(&self, game: &GameSnapshot) -> AppResult<()> {
+        *self.game.lock().await = Some(game.clone());
+        Ok(())
+    }
+
+    async fn load_game(&self) -> AppResult<Option<GameSnapshot>> {
+        Ok(self.game.lock().await.clone())
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use rusqlite::{Connection, params};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::error::{AppResult, ServicesError};
+use crate::snapshot::GameSnapshot;
+use crate::workflow::{UserWorkflowPreferences, WorkflowDefinition, WorkflowState};
+
+use super::WorkflowStore;
+
+/// Embedded SQLite store. Rows hold the JSON form of each value so the schema
+/// does not need to follow every field added to the workflow types. Queries
+/// run on the blocking thread pool so they never stall the async runtime.
+///
+/// Several games can share a database: every row is keyed by the id of the
+/// game it belongs to, and a store only sees the rows of its own game.
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+    game_id: String,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>, game_id: &str) -> AppResult<Self> {
+        let connection = Connection::open(path).map_err(sql_error)?;
+        Self::with_connection(connection, game_id)
+    }
+
+    pub fn open_in_memory(game_id: &str) -> AppResult<Self> {
+        let connection = Connection::open_in_memory().map_err(sql_error)?;
+        Self::with_connection(connection, game_id)
+    }
+
+    fn with_connection(connection: Connection, game_id: &str) -> AppResult<Self> {
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS workflow_definitions (
+                    game_id TEXT NOT NULL,
+                    id TEXT NOT NULL,
+                    body TEXT NOT NULL,
+                    PRIMARY KEY (game_id, id)
+                );
+                CREATE TABLE IF NOT EXISTS workflow_instances (
+                    game_id TEXT NOT NULL,
+                    instance_id TEXT NOT NULL,
+                    updated_at TEXT NOT NULL,
+                    body TEXT NOT NULL,
+                    PRIMARY KEY (game_id, instance_id)
+                );
+                CREATE TABLE IF NOT EXISTS user_preferences (
+                    game_id TEXT NOT NULL,
+                    user_id TEXT NOT NULL,
+                    workflow_id TEXT NOT NULL,
+                    body TEXT NOT NULL,
+                    PRIMARY KEY (game_id, user_id, workflow_id)
+                );
+                CREATE TABLE IF NOT EXISTS games (
+                    game_id TEXT PRIMARY KEY,
+                    body TEXT NOT NULL
+                );",
+            )
+            .map_err(sql_error)?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            game_id: game_id.to_string(),
+        })
+    }
+
+    async fn run<T, F>(&self, f: F) -> AppResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> AppResult<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            f(&connection.lock().expect("sqlite connection poisoned"))
+        })
+        .await
+        .map_err(|e| ServicesError::SQLError(e.to_string()))?
+    }
+}
+
+fn load_bodies<T: DeserializeOwned>(
+    connection: &Connection,
+    query: &str,
+    game_id: &str,
+) -> AppResult<Vec<T>> {
+    let mut statement = connection.prepare(query).map_err(sql_error)?;
+    let rows = statement
+        .query_map([game_id], |row| row.get::<_, String>(0))
+        .map_err(sql_error)?;
+
+    let mut values = Vec::new();
+    for body in rows {
+        let body = body.map_err(sql_error)?;
+        values.push(from_json(&body)?);
+    }
+    Ok(values)
+}
+
+fn sql_error(e: rusqlite::Error) -> ServicesError {
+    ServicesError::SQLError(e.to_string())
+}
+
+fn to_json<T: Serialize>(value: &T) -> AppResult<String> {
+    serde_json::to_string(value).map_err(|e| ServicesError::SQLError(e.to_string()))
+}
+
+fn from_json<T: DeserializeOwned>(body: &str) -> AppResult<T> {
+    serde_json::from_str(body).map_err(|e| ServicesError::SQLError(e.to_string()))
+}
+
+#[async_trait]
+impl WorkflowStore for SqliteStore {
+    async fn save_definition(&self, id: &str, definition: &WorkflowDefinition) -> AppResult<()> {
+        let game_id = self.game_id.clone();
+        let id = id.to_string();
+        let body = to_json(definition)?;
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO workflow_definitions (game_id, id, body) VALUES (?1, ?2, ?3)
+                     ON CONFLICT(game_id, id) DO UPDATE SET body = excluded.body",
+                    params![game_id, id, body],
+                )
+                .map_err(sql_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_definitions(&self) -> AppResult<HashMap<String, WorkflowDefinition>> {
+        let game_id = self.game_id.clone();
+        self.run(move |connection| {
+            let mut statement = connection
+                .prepare("SELECT id, body FROM workflow_definitions WHERE game_id = ?1")
+                .map_err(sql_error)?;
+            let rows = statement
+                .query_map([game_id], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })
+                .map_err(sql_error)?;
+
+            let mut definitions = HashMap::new();
+            for row in rows {
+                let (id, body) = row.map_err(sql_error)?;
+                definitions.insert(id, from_json(&body)?);
+            }
+            Ok(definitions)
+        })
+        .await
+    }
+
+    async fn save_instance(&self, state: &WorkflowState) -> AppResult<()> {
+        let game_id = self.game_id.clone();
+        let instance_id = state.instance_id.clone();
+        let updated_at = state.updated_at.to_rfc3339();
+        let body = to_json(state)?;
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO workflow_instances (game_id, instance_id, updated_at, body)
+                     VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT(game_id, instance_id) DO UPDATE SET
+                        updated_at = excluded.updated_at,
+                        body = excluded.body",
+                    params![game_id, instance_id, updated_at, body],
+                )
+                .map_err(sql_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_instances(&self) -> AppResult<HashMap<String, WorkflowState>> {
+        let game_id = self.game_id.clone();
+        let states: Vec<WorkflowState> = self
+            .run(move |connection| {
+                load_bodies(
+                    connection,
+                    "SELECT body FROM workflow_instances WHERE game_id = ?1 ORDER BY updated_at",
+                    &game_id,
+                )
+            })
+            .await?;
+        Ok(states
+            .into_iter()
+            .map(|state| (state.instance_id.clone(), state))
+            .collect())
+    }
+
+    async fn save_preferences(&self, preferences: &UserWorkflowPreferences) -> AppResult<()> {
+        let game_id = self.game_id.clone();
+        let user_id = preferences.user_id.clone();
+        let workflow_id = preferences.workflow_id.clone();
+        let body = to_json(preferences)?;
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO user_preferences (game_id, user_id, workflow_id, body)
+                     VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT(game_id, user_id, workflow_id) DO UPDATE SET
+                        body = excluded.body",
+                    params![game_id, user_id, workflow_id, body],
+                )
+                .map_err(sql_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_preferences(
+        &self,
+    ) -> AppResult<HashMap<(String, String), UserWorkflowPreferences>> {
+        let game_id = self.game_id.clone();
+        let preferences: Vec<UserWorkflowPreferences> = self
+            .run(move |connection| {
+                load_bodies(
+                    connection,
+                    "SELECT body FROM user_preferences WHERE game_id = ?1",
+                    &game_id,
+                )
+            })
+            .await?;
+        Ok(preferences
+            .into_iter()
+            .map(|pref| ((pref.user_id.clone(), pref.workflow_id.clone()), pref))
+            .collect())
+    }
+
+    async fn save_game(&self, game: &GameSnapshot) -> AppResult<()> {
+        let game_id = self.game_id.clone();
+        let body = to_json(game)?;
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "INSERT INTO games (game_id, body) VALUES (?1, ?2)
+                     ON CONFLICT(game_id) DO UPDATE SET body = excluded.body",
+                    params![game_id, body],
+                )
+                .map_err(sql_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_game(&self) -> AppResult<Option<GameSnapshot>> {
+        let game_id = self.game_id.clone();
+        let games: Vec<GameSnapshot> = self
+            .run(move |connection| {
+                load_bodies(
+                    connection,
+                    "SELECT body FROM games WHERE game_id = ?1",
+                    &game_id,
+                )
+            })
+            .await?;
+        Ok(games.into_iter().next())
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use super::DisplayType;
+
+pub fn is_template(text: &str) -> bool {
+    text.contains("{{")
+}
+
+/// Fill in every `{{path}}` in `template` from `values`. Paths are dotted and
+/// index into lists by position, as in `reveal_player.0.name`. Missing
+/// values, `null`, lists and objects render as nothing, and an unclosed `{{`
+/// is kept as written.
+pub fn render(template: &str, values: &HashMap<String, Value>) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+
+        let path = rest[start + 2..start + 2 + length].trim();
+        match lookup(values, path) {
+            Some(Value::String(text)) => rendered.push_str(text),
+            Some(value @ (Value::Number(_) | Value::Bool(_))) => {
+                rendered.push_str(&value.to_string())
+            }
+            _ => {}
+        }
+
+        rest = &rest[start + 2 + length + 2..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+pub fn lookup<'a>(values: &'a HashMap<String, Value>, path: &str) -> Option<&'a Value> {
+    let mut segments = path.split('.');
+    let mut current = values.get(segments.next()?)?;
+
+    for segment in segments {
+        current = match current {
+            Value::Object(object) => object.get(segment)?,
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    Some(current)
+}
+
+/// Render the templated `Text` displays in `display`, nested ones included.
+/// Displays repeated per list item are left alone, as their values only exist
+/// on the client.
+pub fn render_display(display: &mut DisplayType, values: &HashMap<String, Value>) {
+    match display {
+        DisplayType::Text { text_key, text } if is_template(text_key) => {
+            *text = Some(render(text_key, values));
+        }
+        DisplayType::Page { content, .. }
+        | DisplayType::Card { content, .. }
+        | DisplayType::Flex { content, .. } => {
+            for display in content {
+                render_display(display, values);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::{Value, json};
+
+    use super::{is_template, render, render_display};
+    use crate::workflow::DisplayType;
+
+    fn values() -> HashMap<String, Value> {
+        serde_json::from_value(json!({
+            "name": "Sam",
+            "count": 3,
+            "flag": true,
+            "nothing": null,
+            "cards": ["Seer", "Werewolf"],
+            "reveal": [{ "name": "Vince", "role": { "name": "Werewolf" } }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn renders_paths() {
+        let cases = [
+            ("Hello {{name}}", "Hello Sam"),
+            ("{{ name }}", "Sam"),
+            ("{{count}} cards", "3 cards"),
+            ("{{flag}}", "true"),
+            (
+                "{{reveal.0.name}} is a {{reveal.0.role.name}}",
+                "Vince is a Werewolf",
+            ),
+            ("{{cards.1}}", "Werewolf"),
+            ("[{{missing}}]", "[]"),
+            ("[{{nothing}}]", "[]"),
+            ("[{{cards}}]", "[]"),
+            ("[{{reveal.0}}]", "[]"),
+            ("[{{cards.9}}]", "[]"),
+            ("[{{name.first}}]", "[]"),
+            ("open {{name", "open {{name"),
+            ("{{name}} and {{name", "Sam and {{name"),
+            ("no template", "no template"),
+        ];
+
+        let values = values();
+        for (template, expected) in cases {
+            assert_eq!(render(template, &values), expected, "{template}");
+        }
+    }
+
+    #[test]
+    fn recognises_templates() {
+        let cases = [
+            ("You saw {{card}}", true),
+            ("{{", true),
+            ("selected_card", false),
+            ("{ single }", false),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(is_template(text), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn renders_nested_text_displays() {
+        let text = |text_key: &str| DisplayType::Text {
+            text_key: text_key.to_string(),
+            text: None,
+        };
+        let mut display = DisplayType::Page {
+            title_key: "title".to_string(),
+            content: vec![text("Hi {{name}}"), text("name")],
+        };
+
+        render_display(&mut display, &values());
+
+        let DisplayType::Page { content, .. } = display else {
+            unreachable!();
+        };
+        let rendered: Vec<Option<String>> = content
+            .into_iter()
+            .map(|display| match display {
+                DisplayType::Text { text, .. } => text,
+                _ => unreachable!(),
+            })
+            .collect();
+        // Plain response keys are looked up by the client.
+        assert_eq!(rendered, vec![Some("Hi Sam".to_string()), None]);
+    }
+}
@@ -0,0 +1,594 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::{
+    ActionType, CreateWorkflowDefinition, InputType, NodeCondition, TimeoutAction, WorkflowNode,
+};
+
+#[derive(Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    /// The definition is rejected.
+    Error,
+    /// The definition is accepted but probably does not do what was meant.
+    Warning,
+}
+
+/// A problem found in a workflow definition, located by its JSON path.
+#[derive(Type, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub path: String,
+    pub message: String,
+    pub severity: Severity,
+}
+
+impl Diagnostic {
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "{}: {}", self.path, self.message),
+            Severity::Warning => write!(f, "{}: warning: {}", self.path, self.message),
+        }
+    }
+}
+
+pub fn format_diagnostics(diagnostics: &[Diagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Check a definition for references that would otherwise only fail while a
+/// player is in the middle of it. Every problem is reported, not just the
+/// first one.
+pub fn validate_definition(definition: &CreateWorkflowDefinition) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut report = |severity: Severity, path: String, message: String| {
+        diagnostics.push(Diagnostic {
+            path,
+            message,
+            severity,
+        });
+    };
+
+    if !definition.nodes.contains_key(&definition.initial_node_id) {
+        report(
+            Severity::Error,
+            "$.initial_node_id".to_string(),
+            format!("node '{}' does not exist", definition.initial_node_id),
+        );
+    }
+
+    let mut node_ids: Vec<&String> = definition.nodes.keys().collect();
+    node_ids.sort();
+
+    let computed: HashSet<&str> = definition
+        .nodes
+        .values()
+        .flat_map(|node| node.compute.keys().map(String::as_str))
+        .collect();
+
+    for key in &node_ids {
+        let node = &definition.nodes[*key];
+        let path = format!("$.nodes.{key}");
+
+        if node.id != **key {
+            report(
+                Severity::Error,
+                format!("{path}.id"),
+                format!("node id '{}' does not match its key", node.id),
+            );
+        }
+
+        if let Some(parent_id) = &node.parent_id
+            && !definition.nodes.contains_key(parent_id)
+        {
+            report(
+                Severity::Error,
+                format!("{path}.parent_id"),
+                format!("parent node '{parent_id}' does not exist"),
+            );
+        }
+
+        for (index, action) in node.actions.iter().enumerate() {
+            if let (ActionType::NextNode, Some(target)) = (&action.action_type, &action.target)
+                && !definition.nodes.contains_key(target)
+            {
+                report(
+                    Severity::Error,
+                    format!("{path}.actions[{index}].target"),
+                    format!("target node '{target}' does not exist"),
+                );
+            }
+        }
+
+        for (index, input) in node.inputs.iter().enumerate() {
+            if let InputType::SelectFromList { items_key, .. } = &input.input_type {
+                let root = items_key.split('.').next().unwrap_or_default();
+                if !definition.responses.contains_key(root) && !computed.contains(root) {
+                    report(
+                        Severity::Error,
+                        format!("{path}.inputs[{index}].input_type.SelectFromList.items_key"),
+                        format!("'{items_key}' is neither declared in responses nor computed"),
+                    );
+                }
+            }
+        }
+
+        for (index, transition) in node.transitions.iter().enumerate() {
+            if !definition.nodes.contains_key(&transition.target) {
+                report(
+                    Severity::Error,
+                    format!("{path}.transitions[{index}].target"),
+                    format!("target node '{}' does not exist", transition.target),
+                );
+            }
+        }
+
+        let mut computed_keys: Vec<&String> = node.compute.keys().collect();
+        computed_keys.sort();
+        for key in computed_keys {
+            if node.compute[key].has_stray_item() {
+                report(
+                    Severity::Error,
+                    format!("{path}.compute.{key}"),
+                    "Item is only available inside Filter or Map".to_string(),
+                );
+            }
+        }
+
+        if let Some(timeout) = &node.timeout {
+            if timeout.after_seconds == 0 {
+                report(
+                    Severity::Error,
+                    format!("{path}.timeout.after_seconds"),
+                    "a timeout must be at least one second".to_string(),
+                );
+            }
+            if timeout.remind_every_seconds == Some(0) {
+                report(
+                    Severity::Error,
+                    format!("{path}.timeout.remind_every_seconds"),
+                    "reminders must be at least one second apart".to_string(),
+                );
+            }
+            // Expiring submits the defaults, so required inputs need one unless
+            // the action may leave the node half filled in.
+            let needs_defaults = match &timeout.on_expiry {
+                TimeoutAction::TakeAction(action_id) => {
+                    match node.actions.iter().find(|action| &action.id == action_id) {
+                        Some(action) => !matches!(
+                            action.action_type,
+                            ActionType::PreviousNode | ActionType::Cancel
+                        ),
+                        None => {
+                            report(
+                                Severity::Error,
+                                format!("{path}.timeout.on_expiry.TakeAction"),
+                                format!("action '{action_id}' does not exist on this node"),
+                            );
+                            false
+                        }
+                    }
+                }
+                TimeoutAction::UseDefaults => {
+                    if node.actions.is_empty() {
+                        report(
+                            Severity::Error,
+                            format!("{path}.timeout.on_expiry"),
+                            "UseDefaults needs an action to submit with".to_string(),
+                        );
+                    }
+                    true
+                }
+                TimeoutAction::Cancel => false,
+            };
+            if needs_defaults {
+                for (index, input) in node.inputs.iter().enumerate() {
+                    if input.required && input.default_value.is_none() {
+                        report(
+                            Severity::Error,
+                            format!("{path}.inputs[{index}].default_value"),
+                            "required input has no default to use on timeout".to_string(),
+                        );
+                    }
+                }
+            }
+        }
+
+        if !node.transitions.is_empty() {
+            let last = node.transitions.len() - 1;
+            if !node.transitions[last].is_fallback() {
+                report(
+                    Severity::Error,
+                    format!("{path}.transitions[{last}]"),
+                    "the last transition must be an unconditional fallback".to_string(),
+                );
+            }
+        } else {
+            // Server actions may still move on to a child, so this is only a
+            // warning where a missing fallback transition is an error.
+            let children = children_of(definition, &node.id);
+            if !children.is_empty() && !children.iter().any(|child| is_unconditional(child)) {
+                report(
+                    Severity::Warning,
+                    path.clone(),
+                    "no child is unconditional, so moving on may find nowhere to go".to_string(),
+                );
+            }
+        }
+
+        // Only the intended fallback may match together with another branch.
+        let branches: Vec<(String, Option<&NodeCondition>)> = if node.transitions.is_empty() {
+            let mut children = children_of(definition, &node.id);
+            children.sort_by(|a, b| a.id.cmp(&b.id));
+            // Children are checked in id order, so an unconditional last child
+            // is the fallback.
+            if children.last().is_some_and(|child| is_unconditional(child)) {
+                children.pop();
+            }
+            children
+                .into_iter()
+                .map(|child| {
+                    (
+                        format!("$.nodes.{}.condition", child.id),
+                        child.condition.as_ref(),
+                    )
+                })
+                .collect()
+        } else {
+            let last = node.transitions.len() - 1;
+            node.transitions[..last]
+                .iter()
+                .enumerate()
+                .map(|(index, transition)| {
+                    (
+                        format!("{path}.transitions[{index}].condition"),
+                        transition.condition.as_ref(),
+                    )
+                })
+                .collect()
+        };
+        for (index, (first_path, first)) in branches.iter().enumerate() {
+            for (second_path, second) in &branches[index + 1..] {
+                if conditions_overlap(*first, *second) {
+                    report(
+                        Severity::Warning,
+                        second_path.clone(),
+                        format!("overlaps {first_path}, which is checked first"),
+                    );
+                }
+            }
+        }
+    }
+
+    // Without a valid start every node would be reported as unreachable.
+    if definition.nodes.contains_key(&definition.initial_node_id) {
+        let reachable = reachable_nodes(definition);
+        for key in node_ids {
+            if !reachable.contains(key.as_str()) {
+                report(
+                    Severity::Error,
+                    format!("$.nodes.{key}"),
+                    format!(
+                        "node is not reachable from '{}'",
+                        definition.initial_node_id
+                    ),
+                );
+            }
+        }
+    }
+
+    diagnostics
+}
+
+fn children_of<'a>(
+    definition: &'a CreateWorkflowDefinition,
+    node_id: &str,
+) -> Vec<&'a WorkflowNode> {
+    definition
+        .nodes
+        .values()
+        .filter(|child| child.parent_id.as_deref() == Some(node_id))
+        .collect()
+}
+
+fn is_unconditional(node: &WorkflowNode) -> bool {
+    matches!(node.condition, None | Some(NodeCondition::Always))
+}
+
+/// Whether both conditions are certainly true for some responses. Conditions
+/// on different fields are assumed to be independent.
+fn conditions_overlap(first: Option<&NodeCondition>, second: Option<&NodeCondition>) -> bool {
+    use NodeCondition::*;
+
+    match (first, second) {
+        (None | Some(Always), _) | (_, None | Some(Always)) => true,
+        (Some(first), Some(second)) => match (first, second) {
+            (ResponseEquals { field: a, value: x }, ResponseEquals { field: b, value: y }) => {
+                a == b && x == y
+            }
+            (ResponseExists(a), ResponseExists(b))
+            | (ResponseExists(a), ResponseEquals { field: b, .. })
+            | (ResponseEquals { field: a, .. }, ResponseExists(b))
+            | (ResponseExists(a), ResponseListNotEmpty(b))
+            | (ResponseListNotEmpty(a), ResponseExists(b))
+            | (ResponseListNotEmpty(a), ResponseListNotEmpty(b)) => a == b,
+            _ => false,
+        },
+    }
+}
+
+/// Nodes reachable from the initial node through transitions, explicit
+/// `NextNode` targets or parent/child links, which server actions may follow
+/// as well.
+fn reachable_nodes(definition: &CreateWorkflowDefinition) -> HashSet<&str> {
+    let mut reachable = HashSet::new();
+    let mut queue = VecDeque::from([definition.initial_node_id.as_str()]);
+
+    while let Some(node_id) = queue.pop_front() {
+        if !reachable.insert(node_id) {
+            continue;
+        }
+
+        let node = &definition.nodes[node_id];
+        let targets = node
+            .actions
+            .iter()
+            .filter_map(|action| match action.action_type {
+                ActionType::NextNode => action.target.as_deref(),
+                _ => None,
+            });
+        let transitions = node
+            .transitions
+            .iter()
+            .map(|transition| transition.target.as_str());
+        let children = children_of(definition, node_id)
+            .into_iter()
+            .map(|child| child.id.as_str());
+
+        for next in targets.chain(transitions).chain(children) {
+            if definition.nodes.contains_key(next) && !reachable.contains(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{Severity, validate_definition};
+    use crate::workflow::builder::NodeId;
+    use crate::workflow::expression::Expression;
+    use crate::workflow::{
+        CreateWorkflowDefinition, NodeCondition, NodeTimeout, TimeoutAction, WorkflowAction,
+        WorkflowInput, WorkflowNode,
+    };
+
+    fn start() -> WorkflowNode {
+        WorkflowNode::new(NodeId("start"), "Start")
+            .action(WorkflowAction::next_node("next", "Next"))
+    }
+
+    fn end() -> WorkflowNode {
+        WorkflowNode::new(NodeId("end"), "End").action(WorkflowAction::submit("submit", "Submit"))
+    }
+
+    fn definition() -> CreateWorkflowDefinition {
+        CreateWorkflowDefinition::new("test", "Test")
+            .response("options", json!([]))
+            .node(start().transition(NodeId("end")))
+            .node(end())
+    }
+
+    fn timeout(after_seconds: u64, on_expiry: TimeoutAction) -> NodeTimeout {
+        NodeTimeout {
+            after_seconds,
+            on_expiry,
+            remind_every_seconds: None,
+        }
+    }
+
+    #[test]
+    fn reports_problems_by_path() {
+        use Severity::*;
+
+        let exists = |field: &str| NodeCondition::ResponseExists(field.to_string());
+        let equals = |field: &str, value| NodeCondition::ResponseEquals {
+            field: field.to_string(),
+            value,
+        };
+
+        let cases = vec![
+            ("valid", definition(), vec![]),
+            (
+                "missing initial node",
+                definition().with_initial_node(NodeId("missing")),
+                vec![(Error, "$.initial_node_id")],
+            ),
+            (
+                "missing parent",
+                definition().node(
+                    WorkflowNode::new(NodeId("orphan"), "Orphan").with_parent(NodeId("ghost")),
+                ),
+                vec![
+                    (Error, "$.nodes.orphan.parent_id"),
+                    (Error, "$.nodes.orphan"),
+                ],
+            ),
+            (
+                "missing action target",
+                definition().node(
+                    start()
+                        .action(WorkflowAction::go_to("skip", "Skip", NodeId("nowhere")))
+                        .transition(NodeId("end")),
+                ),
+                vec![(Error, "$.nodes.start.actions[1].target")],
+            ),
+            (
+                "missing transition target",
+                definition().node(start().transition(NodeId("nowhere"))),
+                vec![
+                    (Error, "$.nodes.start.transitions[0].target"),
+                    (Error, "$.nodes.end"),
+                ],
+            ),
+            (
+                "list items from responses and computed values",
+                definition().node(
+                    end()
+                        .input(WorkflowInput::select_from_list("a", "A", "options"))
+                        .input(WorkflowInput::select_from_list("b", "B", "options.0.more"))
+                        .input(WorkflowInput::select_from_list("c", "C", "computed"))
+                        .input(WorkflowInput::select_from_list("d", "D", "undeclared"))
+                        .compute("computed", Expression::Literal(json!([]))),
+                ),
+                vec![(
+                    Error,
+                    "$.nodes.end.inputs[3].input_type.SelectFromList.items_key",
+                )],
+            ),
+            (
+                "stray item",
+                definition().node(end().compute("x", Expression::Item(String::new()))),
+                vec![(Error, "$.nodes.end.compute.x")],
+            ),
+            (
+                "bad timeout",
+                definition().node(end().with_timeout(NodeTimeout {
+                    remind_every_seconds: Some(0),
+                    ..timeout(0, TimeoutAction::TakeAction("missing".to_string()))
+                })),
+                vec![
+                    (Error, "$.nodes.end.timeout.after_seconds"),
+                    (Error, "$.nodes.end.timeout.remind_every_seconds"),
+                    (Error, "$.nodes.end.timeout.on_expiry.TakeAction"),
+                ],
+            ),
+            (
+                "defaults without an action",
+                definition().node(
+                    WorkflowNode::new(NodeId("end"), "End")
+                        .with_timeout(timeout(30, TimeoutAction::UseDefaults)),
+                ),
+                vec![(Error, "$.nodes.end.timeout.on_expiry")],
+            ),
+            (
+                "required inputs without defaults",
+                definition().node(
+                    end()
+                        .input(WorkflowInput::select_from_list("a", "A", "options"))
+                        .input(
+                            WorkflowInput::select_from_list("b", "B", "options")
+                                .with_default(json!("x")),
+                        )
+                        .input(WorkflowInput::select_from_list("c", "C", "options").optional())
+                        .with_timeout(timeout(30, TimeoutAction::UseDefaults)),
+                ),
+                vec![(Error, "$.nodes.end.inputs[0].default_value")],
+            ),
+            (
+                "taking an action on required inputs without defaults",
+                definition().node(
+                    end()
+                        .input(WorkflowInput::select_from_list("a", "A", "options"))
+                        .with_timeout(timeout(30, TimeoutAction::TakeAction("submit".into()))),
+                ),
+                vec![(Error, "$.nodes.end.inputs[0].default_value")],
+            ),
+            (
+                "going back on timeout",
+                definition().node(
+                    end()
+                        .input(WorkflowInput::select_from_list("a", "A", "options"))
+                        .action(WorkflowAction::previous_node("back", "Back"))
+                        .with_timeout(timeout(30, TimeoutAction::TakeAction("back".into()))),
+                ),
+                vec![],
+            ),
+            (
+                "conditional last transition",
+                definition().node(start().transition_if(NodeId("end"), exists("a"))),
+                vec![(Error, "$.nodes.start.transitions[0]")],
+            ),
+            (
+                "overlapping transitions",
+                definition().node(
+                    start()
+                        .transition_if(NodeId("end"), exists("a"))
+                        .transition_if(NodeId("end"), equals("a", json!(1)))
+                        .transition(NodeId("end")),
+                ),
+                vec![(Warning, "$.nodes.start.transitions[1].condition")],
+            ),
+            (
+                "disjoint transitions",
+                definition().node(
+                    start()
+                        .transition_if(NodeId("end"), equals("a", json!(1)))
+                        .transition_if(NodeId("end"), equals("a", json!(2)))
+                        .transition(NodeId("end")),
+                ),
+                vec![],
+            ),
+            (
+                "children with a fallback",
+                definition()
+                    .node(start())
+                    .node(
+                        end()
+                            .with_parent(NodeId("start"))
+                            .with_condition(exists("a")),
+                    )
+                    .node(WorkflowNode::new(NodeId("other"), "Other").with_parent(NodeId("start"))),
+                vec![],
+            ),
+            (
+                "children without a fallback",
+                definition()
+                    .node(start())
+                    .node(
+                        end()
+                            .with_parent(NodeId("start"))
+                            .with_condition(exists("a")),
+                    )
+                    .node(
+                        WorkflowNode::new(NodeId("other"), "Other")
+                            .with_parent(NodeId("start"))
+                            .with_condition(exists("b")),
+                    ),
+                vec![(Warning, "$.nodes.start")],
+            ),
+            (
+                "unreachable node",
+                definition().node(WorkflowNode::new(NodeId("island"), "Island")),
+                vec![(Error, "$.nodes.island")],
+            ),
+        ];
+
+        for (name, definition, expected) in cases {
+            let found: Vec<(Severity, String)> = validate_definition(&definition)
+                .into_iter()
+                .map(|diagnostic| (diagnostic.severity, diagnostic.path))
+                .collect();
+            let expected: Vec<(Severity, String)> = expected
+                .into_iter()
+                .map(|(severity, path)| (severity, path.to_string()))
+                .collect();
+            assert_eq!(found, expected, "{name}");
+        }
+    }
+}